@@ -1,16 +1,22 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
 use avl_trees::tree::Tree;
 
 fn criterion_benchmark(c: &mut Criterion) {
     for &size in &[10000, 40000, 70000, 100000, 130000] {
         let mut tree: Tree<i64> = Tree::new();
         let values: Vec<i64> = (0..size).collect();
+        // A fresh tree per batch, so every insert is of a new key
         c.bench_function(&format!("insert_{}", size), |b| {
-            b.iter(|| {
-                for &value in &values {
-                    tree.insert(black_box(value));
-                }
-            });
+            b.iter_batched(
+                Tree::new,
+                |mut tree| {
+                    for &value in &values {
+                        tree.insert(black_box(value));
+                    }
+                    tree
+                },
+                BatchSize::LargeInput,
+            );
         });
 
         for &value in &values {
@@ -20,7 +26,7 @@ fn criterion_benchmark(c: &mut Criterion) {
         c.bench_function(&format!("search_{}", size), |b| {
             b.iter(|| {
                 for &value in &values[..(size/10).try_into().unwrap()] {
-                    tree.search(black_box(value));
+                    tree.search(black_box(&value));
                 }
             });
         });
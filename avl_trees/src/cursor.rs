@@ -0,0 +1,260 @@
+//! Cursors that walk an AVL tree in key order, and that can insert and remove
+//! keys where they stand without searching again.
+//!
+//! AVL nodes have no parent links, so a cursor holds the path from the root
+//! down to its node. An empty path is the "ghost" position, which sits past
+//! the largest key and before the smallest: moving forward from it reaches
+//! the first key and moving back reaches the last.
+
+use std::fmt::Debug;
+use std::rc::Rc;
+
+use crate::augment::Monoid;
+use crate::node::NodePtr;
+use crate::tree::Tree;
+
+type Path<T, A> = Vec<NodePtr<T, A>>;
+
+pub struct Cursor<'a, T: Clone + Ord + Debug, A: Monoid<T> = ()> {
+    tree: &'a Tree<T, A>,
+    path: Path<T, A>,
+}
+
+impl<'a, T: Clone + Ord + Debug, A: Monoid<T>> Cursor<'a, T, A> {
+    pub(crate) fn new(tree: &'a Tree<T, A>) -> Self {
+        let mut path = Vec::new();
+        push_spine(&mut path, tree.root.clone(), true);
+        Cursor { tree, path }
+    }
+
+    /// The key under the cursor, or `None` at the ghost position
+    pub fn key(&self) -> Option<T> {
+        key_of(&self.path)
+    }
+
+    /// Move to `key` if present, otherwise to the smallest key above it, or
+    /// to the ghost position if there is none. Returns whether `key` was found.
+    pub fn seek(&mut self, key: &T) -> bool {
+        seek(&self.tree.root, key, &mut self.path)
+    }
+
+    pub fn move_next(&mut self) {
+        step(&self.tree.root, &mut self.path, true);
+    }
+
+    pub fn move_prev(&mut self) {
+        step(&self.tree.root, &mut self.path, false);
+    }
+
+    pub fn peek_next(&self) -> Option<T> {
+        peek(&self.tree.root, &self.path, true)
+    }
+
+    pub fn peek_prev(&self) -> Option<T> {
+        peek(&self.tree.root, &self.path, false)
+    }
+}
+
+pub struct CursorMut<'a, T: Clone + Ord + Debug, A: Monoid<T> = ()> {
+    tree: &'a mut Tree<T, A>,
+    path: Path<T, A>,
+}
+
+impl<'a, T: Clone + Ord + Debug, A: Monoid<T>> CursorMut<'a, T, A> {
+    pub(crate) fn new(tree: &'a mut Tree<T, A>) -> Self {
+        let mut path = Vec::new();
+        push_spine(&mut path, tree.root.clone(), true);
+        CursorMut { tree, path }
+    }
+
+    /// The key under the cursor, or `None` at the ghost position
+    pub fn key(&self) -> Option<T> {
+        key_of(&self.path)
+    }
+
+    /// Move to `key` if present, otherwise to the smallest key above it, or
+    /// to the ghost position if there is none. Returns whether `key` was found.
+    pub fn seek(&mut self, key: &T) -> bool {
+        seek(&self.tree.root, key, &mut self.path)
+    }
+
+    pub fn move_next(&mut self) {
+        step(&self.tree.root, &mut self.path, true);
+    }
+
+    pub fn move_prev(&mut self) {
+        step(&self.tree.root, &mut self.path, false);
+    }
+
+    pub fn peek_next(&self) -> Option<T> {
+        peek(&self.tree.root, &self.path, true)
+    }
+
+    pub fn peek_prev(&self) -> Option<T> {
+        peek(&self.tree.root, &self.path, false)
+    }
+
+    /// Remove the key under the cursor and move to the next one, rebalancing
+    /// along the path the cursor already holds. Returns `None`, changing
+    /// nothing, at the ghost position.
+    pub fn remove_current(&mut self) -> Option<T> {
+        self.path.last()?;
+        let removed;
+        (removed, self.path) = self.tree.unlink_last(std::mem::take(&mut self.path));
+        Some(removed)
+    }
+
+    /// Insert `key` just before the cursor, which stays where it is. Returns
+    /// false, changing nothing, unless `key` falls strictly between the
+    /// previous key and the current one.
+    pub fn insert_before(&mut self, key: T) -> bool {
+        let fits = self.peek_prev().is_none_or(|prev| prev < key) && self.key().is_none_or(|current| key < current);
+        if !fits {
+            return false;
+        }
+        self.insert_beside(key, false);
+        true
+    }
+
+    /// Insert `key` just after the cursor, which stays where it is. Returns
+    /// false, changing nothing, unless `key` falls strictly between the
+    /// current key and the next one.
+    pub fn insert_after(&mut self, key: T) -> bool {
+        let fits = self.key().is_none_or(|current| current < key) && self.peek_next().is_none_or(|next| key < next);
+        if !fits {
+            return false;
+        }
+        self.insert_beside(key, true);
+        true
+    }
+
+    /// Link `key` in as a leaf next to the cursor on the side `after` picks:
+    /// as the current node's child on that side if it is free, otherwise at
+    /// the near end of the subtree there. From the ghost position, "after"
+    /// is before the smallest key and "before" is after the largest.
+    fn insert_beside(&mut self, key: T, after: bool) {
+        let mut path = self.path.clone();
+        let as_left = match self.path.last() {
+            None => {
+                push_spine(&mut path, self.tree.root.clone(), after);
+                after
+            }
+            Some(node) => {
+                let subtree = if after { node.borrow().right.clone() } else { node.borrow().left.clone() };
+                let free = subtree.is_none();
+                push_spine(&mut path, subtree, after);
+                if free { !after } else { after }
+            }
+        };
+        self.tree.link_leaf(&path, key, as_left, &mut self.path);
+    }
+}
+
+/// The keys in order, cloned out of the tree
+pub struct Iter<'a, T: Clone + Ord + Debug, A: Monoid<T> = ()> {
+    cursor: Cursor<'a, T, A>,
+}
+
+impl<'a, T: Clone + Ord + Debug, A: Monoid<T>> Iter<'a, T, A> {
+    pub(crate) fn new(tree: &'a Tree<T, A>) -> Self {
+        Iter { cursor: Cursor::new(tree) }
+    }
+
+    /// The keys from `key`, or the smallest one above it, onwards
+    pub(crate) fn starting_at(tree: &'a Tree<T, A>, key: &T) -> Self {
+        let mut cursor = Cursor::new(tree);
+        cursor.seek(key);
+        Iter { cursor }
+    }
+}
+
+impl<T: Clone + Ord + Debug, A: Monoid<T>> Iterator for Iter<'_, T, A> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let key = self.cursor.key()?;
+        self.cursor.move_next();
+        Some(key)
+    }
+}
+
+fn key_of<T: Clone + Debug, A: Monoid<T>>(path: &Path<T, A>) -> Option<T> {
+    path.last().map(|node| node.borrow().data.clone())
+}
+
+/// Push `node` and then its children on the near side, down to the smallest
+/// key below it when `forward` or the largest otherwise
+fn push_spine<T: Clone + Debug, A: Monoid<T>>(path: &mut Path<T, A>, mut node: Option<NodePtr<T, A>>, forward: bool) {
+    while let Some(current) = node {
+        node = if forward { current.borrow().left.clone() } else { current.borrow().right.clone() };
+        path.push(current);
+    }
+}
+
+/// Move to the next key when `forward`, else the previous one, wrapping
+/// through the ghost position
+fn step<T: Clone + Debug, A: Monoid<T>>(root: &Option<NodePtr<T, A>>, path: &mut Path<T, A>, forward: bool) {
+    let node = match path.last() {
+        None => {
+            push_spine(path, root.clone(), forward);
+            return;
+        }
+        Some(node) => node.clone(),
+    };
+    let far = if forward { node.borrow().right.clone() } else { node.borrow().left.clone() };
+    if far.is_some() {
+        push_spine(path, far, forward);
+        return;
+    }
+    // Climb until arriving from the near side, which makes the parent next;
+    // climbing off the root leaves the path empty
+    while let Some(child) = path.pop() {
+        let arrived_from_near = match path.last() {
+            None => return,
+            Some(parent) => {
+                let parent_borrow = parent.borrow();
+                let near = if forward { &parent_borrow.left } else { &parent_borrow.right };
+                near.as_ref().is_some_and(|near| Rc::ptr_eq(near, &child))
+            }
+        };
+        if arrived_from_near {
+            return;
+        }
+    }
+}
+
+fn peek<T: Clone + Debug, A: Monoid<T>>(root: &Option<NodePtr<T, A>>, path: &Path<T, A>, forward: bool) -> Option<T> {
+    let mut path = path.clone();
+    step(root, &mut path, forward);
+    key_of(&path)
+}
+
+/// Point `path` at `key`, or at the smallest key above it, or at the ghost
+/// position. Returns whether `key` was found.
+fn seek<T: Clone + Ord + Debug, A: Monoid<T>>(root: &Option<NodePtr<T, A>>, key: &T, path: &mut Path<T, A>) -> bool {
+    path.clear();
+    // Length of the path to the smallest key seen so far that is above `key`
+    let mut above = 0;
+    let mut current = root.clone();
+    while let Some(node) = current {
+        path.push(node.clone());
+        let node_borrow = node.borrow();
+        current = match key.cmp(&node_borrow.data) {
+            std::cmp::Ordering::Equal => return true,
+            std::cmp::Ordering::Less => {
+                above = path.len();
+                node_borrow.left.clone()
+            }
+            std::cmp::Ordering::Greater => node_borrow.right.clone(),
+        };
+    }
+    path.truncate(above);
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tree::Tree;
+
+    tree_core::cursor_tests!(Tree);
+}
@@ -1,2 +1,11 @@
-pub mod node;
-pub mod tree;
\ No newline at end of file
+pub use tree_core::augment;
+pub mod cursor;
+pub use tree_core::diagram;
+pub mod map;
+mod node;
+pub mod rope;
+pub mod seq;
+pub use tree_core::stats;
+pub mod trace;
+pub mod tree;
+pub mod view;
\ No newline at end of file
@@ -0,0 +1,79 @@
+//! A sorted map kept in an AVL tree.
+//!
+//! The map itself is `tree_core::map::Map`; this module lets it drive the
+//! tree. `entry` walks down once and remembers the path, so a vacant entry
+//! links its new leaf at the end of that path and rebalances back up it, and
+//! an occupied one unlinks its node the same way.
+
+use std::cmp::Ordering;
+use std::fmt::Debug;
+
+use crate::cursor::Iter;
+use crate::node::NodePtr;
+use crate::stats::TreeStats;
+use crate::tree::Tree;
+use tree_core::map::{Map, MapTree, Slot};
+
+pub use tree_core::map::{Entry, OccupiedEntry, VacantEntry};
+
+pub type AvlMap<K, V> = Map<K, V, Tree<Slot<K>>>;
+
+impl<K: Clone + Ord + Debug> MapTree<K> for Tree<Slot<K>> {
+    /// From the root down to the node the descent stopped at
+    type Place = Vec<NodePtr<Slot<K>>>;
+    type Slots<'a>
+        = Iter<'a, Slot<K>>
+    where
+        Self: 'a;
+
+    fn is_empty(&self) -> bool {
+        Tree::is_empty(self)
+    }
+
+    fn stats(&self) -> TreeStats {
+        Tree::stats(self)
+    }
+
+    fn reset_stats(&mut self) {
+        Tree::reset_stats(self);
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        Tree::validate(self)
+    }
+
+    fn slots(&self) -> Iter<'_, Slot<K>> {
+        self.iter()
+    }
+
+    fn slots_from(&self, start: &Slot<K>) -> Iter<'_, Slot<K>> {
+        Iter::starting_at(self, start)
+    }
+
+    fn lookup(&self, probe: impl Fn(&Slot<K>) -> Ordering) -> Option<usize> {
+        Tree::lookup(self, probe).map(|node| node.borrow().data.index())
+    }
+
+    fn descend(&mut self, probe: impl Fn(&Slot<K>) -> Ordering) -> (Self::Place, Option<Ordering>) {
+        Tree::descend(self, probe)
+    }
+
+    fn index_at(path: &Self::Place) -> usize {
+        path.last().unwrap().borrow().data.index()
+    }
+
+    fn link(&mut self, path: Self::Place, slot: Slot<K>, as_left: bool) {
+        self.link_leaf(&path, slot, as_left, &mut Vec::new());
+    }
+
+    fn unlink(&mut self, path: Self::Place) -> Slot<K> {
+        self.unlink_last(path).0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AvlMap;
+
+    tree_core::map_tests!(AvlMap);
+}
@@ -2,19 +2,63 @@ use std::cell::RefCell;
 use std::rc::Rc;
 use std::fmt::Debug;
 
-pub type NodePtr<T: Clone> = Rc<RefCell<Node<T>>>;
+use crate::augment::{Monoid, Summarized};
+use crate::trace::{Direction, RebalanceCase};
+
+pub type NodePtr<T, A = ()> = Rc<RefCell<Node<T, A>>>;
+
+/// What `Node::rebalance_with` is doing, reported as it happens
+pub enum Rebalancing<'a, T: Clone + Debug, A: Monoid<T>> {
+    /// The node being rebalanced has the shape `case`
+    Case(RebalanceCase),
+    /// `pivot` was rotated in `direction`, lifting `top` into its place. The
+    /// first rotation of a double case is already linked under the node
+    /// being rebalanced; the last rotation's `top` is what `rebalance_with`
+    /// returns.
+    Rotated { direction: Direction, pivot: &'a NodePtr<T, A>, top: &'a NodePtr<T, A> },
+}
 
 #[derive(Debug)] 
-pub struct Node<T: Clone + Debug> {
+pub struct Node<T: Clone + Debug, A: Monoid<T> = ()> {
     pub data: T,
     pub height: i32,
-    pub left: Option<NodePtr<T>>,
-    pub right: Option<NodePtr<T>>,
+    /// `A`'s summary of this node's subtree, kept current by `update_height`
+    pub summary: A::Value,
+    pub left: Option<NodePtr<T, A>>,
+    pub right: Option<NodePtr<T, A>>,
+}
+
+impl<T: Clone + Debug, A: Monoid<T>> Summarized<T, A> for Node<T, A> {
+    fn key(&self) -> &T {
+        &self.data
+    }
+
+    fn summary(&self) -> &A::Value {
+        &self.summary
+    }
+
+    fn left(&self) -> &Option<NodePtr<T, A>> {
+        &self.left
+    }
+
+    fn right(&self) -> &Option<NodePtr<T, A>> {
+        &self.right
+    }
 }
 
+/// Nodes without a summary, which the tests build by hand
+#[cfg(test)]
 impl<T: Ord + Clone + Debug> Node<T> {
     pub fn new(data: T) -> NodePtr<T> {
+        Node::leaf(data)
+    }
+}
+
+impl<T: Clone + Debug, A: Monoid<T>> Node<T, A> {
+    /// A node with no children, summarized under `A`
+    pub fn leaf(data: T) -> NodePtr<T, A> {
         Rc::new(RefCell::new(Node {
+            summary: A::of(&data),
             data,
             height: 1, 
             left: None,
@@ -22,20 +66,124 @@ impl<T: Ord + Clone + Debug> Node<T> {
         }))
     }
 
-    pub fn update_height(node: &NodePtr<T>) {
+    /// Recompute the height and summary from the children's, which must
+    /// already be current
+    pub fn update_height(node: &NodePtr<T, A>) {
         let node_borrow = node.borrow();
         let left_height = node_borrow.left.as_ref().map_or(0, |n| n.borrow().height);
         let right_height = node_borrow.right.as_ref().map_or(0, |n| n.borrow().height);
+        let summary = A::combine(
+            &A::combine(&Self::summary_of(&node_borrow.left), &A::of(&node_borrow.data)),
+            &Self::summary_of(&node_borrow.right),
+        );
         drop(node_borrow);
-        node.borrow_mut().height = 1 + std::cmp::max(left_height, right_height);
+        let mut node_borrow = node.borrow_mut();
+        node_borrow.height = 1 + std::cmp::max(left_height, right_height);
+        node_borrow.summary = summary;
+    }
+
+    /// A copy of the subtree under `node` that shares no nodes with it
+    pub fn deep_clone(node: &Option<NodePtr<T, A>>) -> Option<NodePtr<T, A>> {
+        node.as_ref().map(|node| {
+            let node = node.borrow();
+            Rc::new(RefCell::new(Node {
+                data: node.data.clone(),
+                height: node.height,
+                summary: node.summary.clone(),
+                left: Node::deep_clone(&node.left),
+                right: Node::deep_clone(&node.right),
+            }))
+        })
     }
 
-    pub fn balance_factor(node: &NodePtr<T>) -> i32 {
+    /// The summary of a possibly empty subtree
+    pub fn summary_of(node: &Option<NodePtr<T, A>>) -> A::Value {
+        node.as_ref().map_or_else(A::empty, |n| n.borrow().summary.clone())
+    }
+
+    pub fn balance_factor(node: &NodePtr<T, A>) -> i32 {
         let node_borrow = node.borrow();
         let left_height = node_borrow.left.as_ref().map_or(0, |n| n.borrow().height);
         let right_height = node_borrow.right.as_ref().map_or(0, |n| n.borrow().height);
         left_height - right_height
     }
+
+    /// Point whichever of `parent`'s children is `old` at `new` instead
+    pub fn replace_child(parent: &NodePtr<T, A>, old: &NodePtr<T, A>, new: Option<NodePtr<T, A>>) {
+        let mut parent_borrow = parent.borrow_mut();
+        if parent_borrow.left.as_ref().is_some_and(|left| Rc::ptr_eq(left, old)) {
+            parent_borrow.left = new;
+        } else {
+            parent_borrow.right = new;
+        }
+    }
+
+    /// Lift `node`'s right child above it, returning the new subtree root
+    pub fn rotate_left(node: NodePtr<T, A>) -> NodePtr<T, A> {
+        let node_right = node.borrow().right.as_ref().unwrap().clone();
+        let node_right_left = node_right.borrow().left.clone();
+
+        node_right.borrow_mut().left = Some(node.clone());
+        node.borrow_mut().right = node_right_left;
+
+        Node::update_height(&node);
+        Node::update_height(&node_right);
+        node_right
+    }
+
+    /// Lift `node`'s left child above it, returning the new subtree root
+    pub fn rotate_right(node: NodePtr<T, A>) -> NodePtr<T, A> {
+        let node_left = node.borrow().left.as_ref().unwrap().clone();
+        let node_left_right = node_left.borrow().right.clone();
+
+        node_left.borrow_mut().right = Some(node.clone());
+        node.borrow_mut().left = node_left_right;
+
+        Node::update_height(&node);
+        Node::update_height(&node_left);
+        node_left
+    }
+
+    /// Restore the balance of a node whose children are balanced and differ
+    /// in height by at most two, returning the new subtree root
+    pub fn rebalance(node: NodePtr<T, A>) -> NodePtr<T, A> {
+        Node::rebalance_with(node, |_| ())
+    }
+
+    /// Like `rebalance`, but tells `observe` the case found and each
+    /// rotation made. No node is borrowed while `observe` runs.
+    pub fn rebalance_with(node: NodePtr<T, A>, mut observe: impl FnMut(Rebalancing<'_, T, A>)) -> NodePtr<T, A> {
+        let balance = Node::balance_factor(&node);
+        // Left heavy subtree
+        if balance > 1 {
+            let left = node.borrow().left.clone().unwrap();
+            let double = Node::balance_factor(&left) < 0;
+            observe(Rebalancing::Case(if double { RebalanceCase::LeftRight } else { RebalanceCase::LeftLeft }));
+            if double {
+                let top = Node::rotate_left(left.clone());
+                node.borrow_mut().left = Some(top.clone());
+                observe(Rebalancing::Rotated { direction: Direction::Left, pivot: &left, top: &top });
+            }
+            let top = Node::rotate_right(node.clone());
+            observe(Rebalancing::Rotated { direction: Direction::Right, pivot: &node, top: &top });
+            return top;
+        }
+        // Right heavy subtree
+        else if balance < -1 {
+            let right = node.borrow().right.clone().unwrap();
+            let double = Node::balance_factor(&right) > 0;
+            observe(Rebalancing::Case(if double { RebalanceCase::RightLeft } else { RebalanceCase::RightRight }));
+            if double {
+                let top = Node::rotate_right(right.clone());
+                node.borrow_mut().right = Some(top.clone());
+                observe(Rebalancing::Rotated { direction: Direction::Right, pivot: &right, top: &top });
+            }
+            let top = Node::rotate_left(node.clone());
+            observe(Rebalancing::Rotated { direction: Direction::Left, pivot: &node, top: &top });
+            return top;
+        }
+        node
+    }
 }
 
 
@@ -0,0 +1,636 @@
+//! A rope: text split into chunks of at most `CHUNK_BYTES`, kept in position
+//! order in an AVL tree.
+//!
+//! Every node summarizes its subtree's bytes, chars and newlines, so byte,
+//! char and line positions can all be found in O(log n). Small edits change a
+//! single chunk in place; larger ones split the tree at the edit and join the
+//! pieces back together. Either way, a chunk left below `MIN_CHUNK_BYTES`
+//! next to the edit is merged with its neighbour.
+
+use std::fmt::{self, Debug, Display};
+use std::ops::{Bound, Range, RangeBounds};
+
+use crate::augment::Monoid;
+use crate::node::{Node, NodePtr};
+use crate::seq::join;
+
+/// Largest chunk, in bytes, that building or inserting text produces
+pub const CHUNK_BYTES: usize = 1024;
+
+/// Smallest chunk edits leave behind, unless it is the rope's only one
+pub const MIN_CHUNK_BYTES: usize = CHUNK_BYTES / 4;
+
+/// Length of some text in each unit a rope can be indexed by
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Metrics {
+    pub bytes: usize,
+    pub chars: usize,
+    pub newlines: usize,
+}
+
+impl Metrics {
+    pub fn of(text: &str) -> Self {
+        Metrics { bytes: text.len(), chars: text.chars().count(), newlines: text.matches('\n').count() }
+    }
+}
+
+/// Sums `Metrics` over a subtree's chunks
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TextMetrics;
+
+impl Monoid<String> for TextMetrics {
+    type Value = Metrics;
+
+    fn empty() -> Metrics {
+        Metrics::default()
+    }
+
+    fn of(chunk: &String) -> Metrics {
+        Metrics::of(chunk)
+    }
+
+    fn combine(left: &Metrics, right: &Metrics) -> Metrics {
+        Metrics {
+            bytes: left.bytes + right.bytes,
+            chars: left.chars + right.chars,
+            newlines: left.newlines + right.newlines,
+        }
+    }
+}
+
+type Link = Option<NodePtr<String, TextMetrics>>;
+
+pub struct Rope {
+    root: Link,
+}
+
+impl Rope {
+    pub fn new() -> Self {
+        Rope { root: None }
+    }
+
+    pub fn metrics(&self) -> Metrics {
+        Node::summary_of(&self.root)
+    }
+
+    pub fn len_bytes(&self) -> usize {
+        self.metrics().bytes
+    }
+
+    pub fn len_chars(&self) -> usize {
+        self.metrics().chars
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Lines as an editor counts them: one more than the newlines, so empty
+    /// text has one line and text ending in a newline has an empty last line
+    pub fn line_count(&self) -> usize {
+        self.metrics().newlines + 1
+    }
+
+    /// Byte offset where `line` (counting from 0) starts, or `None` past the
+    /// last line
+    pub fn line_to_byte(&self, line: usize) -> Option<usize> {
+        if line == 0 {
+            return Some(0);
+        }
+        // Find the newline ending the previous line
+        let mut newline = line;
+        let mut offset = 0;
+        let mut current = self.root.clone();
+        while let Some(node) = current {
+            let node_borrow = node.borrow();
+            let left = Node::summary_of(&node_borrow.left);
+            let chunk = chunk_metrics(&node_borrow);
+            current = if newline <= left.newlines {
+                node_borrow.left.clone()
+            } else if newline <= left.newlines + chunk.newlines {
+                let (index, _) = node_borrow.data.match_indices('\n').nth(newline - left.newlines - 1).unwrap();
+                return Some(offset + left.bytes + index + 1);
+            } else {
+                newline -= left.newlines + chunk.newlines;
+                offset += left.bytes + chunk.bytes;
+                node_borrow.right.clone()
+            };
+        }
+        None
+    }
+
+    pub fn char_at(&self, char_index: usize) -> Option<char> {
+        let mut index = char_index;
+        let mut current = self.root.clone();
+        while let Some(node) = current {
+            let node_borrow = node.borrow();
+            let left = Node::summary_of(&node_borrow.left).chars;
+            let chunk = chunk_metrics(&node_borrow).chars;
+            current = if index < left {
+                node_borrow.left.clone()
+            } else if index < left + chunk {
+                return node_borrow.data.chars().nth(index - left);
+            } else {
+                index -= left + chunk;
+                node_borrow.right.clone()
+            };
+        }
+        None
+    }
+
+    /// Whether `byte` falls between two chars, counting both ends of the text
+    pub fn is_char_boundary(&self, byte: usize) -> bool {
+        let mut offset = byte;
+        let mut current = self.root.clone();
+        while let Some(node) = current {
+            let node_borrow = node.borrow();
+            let left = Node::summary_of(&node_borrow.left).bytes;
+            let chunk = node_borrow.data.len();
+            current = if offset <= left {
+                node_borrow.left.clone()
+            } else if offset < left + chunk {
+                return node_borrow.data.is_char_boundary(offset - left);
+            } else {
+                offset -= left + chunk;
+                node_borrow.right.clone()
+            };
+        }
+        offset == 0
+    }
+
+    /// Insert `text` starting at `byte`. Panics if `byte` is past the end or
+    /// inside a char.
+    pub fn insert_str(&mut self, byte: usize, text: &str) {
+        self.check_boundary(byte);
+        if text.is_empty() {
+            return;
+        }
+        let fits = |chunk: &mut String, start: usize, _: usize| {
+            let fits = chunk.len() + text.len() <= CHUNK_BYTES;
+            if fits {
+                chunk.insert_str(start, text);
+            }
+            fits
+        };
+        if edit_chunk(&self.root, byte, byte, &fits) {
+            return;
+        }
+        let (before, after) = split(self.root.take(), byte);
+        self.root = concat(concat(before, build(cut_chunks(text))), after);
+        // The chunk cut at `byte` left pieces at both ends of the new text
+        self.mend(byte);
+        self.mend(byte + text.len());
+    }
+
+    /// Remove the bytes in `range`. Panics if either end is past the end of
+    /// the text or inside a char.
+    pub fn delete<R: RangeBounds<usize>>(&mut self, range: R) {
+        let Range { start, end } = self.resolve(range);
+        if start == end {
+            return;
+        }
+        // Leave at least one byte so no chunk is ever empty
+        let shrinks = |chunk: &mut String, start: usize, end: usize| {
+            let shrinks = end - start < chunk.len();
+            if shrinks {
+                chunk.replace_range(start..end, "");
+            }
+            shrinks
+        };
+        if !edit_chunk(&self.root, start, end, &shrinks) {
+            let (before, rest) = split(self.root.take(), start);
+            let (_, after) = split(rest, end - start);
+            self.root = concat(before, after);
+        }
+        self.mend(start);
+    }
+
+    /// The text in the byte `range`. Panics if either end is past the end of
+    /// the text or inside a char.
+    pub fn slice<R: RangeBounds<usize>>(&self, range: R) -> String {
+        let range = self.resolve(range);
+        let mut text = String::with_capacity(range.end - range.start);
+        collect(&self.root, 0, &range, &mut text);
+        text
+    }
+
+    /// The chunks in order
+    pub fn chunks(&self) -> Vec<String> {
+        let mut chunks = Vec::new();
+        walk(&self.root, &mut |chunk| chunks.push(chunk.to_string()));
+        chunks
+    }
+
+    /// Check balance, stored heights and metrics, that no chunk is empty,
+    /// and that none is below `MIN_CHUNK_BYTES` unless it stands alone
+    pub fn validate(&self) -> Result<(), String> {
+        let alone = self.root.as_ref().is_some_and(|root| root.borrow().height == 1);
+        let min_bytes = if alone { 1 } else { MIN_CHUNK_BYTES };
+        validate_rec(&self.root, 0, min_bytes).map(|_| ())
+    }
+
+    /// Merge the chunks either side of `byte` while either is below
+    /// `MIN_CHUNK_BYTES`, taking in a neighbour when `byte` falls inside a
+    /// single small chunk, and cut the text back into even chunks
+    fn mend(&mut self, byte: usize) {
+        let len = self.len_bytes();
+        if len == 0 {
+            return;
+        }
+        // Each merge leaves one chunk fewer, and two small pieces can merge
+        // into one that is still small
+        loop {
+            let before = chunk_around(&self.root, byte.clamp(1, len) - 1);
+            let after = chunk_around(&self.root, std::cmp::min(byte, len - 1));
+            if before.len() >= MIN_CHUNK_BYTES && after.len() >= MIN_CHUNK_BYTES {
+                return;
+            }
+            let mut span = before.start..after.end;
+            if before == after {
+                if span.start > 0 {
+                    span.start = chunk_around(&self.root, span.start - 1).start;
+                } else if span.end < len {
+                    span.end = chunk_around(&self.root, span.end).end;
+                } else {
+                    return;
+                }
+            }
+            let (head, rest) = split(self.root.take(), span.start);
+            let (middle, tail) = split(rest, span.len());
+            let mut text = String::with_capacity(span.len());
+            walk(&middle, &mut |chunk| text.push_str(chunk));
+            self.root = concat(concat(head, build(cut_chunks(&text))), tail);
+        }
+    }
+
+    fn check_boundary(&self, byte: usize) {
+        let len = self.len_bytes();
+        assert!(byte <= len, "byte index {} is out of bounds of a rope of {} bytes", byte, len);
+        assert!(self.is_char_boundary(byte), "byte index {} is not a char boundary", byte);
+    }
+
+    fn resolve<R: RangeBounds<usize>>(&self, range: R) -> Range<usize> {
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end + 1,
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => self.len_bytes(),
+        };
+        assert!(start <= end, "range starts at {} but ends at {}", start, end);
+        self.check_boundary(start);
+        self.check_boundary(end);
+        start..end
+    }
+}
+
+/// `text` cut into as few chunks of at most `CHUNK_BYTES` as hold it, of
+/// about even size and each ending on a char boundary
+fn cut_chunks(text: &str) -> Vec<String> {
+    let mut chunks = Vec::with_capacity(text.len().div_ceil(CHUNK_BYTES));
+    let mut rest = text;
+    while !rest.is_empty() {
+        let mut cut = rest.len().div_ceil(rest.len().div_ceil(CHUNK_BYTES));
+        while !rest.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        let (chunk, tail) = rest.split_at(cut);
+        chunks.push(chunk.to_string());
+        rest = tail;
+    }
+    chunks
+}
+
+/// A node's own chunk's metrics: its summary less its children's, which
+/// spares scanning the chunk's text
+fn chunk_metrics(node: &Node<String, TextMetrics>) -> Metrics {
+    let (left, right) = (Node::summary_of(&node.left), Node::summary_of(&node.right));
+    Metrics {
+        bytes: node.summary.bytes - left.bytes - right.bytes,
+        chars: node.summary.chars - left.chars - right.chars,
+        newlines: node.summary.newlines - left.newlines - right.newlines,
+    }
+}
+
+/// The byte range of the chunk holding `byte`, which must be in the text
+fn chunk_around(root: &Link, byte: usize) -> Range<usize> {
+    let mut offset = 0;
+    let mut current = root.clone();
+    while let Some(node) = current {
+        let node_borrow = node.borrow();
+        let start = offset + Node::summary_of(&node_borrow.left).bytes;
+        let end = start + node_borrow.data.len();
+        current = if byte < start {
+            node_borrow.left.clone()
+        } else if byte < end {
+            return start..end;
+        } else {
+            offset = end;
+            node_borrow.right.clone()
+        };
+    }
+    unreachable!("byte {} is past the end of the rope", byte)
+}
+
+/// A perfectly balanced tree of `chunks`, in order
+fn build(mut chunks: Vec<String>) -> Link {
+    if chunks.is_empty() {
+        return None;
+    }
+    let right = chunks.split_off(chunks.len() / 2 + 1);
+    let node = Node::leaf(chunks.pop().unwrap());
+    let mut node_borrow = node.borrow_mut();
+    node_borrow.left = build(chunks);
+    node_borrow.right = build(right);
+    drop(node_borrow);
+    Node::update_height(&node);
+    Some(node)
+}
+
+fn concat(left: Link, right: Link) -> Link {
+    match (left, right) {
+        (None, only) | (only, None) => only,
+        (Some(left), Some(right)) => {
+            // right's first chunk becomes the node the two trees hang from
+            let first = first_chunk_bytes(&right);
+            let (mid, rest) = split(Some(right), first);
+            Some(join(Some(left), mid.unwrap(), rest))
+        }
+    }
+}
+
+/// Bytes in the first chunk of a non-empty tree
+fn first_chunk_bytes(node: &NodePtr<String, TextMetrics>) -> usize {
+    let mut current = node.clone();
+    loop {
+        let left = current.borrow().left.clone();
+        match left {
+            Some(left) => current = left,
+            None => return current.borrow().data.len(),
+        }
+    }
+}
+
+/// The first `byte` bytes of the subtree and the rest, as two trees, cutting
+/// a chunk in two if `byte` falls inside it
+fn split(node: Link, byte: usize) -> (Link, Link) {
+    let node = match node {
+        None => return (None, None),
+        Some(node) => node,
+    };
+    let left = node.borrow_mut().left.take();
+    let right = node.borrow_mut().right.take();
+    let left_bytes = Node::summary_of(&left).bytes;
+    let chunk_bytes = node.borrow().data.len();
+    if byte <= left_bytes {
+        let (before, after) = split(left, byte);
+        (before, Some(join(after, node, right)))
+    } else if byte >= left_bytes + chunk_bytes {
+        let (before, after) = split(right, byte - left_bytes - chunk_bytes);
+        (Some(join(left, node, before)), after)
+    } else {
+        let tail = node.borrow_mut().data.split_off(byte - left_bytes);
+        (Some(join(left, node, None)), Some(join(None, Node::leaf(tail), right)))
+    }
+}
+
+/// Apply `edit` to the one chunk holding the bytes `start..end`, with the
+/// range made relative to the chunk, then refresh the metrics above it.
+/// Returns false, changing nothing, if the range spans chunks or `edit`
+/// declines.
+fn edit_chunk(node: &Link, start: usize, end: usize, edit: &dyn Fn(&mut String, usize, usize) -> bool) -> bool {
+    let node = match node {
+        None => return false,
+        Some(node) => node,
+    };
+    let (left_bytes, chunk_bytes) = {
+        let node_borrow = node.borrow();
+        (Node::summary_of(&node_borrow.left).bytes, node_borrow.data.len())
+    };
+    let edited = if start < left_bytes {
+        end <= left_bytes && edit_chunk(&node.borrow().left, start, end, edit)
+    } else if end <= left_bytes + chunk_bytes {
+        edit(&mut node.borrow_mut().data, start - left_bytes, end - left_bytes)
+    } else if start >= left_bytes + chunk_bytes {
+        let offset = left_bytes + chunk_bytes;
+        edit_chunk(&node.borrow().right, start - offset, end - offset, edit)
+    } else {
+        false
+    };
+    if edited {
+        Node::update_height(node);
+    }
+    edited
+}
+
+/// Append the part of the subtree that falls in `range` to `text`; the
+/// subtree starts at byte `offset`
+fn collect(node: &Link, offset: usize, range: &Range<usize>, text: &mut String) {
+    let node = match node {
+        None => return,
+        Some(node) => node.borrow(),
+    };
+    let left_bytes = Node::summary_of(&node.left).bytes;
+    let chunk_start = offset + left_bytes;
+    let chunk_end = chunk_start + node.data.len();
+    if range.start < chunk_start {
+        collect(&node.left, offset, range, text);
+    }
+    if range.start < chunk_end && chunk_start < range.end {
+        let from = range.start.saturating_sub(chunk_start);
+        let to = std::cmp::min(range.end, chunk_end) - chunk_start;
+        text.push_str(&node.data[from..to]);
+    }
+    if chunk_end < range.end {
+        collect(&node.right, chunk_end, range, text);
+    }
+}
+
+fn walk(node: &Link, visit: &mut dyn FnMut(&str)) {
+    if let Some(node) = node {
+        let node_borrow = node.borrow();
+        walk(&node_borrow.left, visit);
+        visit(&node_borrow.data);
+        walk(&node_borrow.right, visit);
+    }
+}
+
+/// Returns the height and metrics of a valid subtree starting at byte
+/// `offset`, which only serves to locate chunks in errors
+fn validate_rec(node: &Link, offset: usize, min_bytes: usize) -> Result<(i32, Metrics), String> {
+    let node = match node {
+        None => return Ok((0, Metrics::default())),
+        Some(node) => node.borrow(),
+    };
+    let (left_height, left) = validate_rec(&node.left, offset, min_bytes)?;
+    let start = offset + left.bytes;
+    if node.data.is_empty() {
+        return Err(format!("the chunk at byte {} is empty", start));
+    }
+    if node.data.len() < min_bytes {
+        return Err(format!("the chunk at byte {} has {} bytes, fewer than {}", start, node.data.len(), min_bytes));
+    }
+    let (right_height, right) = validate_rec(&node.right, start + node.data.len(), min_bytes)?;
+    if (left_height - right_height).abs() > 1 {
+        return Err(format!("the chunk at byte {} is unbalanced: left height {}, right height {}", start, left_height, right_height));
+    }
+    let height = 1 + std::cmp::max(left_height, right_height);
+    if node.height != height {
+        return Err(format!("the chunk at byte {} stores height {} but has height {}", start, node.height, height));
+    }
+    let metrics = TextMetrics::combine(&TextMetrics::combine(&left, &Metrics::of(&node.data)), &right);
+    if node.summary != metrics {
+        return Err(format!("the chunk at byte {} stores {:?} but has {:?}", start, node.summary, metrics));
+    }
+    Ok((height, metrics))
+}
+
+impl Default for Rope {
+    fn default() -> Self {
+        Rope::new()
+    }
+}
+
+impl From<&str> for Rope {
+    fn from(text: &str) -> Self {
+        Rope { root: build(cut_chunks(text)) }
+    }
+}
+
+impl From<String> for Rope {
+    fn from(text: String) -> Self {
+        Rope::from(text.as_str())
+    }
+}
+
+impl From<&Rope> for String {
+    fn from(rope: &Rope) -> Self {
+        let mut text = String::with_capacity(rope.len_bytes());
+        walk(&rope.root, &mut |chunk| text.push_str(chunk));
+        text
+    }
+}
+
+impl From<Rope> for String {
+    fn from(rope: Rope) -> Self {
+        String::from(&rope)
+    }
+}
+
+impl Display for Rope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut result = Ok(());
+        walk(&self.root, &mut |chunk| {
+            if result.is_ok() {
+                result = f.write_str(chunk);
+            }
+        });
+        result
+    }
+}
+
+impl Debug for Rope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Rope({:?})", String::from(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_edits_match_string() {
+        let mut rope = Rope::new();
+        let mut expected = String::new();
+        let mut rng = StdRng::seed_from_u64(421);
+        let mut next = |bound: usize| rng.gen_range(0..bound);
+        let words = ["a", "héllo ", "wörld\n", "\n", "日本語", "🦀 crab\n", &"x".repeat(3000)];
+        for step in 0..600 {
+            // Any char boundary, found by walking back from a random byte
+            let mut at = next(expected.len() + 1);
+            while !expected.is_char_boundary(at) {
+                at -= 1;
+            }
+            if step % 3 == 2 {
+                let mut end = at + next(expected.len() - at + 1).min(2500);
+                while !expected.is_char_boundary(end) {
+                    end -= 1;
+                }
+                rope.delete(at..end);
+                expected.replace_range(at..end, "");
+            } else {
+                let word = words[next(words.len())];
+                rope.insert_str(at, word);
+                expected.insert_str(at, word);
+            }
+            rope.validate().unwrap();
+            assert_eq!(rope.len_bytes(), expected.len());
+            assert_eq!(rope.len_chars(), expected.chars().count());
+            assert_eq!(rope.line_count(), expected.matches('\n').count() + 1);
+        }
+        assert!(rope.chunks().len() > 1);
+        assert!(rope.chunks().iter().all(|chunk| chunk.len() <= CHUNK_BYTES));
+        assert_eq!(String::from(&rope), expected);
+        assert_eq!(rope.to_string(), expected);
+
+        let quarter = (1..).map(|i| expected.len() / 4 + i).find(|&i| expected.is_char_boundary(i)).unwrap();
+        assert_eq!(rope.slice(quarter..), expected[quarter..]);
+        assert_eq!(rope.slice(..quarter), expected[..quarter]);
+        for (index, char) in expected.chars().enumerate().step_by(97) {
+            assert_eq!(rope.char_at(index), Some(char));
+        }
+        assert_eq!(rope.char_at(expected.chars().count()), None);
+    }
+
+    #[test]
+    fn test_small_chunks_merge() {
+        let mut rope = Rope::from("x".repeat(2000));
+        assert_eq!(rope.chunks().iter().map(String::len).collect::<Vec<_>>(), vec![1000, 1000]);
+        // Shrinks the first chunk in place, below the minimum
+        rope.delete(10..990);
+        rope.validate().unwrap();
+        assert_eq!(rope.chunks().len(), 1);
+
+        // A small insert that cannot fit beside a full chunk merges into it
+        let mut rope = Rope::from("y".repeat(CHUNK_BYTES * 2));
+        rope.insert_str(CHUNK_BYTES, "z");
+        rope.validate().unwrap();
+        assert!(rope.chunks().iter().all(|chunk| chunk.len() >= MIN_CHUNK_BYTES));
+        assert_eq!(rope.slice(CHUNK_BYTES - 1..CHUNK_BYTES + 2), "yzy");
+    }
+
+    #[test]
+    fn test_lines() {
+        let rope = Rope::from("first\nsecond\n\nlast");
+        assert_eq!(rope.line_count(), 4);
+        assert_eq!(rope.line_to_byte(0), Some(0));
+        assert_eq!(rope.line_to_byte(1), Some(6));
+        assert_eq!(rope.line_to_byte(2), Some(13));
+        assert_eq!(rope.line_to_byte(3), Some(14));
+        assert_eq!(rope.line_to_byte(4), None);
+        assert_eq!(Rope::new().line_count(), 1);
+
+        // Across chunks
+        let text: String = (0..500).map(|line| format!("line {}\n", line)).collect();
+        let rope = Rope::from(text.clone());
+        rope.validate().unwrap();
+        assert_eq!(rope.line_count(), 501);
+        for line in [1, 137, 499, 500] {
+            let expected = text.match_indices('\n').nth(line - 1).unwrap().0 + 1;
+            assert_eq!(rope.line_to_byte(line), Some(expected));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "byte index 1 is not a char boundary")]
+    fn test_insert_inside_char_panics() {
+        let mut rope = Rope::from("é");
+        rope.insert_str(1, "x");
+    }
+}
@@ -0,0 +1,342 @@
+//! A list kept in an AVL tree, ordered by position instead of by key.
+//!
+//! Each node's `Count` summary is the size of its subtree, so an index picks
+//! a side at every level: left if it falls within the left subtree, the node
+//! itself if it equals the left subtree's size, and right otherwise. Inserts,
+//! removes and lookups at any position, splits and concatenations all take
+//! O(log n).
+
+use std::fmt::{self, Debug};
+
+use crate::augment::{Count, Monoid};
+use crate::node::{Node, NodePtr};
+
+type Link<T> = Option<NodePtr<T, Count>>;
+
+pub struct AvlSeq<T: Clone + Debug> {
+    root: Link<T>,
+}
+
+impl<T: Clone + Debug> AvlSeq<T> {
+    pub fn new() -> Self {
+        AvlSeq { root: None }
+    }
+
+    pub fn len(&self) -> usize {
+        size(&self.root)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Insert `value` so it ends up at `index`, shifting everything after it
+    /// along. Panics if `index > len`.
+    pub fn insert_at(&mut self, index: usize, value: T) {
+        let len = self.len();
+        assert!(index <= len, "insertion index (is {}) should be <= len (is {})", index, len);
+        self.root = Some(insert_rec(self.root.take(), index, value));
+    }
+
+    pub fn push(&mut self, value: T) {
+        self.insert_at(self.len(), value);
+    }
+
+    /// Remove and return the value at `index`. Panics if `index >= len`.
+    pub fn remove_at(&mut self, index: usize) -> T {
+        let len = self.len();
+        assert!(index < len, "removal index (is {}) should be < len (is {})", index, len);
+        let mut removed = None;
+        self.root = remove_rec(self.root.take().unwrap(), index, &mut removed);
+        removed.unwrap()
+    }
+
+    pub fn get(&self, index: usize) -> Option<T> {
+        let mut current = self.root.clone();
+        let mut index = index;
+        while let Some(node) = current {
+            let node_borrow = node.borrow();
+            let left_size = size(&node_borrow.left);
+            current = match index.cmp(&left_size) {
+                std::cmp::Ordering::Less => node_borrow.left.clone(),
+                std::cmp::Ordering::Equal => return Some(node_borrow.data.clone()),
+                std::cmp::Ordering::Greater => {
+                    index -= left_size + 1;
+                    node_borrow.right.clone()
+                }
+            };
+        }
+        None
+    }
+
+    /// The first `index` values and the rest. Panics if `index > len`.
+    pub fn split_at(self, index: usize) -> (AvlSeq<T>, AvlSeq<T>) {
+        let len = self.len();
+        assert!(index <= len, "split index (is {}) should be <= len (is {})", index, len);
+        let (left, right) = split(self.root, index);
+        (AvlSeq { root: left }, AvlSeq { root: right })
+    }
+
+    /// This sequence followed by `other`
+    pub fn concat(self, other: AvlSeq<T>) -> AvlSeq<T> {
+        if other.is_empty() {
+            return self;
+        }
+        // other's first node becomes the one the two trees hang from
+        let (first, rest) = split(other.root, 1);
+        AvlSeq { root: Some(join(self.root, first.unwrap(), rest)) }
+    }
+
+    pub fn iter(&self) -> Iter<T> {
+        let mut iter = Iter { stack: Vec::new() };
+        iter.push_left_spine(self.root.clone());
+        iter
+    }
+
+    /// Check that every node is balanced and stores its true height and size
+    pub fn validate(&self) -> Result<(), String> {
+        validate_rec(&self.root, 0).map(|_| ())
+    }
+}
+
+fn size<T: Clone + Debug>(node: &Link<T>) -> usize {
+    Node::summary_of(node)
+}
+
+fn height<T: Clone + Debug, A: Monoid<T>>(node: &Option<NodePtr<T, A>>) -> i32 {
+    node.as_ref().map_or(0, |node| node.borrow().height)
+}
+
+fn insert_rec<T: Clone + Debug>(node: Link<T>, index: usize, value: T) -> NodePtr<T, Count> {
+    let node = match node {
+        None => return Node::leaf(value),
+        Some(node) => node,
+    };
+    let left_size = size(&node.borrow().left);
+    if index <= left_size {
+        let left = node.borrow_mut().left.take();
+        node.borrow_mut().left = Some(insert_rec(left, index, value));
+    } else {
+        let right = node.borrow_mut().right.take();
+        node.borrow_mut().right = Some(insert_rec(right, index - left_size - 1, value));
+    }
+    Node::update_height(&node);
+    Node::rebalance(node)
+}
+
+fn remove_rec<T: Clone + Debug>(node: NodePtr<T, Count>, index: usize, removed: &mut Option<T>) -> Link<T> {
+    let left_size = size(&node.borrow().left);
+    if index < left_size {
+        let left = node.borrow_mut().left.take().unwrap();
+        node.borrow_mut().left = remove_rec(left, index, removed);
+    } else if index > left_size {
+        let right = node.borrow_mut().right.take().unwrap();
+        node.borrow_mut().right = remove_rec(right, index - left_size - 1, removed);
+    } else {
+        let left = node.borrow_mut().left.take();
+        let right = node.borrow_mut().right.take();
+        match (left, right) {
+            (None, only) | (only, None) => {
+                *removed = Some(node.borrow().data.clone());
+                return only;
+            }
+            // Two children: the next value moves up into this node
+            (Some(left), Some(right)) => {
+                let mut successor = None;
+                let right = remove_rec(right, 0, &mut successor);
+                let mut node_borrow = node.borrow_mut();
+                *removed = Some(std::mem::replace(&mut node_borrow.data, successor.unwrap()));
+                node_borrow.left = Some(left);
+                node_borrow.right = right;
+            }
+        }
+    }
+    Node::update_height(&node);
+    Some(Node::rebalance(node))
+}
+
+/// Every value in `left`, then `mid`'s, then every value in `right`, as one
+/// balanced tree. Descends the taller side until the heights are within one.
+pub(crate) fn join<T: Clone + Debug, A: Monoid<T>>(
+    left: Option<NodePtr<T, A>>,
+    mid: NodePtr<T, A>,
+    right: Option<NodePtr<T, A>>,
+) -> NodePtr<T, A> {
+    let (left_height, right_height) = (height(&left), height(&right));
+    if left_height > right_height + 1 {
+        let left = left.unwrap();
+        let left_right = left.borrow_mut().right.take();
+        left.borrow_mut().right = Some(join(left_right, mid, right));
+        Node::update_height(&left);
+        Node::rebalance(left)
+    } else if right_height > left_height + 1 {
+        let right = right.unwrap();
+        let right_left = right.borrow_mut().left.take();
+        right.borrow_mut().left = Some(join(left, mid, right_left));
+        Node::update_height(&right);
+        Node::rebalance(right)
+    } else {
+        mid.borrow_mut().left = left;
+        mid.borrow_mut().right = right;
+        Node::update_height(&mid);
+        mid
+    }
+}
+
+/// The first `index` values of the subtree and the rest, as two trees
+fn split<T: Clone + Debug>(node: Link<T>, index: usize) -> (Link<T>, Link<T>) {
+    let node = match node {
+        None => return (None, None),
+        Some(node) => node,
+    };
+    let left = node.borrow_mut().left.take();
+    let right = node.borrow_mut().right.take();
+    let left_size = size(&left);
+    if index <= left_size {
+        let (before, after) = split(left, index);
+        (before, Some(join(after, node, right)))
+    } else {
+        let (before, after) = split(right, index - left_size - 1);
+        (Some(join(left, node, before)), after)
+    }
+}
+
+/// Returns the height and size of a valid subtree whose first value is at
+/// `position`, which only serves to name nodes in errors
+fn validate_rec<T: Clone + Debug>(node: &Link<T>, position: usize) -> Result<(i32, usize), String> {
+    let node = match node {
+        None => return Ok((0, 0)),
+        Some(node) => node.borrow(),
+    };
+    let (left_height, left_size) = validate_rec(&node.left, position)?;
+    let (right_height, right_size) = validate_rec(&node.right, position + left_size + 1)?;
+    let index = position + left_size;
+    if (left_height - right_height).abs() > 1 {
+        return Err(format!("index {} is unbalanced: left height {}, right height {}", index, left_height, right_height));
+    }
+    let (height, size) = (1 + std::cmp::max(left_height, right_height), left_size + 1 + right_size);
+    if node.height != height {
+        return Err(format!("index {} stores height {} but has height {}", index, node.height, height));
+    }
+    if node.summary != size {
+        return Err(format!("index {} stores size {} but has size {}", index, node.summary, size));
+    }
+    Ok((height, size))
+}
+
+/// The values in order, cloned out of the nodes
+pub struct Iter<T: Clone + Debug> {
+    stack: Vec<NodePtr<T, Count>>,
+}
+
+impl<T: Clone + Debug> Iter<T> {
+    fn push_left_spine(&mut self, mut node: Link<T>) {
+        while let Some(current) = node {
+            node = current.borrow().left.clone();
+            self.stack.push(current);
+        }
+    }
+}
+
+impl<T: Clone + Debug> Iterator for Iter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let node = self.stack.pop()?;
+        self.push_left_spine(node.borrow().right.clone());
+        let data = node.borrow().data.clone();
+        Some(data)
+    }
+}
+
+impl<T: Clone + Debug> Default for AvlSeq<T> {
+    fn default() -> Self {
+        AvlSeq::new()
+    }
+}
+
+impl<T: Clone + Debug> Debug for AvlSeq<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: Clone + Debug> FromIterator<T> for AvlSeq<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut seq = AvlSeq::new();
+        for value in iter {
+            seq.push(value);
+        }
+        seq
+    }
+}
+
+impl<T: Clone + Debug> IntoIterator for &AvlSeq<T> {
+    type Item = T;
+    type IntoIter = Iter<T>;
+
+    fn into_iter(self) -> Iter<T> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_positional_ops_match_vec() {
+        let mut seq = AvlSeq::new();
+        let mut expected = Vec::new();
+        let mut rng = StdRng::seed_from_u64(421);
+        for step in 0..2000 {
+            let index = rng.gen_range(0..=expected.len());
+            if step % 3 == 2 && !expected.is_empty() {
+                let index = index.min(expected.len() - 1);
+                assert_eq!(seq.remove_at(index), expected.remove(index));
+            } else {
+                seq.insert_at(index, step);
+                expected.insert(index, step);
+            }
+            seq.validate().unwrap();
+            assert_eq!(seq.len(), expected.len());
+            assert_eq!(seq.get(index), expected.get(index).copied());
+        }
+        assert_eq!(seq.iter().collect::<Vec<_>>(), expected);
+        assert_eq!(seq.get(expected.len()), None);
+    }
+
+    #[test]
+    fn test_split_and_concat() {
+        for index in [0, 1, 37, 99, 100] {
+            let seq: AvlSeq<usize> = (0..100).collect();
+            let (front, back) = seq.split_at(index);
+            front.validate().unwrap();
+            back.validate().unwrap();
+            assert_eq!(front.iter().collect::<Vec<_>>(), (0..index).collect::<Vec<_>>());
+            assert_eq!(back.iter().collect::<Vec<_>>(), (index..100).collect::<Vec<_>>());
+
+            let joined = back.concat(front);
+            joined.validate().unwrap();
+            assert_eq!(joined.iter().collect::<Vec<_>>(), (index..100).chain(0..index).collect::<Vec<_>>());
+        }
+
+        // Heights far apart
+        let short: AvlSeq<char> = "ab".chars().collect();
+        let tall: AvlSeq<char> = "cdefghijklmnopqrstuvwxyz".chars().collect();
+        let joined = short.concat(tall);
+        joined.validate().unwrap();
+        assert_eq!(joined.iter().collect::<String>(), "abcdefghijklmnopqrstuvwxyz");
+        assert_eq!(format!("{:?}", joined.split_at(3).0), "['a', 'b', 'c']");
+    }
+
+    #[test]
+    #[should_panic(expected = "insertion index (is 2) should be <= len (is 1)")]
+    fn test_insert_past_end_panics() {
+        let mut seq = AvlSeq::new();
+        seq.push(1);
+        seq.insert_at(2, 2);
+    }
+}
@@ -0,0 +1,35 @@
+//! Structured record of what a single `insert` or `delete` did, returned by
+//! `Tree::insert_traced` and `Tree::delete_traced`, or with intermediate
+//! diagrams by `Tree::insert_explained` and `Tree::delete_explained`.
+
+use std::cmp::Ordering;
+
+pub use tree_core::trace::Direction;
+pub(crate) use tree_core::trace::Recorder;
+
+/// The four imbalance shapes `rebalance` repairs, named by the path from the
+/// unbalanced node to its taller grandchild
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RebalanceCase {
+    /// Fixed by `rotate_right` on the node
+    LeftLeft,
+    /// Fixed by `rotate_left` on the left child, then `rotate_right` on the node
+    LeftRight,
+    /// Fixed by `rotate_left` on the node
+    RightRight,
+    /// Fixed by `rotate_right` on the right child, then `rotate_left` on the node
+    RightLeft,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TraceEvent<T> {
+    /// The operation's key was compared against `node` on the way down
+    Compare { node: T, ordering: Ordering },
+    /// `rebalance` found `node` out of balance
+    Rebalance { case: RebalanceCase, node: T },
+    /// `rotate_left` or `rotate_right` was called with `pivot`
+    Rotate { direction: Direction, pivot: T },
+    /// Diagram of the whole tree right after a rotation. Only recorded by
+    /// the `_explained` operations.
+    Snapshot { diagram: String },
+}
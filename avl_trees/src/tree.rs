@@ -1,286 +1,588 @@
-use std::fmt::Debug;
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::ops::RangeBounds;
+use std::rc::Rc;
 
-use crate::node::{Node, NodePtr};
+use crate::augment::{self, Monoid};
+use crate::cursor::{Cursor, CursorMut, Iter};
+use crate::diagram::{self, DiagramOptions, Label, Sketch};
+use crate::node::{Node, NodePtr, Rebalancing};
+use crate::stats::TreeStats;
+use crate::trace::{RebalanceCase, Recorder, TraceEvent};
+use crate::view::NodeView;
 #[derive(Debug)] 
-pub enum Tree<T: Clone + Ord + Debug> {
-    Empty,
-    Root(NodePtr<T>),
+pub struct Tree<T: Clone + Ord + Debug, A: Monoid<T> = ()> {
+    pub(crate) root: Option<NodePtr<T, A>>,
+    trace: Recorder<TraceEvent<T>>,
+    pub(crate) stats: TreeStats,
+    /// What `search` has cost, kept apart because it only borrows the tree
+    searched: Cell<TreeStats>,
 }
 
 impl<T: Clone + Ord + Debug> Tree<T> {
     pub fn new() -> Self {
-        Tree::Empty
+        Tree::augmented()
+    }
+}
+
+impl<T: Clone + Ord + Debug, A: Monoid<T>> Tree<T, A> {
+    /// An empty tree whose nodes keep an `A` summary of their subtrees, for
+    /// `aggregate`
+    pub fn augmented() -> Self {
+        Tree { root: None, trace: Recorder::disabled(), stats: TreeStats::default(), searched: Cell::default() }
     }
 
-    pub fn insert(&mut self, data: T) {
-        match *self {
-            Tree::Empty => {
-                *self = Tree::Root(Node::new(data));
-            },
-            Tree::Root(ref root) => {
-                let new_root = Self::insert_rec(root, data);
-                *self = Tree::Root(new_root);            
-            },
+    /// Counters for every operation since the tree was created or last reset
+    pub fn stats(&self) -> TreeStats {
+        let searched = self.searched.get();
+        TreeStats {
+            searches: self.stats.searches + searched.searches,
+            comparisons: self.stats.comparisons + searched.comparisons,
+            max_depth: std::cmp::max(self.stats.max_depth, searched.max_depth),
+            ..self.stats
         }
     }
 
-    fn insert_rec(node: &NodePtr<T>, data: T) -> NodePtr<T>  {
-        let mut node_borrow = node.borrow_mut();
-        if data < node_borrow.data {
-            if let Some(ref left) = node_borrow.left {
-                let new_left = Self::insert_rec(left, data);
-                node_borrow.left = Some(new_left);            
-            } else {
-                node_borrow.left = Some(Node::new(data));
+    pub fn reset_stats(&mut self) {
+        self.stats.reset();
+        self.searched.take();
+    }
+
+    /// Returns false, leaving the tree unchanged, if `data` is already present
+    pub fn insert(&mut self, data: T) -> bool {
+        let comparisons = self.stats.comparisons;
+        let (path, ordering) = self.descend(|node| data.cmp(node));
+        if ordering == Some(Ordering::Equal) {
+            return false;
+        }
+        // The new leaf sits one level below the last node it was compared with
+        self.reached_depth(comparisons, 1);
+        self.link_leaf(&path, data, ordering == Some(Ordering::Less), &mut Vec::new());
+        true
+    }
+
+    /// Like `insert`, but returns every comparison, rebalance case and
+    /// rotation the insert performed, in order
+    pub fn insert_traced(&mut self, data: T) -> Vec<TraceEvent<T>> {
+        self.trace = Recorder::enabled();
+        self.insert(data);
+        std::mem::replace(&mut self.trace, Recorder::disabled()).into_events()
+    }
+
+    /// Like `insert_traced`, but also snapshots the whole tree after every
+    /// rotation. Returns whether the key was inserted.
+    pub fn insert_explained(&mut self, data: T) -> (bool, Vec<TraceEvent<T>>) {
+        self.trace = Recorder::with_snapshots();
+        let inserted = self.insert(data);
+        (inserted, std::mem::replace(&mut self.trace, Recorder::disabled()).into_events())
+    }
+
+    pub(crate) fn new_node(&mut self, data: T) -> NodePtr<T, A> {
+        self.stats.allocations += 1;
+        Node::leaf(data)
+    }
+
+    /// Raise `max_depth` to the length of the path walked since the
+    /// comparison count was `comparisons_before`, plus `extra` levels
+    fn reached_depth(&mut self, comparisons_before: u64, extra: usize) {
+        let depth = (self.stats.comparisons - comparisons_before) as usize + extra;
+        self.stats.max_depth = std::cmp::max(self.stats.max_depth, depth);
+    }
+
+    /// Rebalance `node`, whose parent is `parent` or which is the root if
+    /// that is `None`, relinking and snapshotting the tree after every
+    /// rotation. Returns every node a rotation moved, each pivot followed by
+    /// the node lifted over it, so the new subtree root comes last.
+    fn rebalance(&mut self, parent: Option<&NodePtr<T, A>>, node: NodePtr<T, A>) -> Vec<NodePtr<T, A>> {
+        let mut moved = Vec::new();
+        Node::rebalance_with(node.clone(), |step| match step {
+            Rebalancing::Case(case) => self.record_rebalance(case, &node),
+            Rebalancing::Rotated { direction, pivot, top } => {
+                self.trace.record(|| TraceEvent::Rotate { direction, pivot: pivot.borrow().data.clone() });
+                // The first rotation of a double case is already linked
+                // under `node`; the last one goes where `node` was
+                if Rc::ptr_eq(pivot, &node) {
+                    match parent {
+                        None => self.root = Some(top.clone()),
+                        Some(parent) => Node::replace_child(parent, &node, Some(top.clone())),
+                    }
+                }
+                let root = self.root.as_ref().unwrap();
+                self.trace.snapshot(|| TraceEvent::Snapshot { diagram: diagram::render(&Self::sketch(root), &DiagramOptions::new()) });
+                moved.extend([pivot.clone(), top.clone()]);
             }
-        } else if data > node_borrow.data {
-            if let Some(ref right) = node_borrow.right {
-                let new_right = Self::insert_rec(right, data);
-                node_borrow.right = Some(new_right);
-            } else {
-                node_borrow.right = Some(Node::new(data));
+        });
+        moved
+    }
+
+    /// Refresh heights and rebalance every node on `path`, a chain from the
+    /// root down, from the bottom up. `keep` is another chain from the root
+    /// that runs along `path` for a while; it is mended after each rotation
+    /// so that it still leads to the same node.
+    pub(crate) fn retrace(&mut self, path: &[NodePtr<T, A>], keep: &mut Vec<NodePtr<T, A>>) {
+        for (depth, node) in path.iter().enumerate().rev() {
+            Node::update_height(node);
+            let parent = depth.checked_sub(1).map(|parent| &path[parent]);
+            let moved = self.rebalance(parent, node.clone());
+            if !moved.is_empty() && keep.get(depth).is_some_and(|kept| Rc::ptr_eq(kept, node)) {
+                Self::mend(keep, depth, &moved);
             }
         }
-        drop(node_borrow);
+    }
 
-        Node::update_height(node);
+    /// Rewrite `keep` from `depth` down after a rebalance there moved the
+    /// nodes in `moved`. Only those few nodes changed parents, so the walk
+    /// down from the new subtree root rejoins the old chain within a few
+    /// levels, at the first node that did not move.
+    fn mend(keep: &mut Vec<NodePtr<T, A>>, depth: usize, moved: &[NodePtr<T, A>]) {
+        let old = keep.split_off(depth);
+        let target = old.last().unwrap().clone();
+        let mut current = moved.last().unwrap().clone();
+        while moved.iter().any(|node| Rc::ptr_eq(node, &current)) {
+            keep.push(current.clone());
+            if Rc::ptr_eq(&current, &target) {
+                return;
+            }
+            let next = if target.borrow().data < current.borrow().data {
+                current.borrow().left.clone()
+            } else {
+                current.borrow().right.clone()
+            };
+            current = next.unwrap();
+        }
+        let rejoin = old.iter().position(|node| Rc::ptr_eq(node, &current)).unwrap();
+        keep.extend_from_slice(&old[rejoin..]);
+    }
+
+    fn record_rebalance(&mut self, case: RebalanceCase, node: &NodePtr<T, A>) {
+        match case {
+            RebalanceCase::LeftLeft | RebalanceCase::RightRight => self.stats.single_rotations += 1,
+            RebalanceCase::LeftRight | RebalanceCase::RightLeft => self.stats.double_rotations += 1,
+        }
+        self.trace.record(|| TraceEvent::Rebalance { case, node: node.borrow().data.clone() });
+    }
 
-        Self::rebalance(node.clone())
+    /// Whether the tree holds `data`, which may be any borrowed form of the
+    /// key type, like `&str` for a `Tree<String>`
+    pub fn search<Q: ?Sized + Ord>(&self, data: &Q) -> bool
+    where
+        T: std::borrow::Borrow<Q>,
+    {
+        self.lookup(|node| data.cmp(node.borrow())).is_some()
     }
 
-    fn rebalance(node: NodePtr<T>) -> NodePtr<T> {
-        let balance = Node::balance_factor(&node);
-        // Left heavy subtree
-        if balance > 1 {
-            let left_balance = Node::balance_factor(&node.borrow().left.as_ref().unwrap());
-            if left_balance < 0 {
-                let left = node.borrow().left.clone().unwrap();
-                node.borrow_mut().left = Some(Self::rotate_left(left));
+    /// The node `probe` calls `Equal`, counted as a search. `probe` compares
+    /// the sought key with a node's.
+    pub(crate) fn lookup(&self, probe: impl Fn(&T) -> Ordering) -> Option<NodePtr<T, A>> {
+        let mut comparisons = 0;
+        let mut found = None;
+        let mut current = self.root.clone();
+        while let Some(node) = current {
+            let node_borrow = node.borrow();
+            comparisons += 1;
+            current = match probe(&node_borrow.data) {
+                Ordering::Less => node_borrow.left.clone(),
+                Ordering::Greater => node_borrow.right.clone(),
+                Ordering::Equal => {
+                    found = Some(node.clone());
+                    None
+                }
+            };
+        }
+        let mut searched = self.searched.get();
+        searched.searches += 1;
+        searched.comparisons += comparisons;
+        searched.max_depth = std::cmp::max(searched.max_depth, comparisons as usize);
+        self.searched.set(searched);
+        found
+    }
+
+    /// The path from the root down to the node `probe` calls `Equal`, or to
+    /// the node a new key would hang from, and what `probe` said about the
+    /// last node on it. The ordering is `None` only when the tree is empty.
+    pub(crate) fn descend(&mut self, probe: impl Fn(&T) -> Ordering) -> (Vec<NodePtr<T, A>>, Option<Ordering>) {
+        let comparisons = self.stats.comparisons;
+        let mut path = Vec::new();
+        let mut ordering = None;
+        let mut current = self.root.clone();
+        while let Some(node) = current {
+            let node_borrow = node.borrow();
+            self.stats.comparisons += 1;
+            let last = probe(&node_borrow.data);
+            self.trace.record(|| TraceEvent::Compare { node: node_borrow.data.clone(), ordering: last });
+            current = match last {
+                Ordering::Less => node_borrow.left.clone(),
+                Ordering::Greater => node_borrow.right.clone(),
+                Ordering::Equal => None,
+            };
+            drop(node_borrow);
+            path.push(node);
+            ordering = Some(last);
+        }
+        self.reached_depth(comparisons, 0);
+        (path, ordering)
+    }
+
+    /// Hang a new leaf holding `data` off the last node of `path`, on the
+    /// left if `as_left`, and rebalance back up the path once, mending
+    /// `keep` as `retrace` does
+    pub(crate) fn link_leaf(&mut self, path: &[NodePtr<T, A>], data: T, as_left: bool, keep: &mut Vec<NodePtr<T, A>>) {
+        self.stats.inserts += 1;
+        let leaf = Some(self.new_node(data));
+        match path.last() {
+            None => self.root = leaf,
+            Some(parent) if as_left => parent.borrow_mut().left = leaf,
+            Some(parent) => parent.borrow_mut().right = leaf,
+        }
+        self.retrace(path, keep);
+    }
+
+    /// Remove the last node of `path` and rebalance back up it. Returns the
+    /// removed data and the path, after rebalancing, to the node that now
+    /// holds the next key, which is empty if there is none.
+    pub(crate) fn unlink_last(&mut self, mut path: Vec<NodePtr<T, A>>) -> (T, Vec<NodePtr<T, A>>) {
+        self.stats.deletes += 1;
+        let node = path.last().unwrap().clone();
+        let removed = node.borrow().data.clone();
+        let (left, right) = (node.borrow().left.clone(), node.borrow().right.clone());
+        let mut next = match right.clone() {
+            // The successor's data moves up into `node`
+            Some(_) if left.is_some() => path.clone(),
+            // `right` takes `node`'s place, so the successor is its smallest
+            Some(right) => {
+                let mut next = path[..path.len() - 1].to_vec();
+                let mut current = Some(right);
+                while let Some(node) = current {
+                    current = node.borrow().left.clone();
+                    next.push(node);
+                }
+                next
             }
-            return Self::rotate_right(node);
-        }
-        // Right heavy subtree
-        else if balance < -1 {
-            let right_balance = Node::balance_factor(&node.borrow().right.as_ref().unwrap());
-            if right_balance > 0 {
-                let right = node.borrow().right.clone().unwrap();
-                node.borrow_mut().right = Some(Self::rotate_right(right));
+            // The successor is the nearest ancestor `node` lies left of
+            None => {
+                let above = path.windows(2).rposition(|pair| {
+                    pair[0].borrow().left.as_ref().is_some_and(|left| Rc::ptr_eq(left, &pair[1]))
+                });
+                above.map_or_else(Vec::new, |above| path[..=above].to_vec())
             }
-            return Self::rotate_left(node);
+        };
+        // A node with two children takes its successor's data, and the
+        // successor, which has no left child, is unlinked instead
+        if left.is_some() && right.is_some() {
+            let mut successor = node.borrow().right.clone();
+            while let Some(next) = successor {
+                successor = next.borrow().left.clone();
+                path.push(next);
+            }
+            let successor = path.last().unwrap().borrow().data.clone();
+            node.borrow_mut().data = successor;
+        }
+        let target = path.pop().unwrap();
+        let child = {
+            let target_borrow = target.borrow();
+            target_borrow.left.clone().or_else(|| target_borrow.right.clone())
+        };
+        match path.last() {
+            None => self.root = child,
+            Some(parent) => Node::replace_child(parent, &target, child),
         }
-        node
+        self.retrace(&path, &mut next);
+        (removed, next)
     }
 
-    fn rotate_left(node: NodePtr<T>) -> NodePtr<T> {
-        let node_right = node.borrow().right.as_ref().unwrap().clone();
-        let node_right_left = node_right.borrow().left.clone();
+    /// A read-only view of the root, from which the whole tree's shape can
+    /// be walked
+    pub fn view(&self) -> Option<NodeView<'_, T, A>> {
+        NodeView::of(&self.root)
+    }
 
-        node_right.borrow_mut().left = Some(node.clone());
-        node.borrow_mut().right = node_right_left;
+    /// The keys in order, cloned out of the tree
+    pub fn iter(&self) -> Iter<'_, T, A> {
+        Iter::new(self)
+    }
 
-        Node::update_height(&node);
-        Node::update_height(&node_right);
+    /// A read-only cursor on the smallest key
+    pub fn cursor(&self) -> Cursor<'_, T, A> {
+        Cursor::new(self)
+    }
 
-        node_right
+    /// A cursor on the smallest key that can also insert and remove keys
+    pub fn cursor_mut(&mut self) -> CursorMut<'_, T, A> {
+        CursorMut::new(self)
     }
 
-    fn rotate_right(node: NodePtr<T>) -> NodePtr<T> {
-        let node_left = node.borrow().left.as_ref().unwrap().clone();
-        let node_left_right = node_left.borrow().right.clone();
+    /// Combine the summaries of every key in `range`, in ascending order,
+    /// visiting O(log n) nodes
+    pub fn aggregate<R: RangeBounds<T>>(&self, range: R) -> A::Value {
+        augment::aggregate(&self.root, range.start_bound(), range.end_bound())
+    }
 
-        node_left.borrow_mut().right = Some(node.clone());
-        node.borrow_mut().left = node_left_right;
+    /// Returns false if `data` was not in the tree
+    pub fn delete(&mut self, data: T) -> bool {
+        let (path, ordering) = self.descend(|node| data.cmp(node));
+        if ordering != Some(Ordering::Equal) {
+            return false;
+        }
+        self.unlink_last(path);
+        true
+    }
 
-        Node::update_height(&node);
-        Node::update_height(&node_left);
+    /// Like `delete`, but returns every comparison, rebalance case and
+    /// rotation the delete performed, in order
+    pub fn delete_traced(&mut self, data: T) -> Vec<TraceEvent<T>> {
+        self.trace = Recorder::enabled();
+        self.delete(data);
+        std::mem::replace(&mut self.trace, Recorder::disabled()).into_events()
+    }
 
-        node_left
+    /// Like `delete_traced`, but also snapshots the whole tree after every
+    /// rotation. Returns whether the key was removed.
+    pub fn delete_explained(&mut self, data: T) -> (bool, Vec<TraceEvent<T>>) {
+        self.trace = Recorder::with_snapshots();
+        let removed = self.delete(data);
+        (removed, std::mem::replace(&mut self.trace, Recorder::disabled()).into_events())
     }
 
-    pub fn search(&mut self, data: T) -> bool{
-        match *self {
-            Tree::Empty => false,
-            Tree::Root(ref root) => {
-                Self::search_rec(&root, &data)
-            },
+    ///Return the number of leaves in the tree
+    pub fn leaves(&self) -> usize {
+        match self.root {
+            None => 0,
+            Some(ref root) => Self::leaves_rec(root),
         }
     }
 
-    fn search_rec(node: &NodePtr<T>, data: &T) -> bool {
+    fn leaves_rec(node: &NodePtr<T, A>) -> usize {
         let node_borrow = node.borrow();
-        if data < &node_borrow.data {
-            if let Some(ref left) = node_borrow.left {
-                return Self::search_rec(left, data);
-            }
-        } else if data > &node_borrow.data {
-            if let Some(ref right) = node_borrow.right {
-                return Self::search_rec(right, data);
-            }
+        if node_borrow.left.is_none() && node_borrow.right.is_none() {
+            1
         } else {
-            return true;
+            //If node has children, return sum of recursive call of 'leaves' on children
+            node_borrow.left.as_ref().map_or(0, Self::leaves_rec) + node_borrow.right.as_ref().map_or(0, Self::leaves_rec)
         }
-        false 
     }
-    
 
-    pub fn delete(&mut self, data: T) {
-        *self = match *self {
-            Tree::Empty => Tree::Empty,
-            Tree::Root(ref root) => {
-                match Self::delete_rec(&root, &data) {
-                    Some(node) => Tree::Root(node),
-                    None => Tree::Empty,
-                }
-            }
-        };
+    pub fn is_empty(&self) -> bool{
+        self.root.is_none()
     }
 
-    fn delete_rec(node: &NodePtr<T>, data: &T) -> Option<NodePtr<T>> {
-        if data < &node.borrow().data {
-            if let Some(ref left) = node.borrow().left {
-                node.borrow_mut().left = Self::delete_rec(left, data);
-            }
-        } else if data > &node.borrow().data {
-            if let Some(ref right) = node.borrow().right {
-                node.borrow_mut().right = Self::delete_rec(right, data);
-            }
-        } else {
-            // Node has no children
-            if node.borrow().left.is_none() && node.borrow().right.is_none() {
-                return None;
-            }
-            // Node has only one child
-            else if node.borrow().left.is_none() {
-                return node.borrow().right.clone();
-            } else if node.borrow().right.is_none() {
-                return node.borrow().left.clone();
-            }
-            // Node has two children
-            else {
+    /// Check that keys are in order, stored heights are correct and every
+    /// node is balanced, describing the first problem found
+    pub fn validate(&self) -> Result<(), String> {
+        Self::validate_rec(&self.root, None, None).map(|_| ())
+    }
 
-                let successor = Self::min_value_node(node.borrow().right.as_ref().unwrap().clone());
-                let successor_data = successor.borrow().data.clone();
-                node.borrow_mut().right = Self::delete_rec(&successor, &successor_data);
-                node.borrow_mut().data = successor_data;
-            }
+    /// Returns the height of a valid subtree whose keys must lie strictly
+    /// between `low` and `high`
+    fn validate_rec(node: &Option<NodePtr<T, A>>, low: Option<&T>, high: Option<&T>) -> Result<i32, String> {
+        let node = match node {
+            None => return Ok(0),
+            Some(node) => node.borrow(),
+        };
+        if low.is_some_and(|low| node.data <= *low) || high.is_some_and(|high| node.data >= *high) {
+            return Err(format!("{:?} is out of order", node.data));
+        }
+        let left = Self::validate_rec(&node.left, low, Some(&node.data))?;
+        let right = Self::validate_rec(&node.right, Some(&node.data), high)?;
+        if (left - right).abs() > 1 {
+            return Err(format!("{:?} is unbalanced: left height {}, right height {}", node.data, left, right));
+        }
+        let height = 1 + std::cmp::max(left, right);
+        if node.height != height {
+            return Err(format!("{:?} stores height {} but has height {}", node.data, node.height, height));
         }
-        Node::update_height(node);
-        Some(Self::rebalance(node.clone()))
+        Ok(height)
     }
-    
 
-    fn min_value_node(node: NodePtr<T>) -> NodePtr<T> {
-        let mut current = node;
-        loop {
-            let next = { current.borrow().left.clone() };
-            match next {
-                Some(left) => current = left,
-                None => break,
-            }
+    /// Write the keys in ascending order, one per line, to `out`
+    pub fn write_in_order_traversal(&self, out: &mut dyn io::Write) -> io::Result<()> {
+        let mut rendered = String::new();
+        if let Some(ref root) = self.root {
+            Self::fmt_in_order(root, &mut rendered).expect("writing to a String cannot fail");
         }
-        current
+        out.write_all(rendered.as_bytes())
     }
 
-    ///Return the number of leaves in the tree
-    pub fn leaves(&self) -> usize {
-        match *self {
-            Tree::Empty => 0,
-            Tree::Root(ref root) => {
-                if root.borrow().left.is_none() && root.borrow().right.is_none() {
-                    1
-                } else {
-                    //If node has children, return sum of recursive call of 'leaves' on children
-
-                    let mut sum = 0;
+    pub fn print_in_order_traversal(&self) {
+        self.write_in_order_traversal(&mut io::stdout()).unwrap();
+    }
 
-                    //Check if both left and right children are Some
-                    if root.borrow().left.is_some() && root.borrow().right.is_some() {
-                        sum += Tree::Root(root.borrow().left.clone().unwrap()).leaves() + Tree::Root(root.borrow().right.clone().unwrap()).leaves();
-                    }
+    fn fmt_in_order(node: &NodePtr<T, A>, f: &mut dyn fmt::Write) -> fmt::Result {
+        if let Some(left) = &node.borrow().left {
+            Self::fmt_in_order(left, f)?;
+        }
+        writeln!(f, "{:?}", node.borrow().data)?;
+        if let Some(right) = &node.borrow().right {
+            Self::fmt_in_order(right, f)?;
+        }
+        Ok(())
+    }
 
-                    //Check if only left child is Some
-                    if root.borrow().left.is_some() && root.borrow().right.is_none() {
-                        sum += Tree::Root(root.borrow().left.clone().unwrap()).leaves();
-                    }
+    /// Write the sideways tree diagram (the same one `Display` produces) to `out`
+    pub fn write_tree(&self, out: &mut dyn io::Write) -> io::Result<()> {
+        write!(out, "{}", self)
+    }
 
-                    //Check if only right child is Some
-                    if root.borrow().left.is_none() && root.borrow().right.is_some() {
-                        sum += Tree::Root(root.borrow().right.clone().unwrap()).leaves();
-                    }
+    pub fn print_tree(&self, depth: usize, is_right: bool) {
+        let mut rendered = String::new();
+        if let Some(ref root) = self.root {
+            Self::fmt_subtree(root, &mut rendered, depth, is_right).expect("writing to a String cannot fail");
+        }
+        print!("{}", rendered);
+    }
 
-                    return sum;
-                }
+    fn fmt_subtree(node: &NodePtr<T, A>, f: &mut dyn fmt::Write, depth: usize, is_right: bool) -> fmt::Result {
+        for _ in 0..depth {
+            f.write_str("    ")?;
+        }
+        if depth > 0 {
+            if is_right {
+                f.write_str("└─R─")?;
+            } else {
+                f.write_str("├─L─")?;
             }
         }
+        writeln!(f, "{:?}", node.borrow().data)?;
+        if let Some(left) = &node.borrow().left {
+            Self::fmt_subtree(left, f, depth + 1, false)?;
+        }
+        if let Some(right) = &node.borrow().right {
+            Self::fmt_subtree(right, f, depth + 1, true)?;
+        }
+        Ok(())
     }
 
-    pub fn is_empty(&self) -> bool{
-        match *self {
-            Tree::Empty => true,
-            Tree::Root(_) => false,
+    /// Render the tree top-down, with each key centered over its children
+    pub fn diagram(&self, options: &DiagramOptions) -> String {
+        match self.root {
+            None => String::new(),
+            Some(ref root) => diagram::render(&Self::sketch(root), options),
         }
     }
 
-    pub fn print_in_order_traversal(&self) { 
-        match *self {
-            Tree::Empty => (),
-            Tree::Root(ref root) => {
-                if let Some(left) = &root.borrow().left {
-                    Tree::Root(left.clone()).print_in_order_traversal();
-                }
-                println!("{:?}", root.borrow().data);
-                if let Some(right) = &root.borrow().right {
-                    Tree::Root(right.clone()).print_in_order_traversal();
-                }
-            }
-        }
+    pub fn write_diagram(&self, out: &mut dyn io::Write, options: &DiagramOptions) -> io::Result<()> {
+        out.write_all(self.diagram(options).as_bytes())
     }
 
-    pub fn print_tree(&self, depth: usize, is_right: bool) {
-        match *self {
-            Tree::Empty => (),
-            Tree::Root(ref root) => {
-                for _ in 0..depth {
-                    print!("    ");
-                }
-                if(depth > 0){
-                    //TODO print the spaces for the number of characters of the parent node -1, to even the print 
-                    if is_right {
-                        print!("└─R─");
-                    } else {
-                        print!("├─L─");
-                    }
-                }
-                println!("{:?}", root.borrow().data);
-                if let Some(left) = &root.borrow().left {
-                    Tree::Root(left.clone()).print_tree(depth+1, false);
-                }
-                if let Some(right) = &root.borrow().right {
-                    Tree::Root(right.clone()).print_tree(depth+1, true);
-                }
-            }
+    fn sketch(node: &NodePtr<T, A>) -> Sketch {
+        let node_borrow = node.borrow();
+        Sketch {
+            label: Label::plain(format!("{:?}", node_borrow.data)),
+            left: node_borrow.left.as_ref().map(|left| Box::new(Self::sketch(left))),
+            right: node_borrow.right.as_ref().map(|right| Box::new(Self::sketch(right))),
         }
     }
 
     pub fn get_height(&self, count: usize) -> usize {
-        match *self {
-            Tree::Empty => 0,
-            Tree::Root(ref root ) => {
-                let mut left_height = 0;
-                let mut right_height = 0;
-                if let Some(left) = &root.borrow().left {
-                    left_height += Tree::Root(left.clone()).get_height(count + 1);
-                }
-                if let Some(right) = &root.borrow().right {
-                    right_height += Tree::Root(right.clone()).get_height(count +1);
-                }
-                std::cmp::max(left_height, right_height) + 1
-            }
+        match self.root {
+            None => 0,
+            Some(ref root) => Self::get_height_rec(root, count),
+        }
+    }
+
+    #[allow(clippy::only_used_in_recursion)]
+    fn get_height_rec(node: &NodePtr<T, A>, count: usize) -> usize {
+        let mut left_height = 0;
+        let mut right_height = 0;
+        if let Some(left) = &node.borrow().left {
+            left_height += Self::get_height_rec(left, count + 1);
+        }
+        if let Some(right) = &node.borrow().right {
+            right_height += Self::get_height_rec(right, count + 1);
         }
+        std::cmp::max(left_height, right_height) + 1
     }
 }
 
 
+impl<T: Clone + Ord + Debug, A: Monoid<T>> Default for Tree<T, A> {
+    fn default() -> Self {
+        Tree::augmented()
+    }
+}
+
+/// A copy with the same shape that shares no nodes with the original and
+/// starts counting its stats from zero
+impl<T: Clone + Ord + Debug, A: Monoid<T>> Clone for Tree<T, A> {
+    fn clone(&self) -> Self {
+        Tree {
+            root: Node::deep_clone(&self.root),
+            trace: Recorder::disabled(),
+            stats: TreeStats::default(),
+            searched: Cell::default(),
+        }
+    }
+}
+
+/// Trees are equal when they hold the same keys, whatever their shape
+impl<T: Clone + Ord + Debug, A: Monoid<T>> PartialEq for Tree<T, A> {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Clone + Ord + Debug, A: Monoid<T>> Eq for Tree<T, A> {}
+
+impl<T: Clone + Ord + Debug, A: Monoid<T>> PartialOrd for Tree<T, A> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Compares the keys in order, like `BTreeSet`
+impl<T: Clone + Ord + Debug, A: Monoid<T>> Ord for Tree<T, A> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+impl<T: Clone + Ord + Debug + Hash, A: Monoid<T>> Hash for Tree<T, A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut len = 0usize;
+        for key in self.iter() {
+            key.hash(state);
+            len += 1;
+        }
+        len.hash(state);
+    }
+}
+
+impl<T: Clone + Ord + Debug, A: Monoid<T>> Extend<T> for Tree<T, A> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for key in iter {
+            self.insert(key);
+        }
+    }
+}
+
+impl<T: Clone + Ord + Debug, A: Monoid<T>> FromIterator<T> for Tree<T, A> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut tree = Tree::augmented();
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl<'a, T: Clone + Ord + Debug, A: Monoid<T>> IntoIterator for &'a Tree<T, A> {
+    type Item = T;
+    type IntoIter = Iter<'a, T, A>;
+
+    fn into_iter(self) -> Iter<'a, T, A> {
+        self.iter()
+    }
+}
+
+impl<T: Clone + Ord + Debug, A: Monoid<T>> fmt::Display for Tree<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.root {
+            None => Ok(()),
+            Some(ref root) => Self::fmt_subtree(root, f, 0, true),
+        }
+    }
+}
+
 //HOW IS root.clone() DIFFERENT THAN root.borrow().left().clone().unwrap()? 
     //root.clone() ->  cloning an RC returns the value wrapped in the RC. 
     //root.borrow() -> immutably borrows the value in the RefCell 
@@ -288,12 +590,16 @@ impl<T: Clone + Ord + Debug> Tree<T> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::ops::Bound;
+    use crate::trace::Direction;
 
     #[test]
     fn test_insert_into_empty_tree() {
         let mut tree = Tree::new();
         tree.insert(10);
-        if let Tree::Root(node) = tree {
+        if let Some(node) = tree.root {
             assert_eq!(node.borrow().data, 10);
         } else {
             panic!("Tree was expected to have a root");
@@ -305,7 +611,7 @@ mod tests {
         let mut tree = Tree::new();
         tree.insert(10);
         tree.insert(5);
-        if let Tree::Root(node) = &tree {
+        if let Some(node) = &tree.root {
             if let Some(left_child) = &node.borrow().left {
                 assert_eq!(left_child.borrow().data, 5);
             } else {
@@ -321,7 +627,7 @@ mod tests {
         let mut tree = Tree::new();
         tree.insert(10);
         tree.insert(15);
-        if let Tree::Root(node) = &tree {
+        if let Some(node) = &tree.root {
             if let Some(right_child) = &node.borrow().right {
                 assert_eq!(right_child.borrow().data, 15);
             } else {
@@ -337,7 +643,7 @@ mod tests {
         let mut tree = Tree::new();
         tree.insert(10);
         tree.insert(10);
-        if let Tree::Root(node) = &tree {
+        if let Some(node) = &tree.root {
             assert!(
                 node.borrow().left.is_none(),
                 "Left child should not exist for a duplicate value"
@@ -362,7 +668,7 @@ mod tests {
         tree.insert(12);
         tree.insert(17);
 
-        if let Tree::Root(node) = &tree {
+        if let Some(node) = &tree.root {
             assert_eq!(node.borrow().data, 10);
             if let Some(left_child) = &node.borrow().left {
                 assert_eq!(left_child.borrow().data, 5);
@@ -415,14 +721,63 @@ mod tests {
     #[test]
     fn test_is_tree_empty(){
         let mut tree = Tree::new();
-        assert_eq!(tree.is_empty(), true);
+        assert!(tree.is_empty());
         tree.insert(2);
-        assert_eq!(tree.is_empty(), false);
+        assert!(!tree.is_empty());
         tree.delete(2);
         // println!("{:#?}", tree);
-        assert_eq!(tree.is_empty(), true);
+        assert!(tree.is_empty());
     }
     
+    #[test]
+    fn test_insert_and_delete_report_changes() {
+        let mut tree = Tree::new();
+        assert!(tree.insert(5));
+        assert!(tree.insert(3));
+        assert!(!tree.insert(5));
+        assert!(tree.delete(5));
+        assert!(!tree.delete(5));
+        assert!(!tree.delete(8));
+        assert!(tree.search(&3));
+    }
+
+    #[test]
+    fn test_search_by_borrowed_key() {
+        let mut tree = Tree::new();
+        for word in ["pear", "apple", "fig"] {
+            tree.insert(word.to_string());
+        }
+        let shared = &tree;
+        assert!(shared.search("fig"));
+        assert!(!shared.search("kiwi"));
+        assert_eq!(shared.stats().searches, 2);
+    }
+
+    #[test]
+    fn test_stats_count_operations() {
+        let mut tree = Tree::new();
+        // 30 -> 20 -> 10 is a left-left case, 25 then forces a left-right case
+        for key in [30, 20, 10, 5, 25, 27] {
+            tree.insert(key);
+        }
+        // Neither a duplicate insert nor a missing delete changes the tree
+        tree.insert(27);
+        tree.delete(40);
+        let stats = tree.stats();
+        assert_eq!((stats.inserts, stats.deletes), (6, 0));
+        assert_eq!(stats.allocations, 6);
+        assert_eq!(stats.single_rotations, 1);
+        assert_eq!(stats.double_rotations, 1);
+        assert_eq!(stats.rotations(), 3);
+        assert_eq!(stats.recolors, 0);
+        assert_eq!(stats.max_depth, 4);
+
+        tree.reset_stats();
+        // The left-right rotation lifted 27 to the second level
+        assert!(tree.search(&27));
+        assert_eq!(tree.stats(), TreeStats { searches: 1, comparisons: 2, max_depth: 2, ..TreeStats::default() });
+    }
+
     #[test]
     fn test_in_order_print(){
         let mut tree = Tree::new();
@@ -436,6 +791,10 @@ mod tests {
         tree.insert(4);
         tree.insert(16);
         tree.print_in_order_traversal();
+
+        let mut out = Vec::new();
+        tree.write_in_order_traversal(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "3\n4\n5\n7\n10\n12\n15\n16\n17\n");
     }
 
     #[test]
@@ -448,6 +807,220 @@ mod tests {
         tree.insert(80);
         tree.insert(60);
         tree.print_tree(0, true);
+
+        let expected = "50\n    ├─L─40\n        ├─L─30\n    └─R─70\n        ├─L─60\n        └─R─80\n";
+        assert_eq!(tree.to_string(), expected);
+        let mut out = Vec::new();
+        tree.write_tree(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_diagram(){
+        let mut tree = Tree::new();
+        assert_eq!(tree.diagram(&DiagramOptions::new()), "");
+        for data in [50, 40, 30, 70, 80, 60, 100] {
+            tree.insert(data);
+        }
+        let expected = "    _50_\n   /    \\\n  40   70\n /    /  \\\n30   60 80\n          \\\n         100\n";
+        assert_eq!(tree.diagram(&DiagramOptions::new()), expected);
+
+        let mut out = Vec::new();
+        tree.write_diagram(&mut out, &DiagramOptions::new().max_width(8)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "  50_\n /   \\\n... ...\n");
+    }
+
+    #[test]
+    fn test_display_empty_tree(){
+        let tree: Tree<i32> = Tree::new();
+        assert_eq!(tree.to_string(), "");
+    }
+
+    /// Check ordering, stored heights and balance; returns the subtree height
+    fn check_subtree(node: &Option<NodePtr<u32>>, low: Option<u32>, high: Option<u32>) -> i32 {
+        match node {
+            None => 0,
+            Some(node) => {
+                let node_borrow = node.borrow();
+                let data = node_borrow.data;
+                assert!(low.is_none_or(|low| data > low), "{} is out of order", data);
+                assert!(high.is_none_or(|high| data < high), "{} is out of order", data);
+                let left = check_subtree(&node_borrow.left, low, Some(data));
+                let right = check_subtree(&node_borrow.right, Some(data), high);
+                assert!((left - right).abs() <= 1, "{} is unbalanced", data);
+                assert_eq!(node_borrow.height, 1 + std::cmp::max(left, right));
+                node_borrow.height
+            }
+        }
+    }
+
+    fn assert_valid(tree: &Tree<u32>) -> Vec<u32> {
+        check_subtree(&tree.root, None, None);
+        tree.validate().unwrap();
+        let mut out = Vec::new();
+        tree.write_in_order_traversal(&mut out).unwrap();
+        String::from_utf8(out).unwrap().lines().map(|line| line.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_validate_reports_broken_trees() {
+        let mut tree = Tree::new();
+        for key in [20, 10, 30] {
+            tree.insert(key);
+        }
+        assert_eq!(tree.validate(), Ok(()));
+
+        let root = tree.root.clone().unwrap();
+        root.borrow_mut().height = 5;
+        assert_eq!(tree.validate(), Err("20 stores height 5 but has height 2".to_string()));
+        root.borrow_mut().height = 2;
+
+        root.borrow().left.as_ref().unwrap().borrow_mut().data = 25;
+        assert_eq!(tree.validate(), Err("25 is out of order".to_string()));
+    }
+
+    #[test]
+    fn test_random_inserts_and_deletes_keep_invariants(){
+        let mut tree = Tree::new();
+        let mut expected = std::collections::BTreeSet::new();
+        let mut rng = StdRng::seed_from_u64(421);
+        for step in 0..2000 {
+            let data: u32 = rng.gen_range(0..200);
+            if step % 3 == 2 {
+                tree.delete(data);
+                expected.remove(&data);
+            } else {
+                tree.insert(data);
+                expected.insert(data);
+            }
+            assert_eq!(assert_valid(&tree), expected.iter().copied().collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_aggregate_matches_brute_force(){
+        use crate::augment::{Count, Max, Min, Sum};
+        let mut sums: Tree<u64, Sum> = Tree::augmented();
+        let mut counts: Tree<u64, Count> = Tree::augmented();
+        let mut mins: Tree<u64, Min> = Tree::augmented();
+        let mut maxes: Tree<u64, Max> = Tree::default();
+        let mut expected = std::collections::BTreeSet::new();
+        let mut rng = StdRng::seed_from_u64(421);
+        for step in 0..1000 {
+            let data: u64 = rng.gen_range(0..200);
+            if step % 3 == 2 {
+                let removed = expected.remove(&data);
+                for changed in [sums.delete(data), counts.delete(data), mins.delete(data), maxes.delete(data)] {
+                    assert_eq!(changed, removed);
+                }
+            } else {
+                sums.insert(data);
+                counts.insert(data);
+                mins.insert(data);
+                maxes.insert(data);
+                expected.insert(data);
+            }
+            let (low, high) = (data.saturating_sub(40), data + 25);
+            let inside: Vec<u64> = expected.range(low..high).copied().collect();
+            assert_eq!(sums.aggregate(low..high), inside.iter().sum::<u64>());
+            assert_eq!(counts.aggregate(low..high), inside.len());
+            assert_eq!(mins.aggregate(low..high), inside.first().copied());
+            assert_eq!(maxes.aggregate(low..high), inside.last().copied());
+        }
+        assert_eq!(sums.aggregate(..), expected.iter().sum::<u64>());
+        assert_eq!(counts.aggregate(50..=50), usize::from(expected.contains(&50)));
+        assert_eq!(counts.aggregate((Bound::Excluded(10), Bound::Included(20))), expected.range(11..=20).count());
+        assert_eq!(mins.aggregate(300..), None);
+        assert_eq!(Tree::<u64, Sum>::augmented().aggregate(..), 0);
+    }
+
+    #[test]
+    fn test_delete_node_with_two_children(){
+        let mut tree = Tree::new();
+        for data in [50, 30, 70, 20, 40, 60, 80, 65] {
+            tree.insert(data);
+        }
+        // The successor of 50 is 60, two levels below its right child
+        tree.delete(50);
+        assert_eq!(assert_valid(&tree), vec![20, 30, 40, 60, 65, 70, 80]);
+        tree.delete(20);
+        assert_eq!(assert_valid(&tree), vec![30, 40, 60, 65, 70, 80]);
+    }
+
+    #[test]
+    fn test_insert_trace_single_rotation(){
+        let mut tree = Tree::new();
+        tree.insert(30);
+        tree.insert(20);
+        assert_eq!(
+            tree.insert_traced(10),
+            vec![
+                TraceEvent::Compare { node: 30, ordering: Ordering::Less },
+                TraceEvent::Compare { node: 20, ordering: Ordering::Less },
+                TraceEvent::Rebalance { case: RebalanceCase::LeftLeft, node: 30 },
+                TraceEvent::Rotate { direction: Direction::Right, pivot: 30 },
+            ]
+        );
+        // Tracing is per call
+        assert_eq!(tree.insert_traced(20), vec![TraceEvent::Compare { node: 20, ordering: Ordering::Equal }]);
+    }
+
+    #[test]
+    fn test_insert_trace_double_rotation(){
+        let mut tree = Tree::new();
+        tree.insert(10);
+        tree.insert(30);
+        assert_eq!(
+            &tree.insert_traced(20)[2..],
+            &[
+                TraceEvent::Rebalance { case: RebalanceCase::RightLeft, node: 10 },
+                TraceEvent::Rotate { direction: Direction::Right, pivot: 30 },
+                TraceEvent::Rotate { direction: Direction::Left, pivot: 10 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_insert_explained_snapshots_each_rotation(){
+        let mut tree = Tree::new();
+        tree.insert(10);
+        tree.insert(30);
+        let (inserted, events) = tree.insert_explained(20);
+        assert!(inserted);
+        assert_eq!(
+            &events[2..],
+            &[
+                TraceEvent::Rebalance { case: RebalanceCase::RightLeft, node: 10 },
+                TraceEvent::Rotate { direction: Direction::Right, pivot: 30 },
+                // The whole tree, halfway through the double rotation
+                TraceEvent::Snapshot { diagram: "10\n  \\\n 20\n   \\\n  30\n".to_string() },
+                TraceEvent::Rotate { direction: Direction::Left, pivot: 10 },
+                TraceEvent::Snapshot { diagram: "  20\n /  \\\n10 30\n".to_string() },
+            ]
+        );
+        assert_eq!(tree.delete_explained(99), (false, vec![
+            TraceEvent::Compare { node: 20, ordering: Ordering::Greater },
+            TraceEvent::Compare { node: 30, ordering: Ordering::Greater },
+        ]));
+    }
+
+    #[test]
+    fn test_delete_trace(){
+        let mut tree = Tree::new();
+        for data in [20, 10, 30, 25] {
+            tree.insert(data);
+        }
+        assert_eq!(
+            tree.delete_traced(10),
+            vec![
+                TraceEvent::Compare { node: 20, ordering: Ordering::Less },
+                TraceEvent::Compare { node: 10, ordering: Ordering::Equal },
+                TraceEvent::Rebalance { case: RebalanceCase::RightLeft, node: 20 },
+                TraceEvent::Rotate { direction: Direction::Right, pivot: 30 },
+                TraceEvent::Rotate { direction: Direction::Left, pivot: 20 },
+            ]
+        );
+        assert_eq!(assert_valid(&tree), vec![20, 25, 30]);
     }
 
     #[test]
@@ -463,4 +1036,35 @@ mod tests {
         tree.insert(80);
         assert_eq!(tree.get_height(0), 3 );
     }
+
+    #[test]
+    fn test_clone_is_deep_and_equality_ignores_shape() {
+        use std::collections::hash_map::DefaultHasher;
+        fn hash_of(tree: &Tree<i32>) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            tree.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let ascending: Tree<i32> = (1..=7).collect();
+        let mut shuffled = Tree::new();
+        shuffled.extend([5, 2, 7, 1, 4, 6, 3]);
+        // Different shapes, same keys
+        assert_ne!(ascending.to_string(), shuffled.to_string());
+        assert_eq!(ascending, shuffled);
+        assert_eq!(hash_of(&ascending), hash_of(&shuffled));
+
+        let mut copy = ascending.clone();
+        assert_eq!(copy.to_string(), ascending.to_string());
+        // The original's inserts are not the copy's
+        assert_eq!(copy.stats(), TreeStats::default());
+        copy.delete(4);
+        copy.insert(8);
+        copy.validate().unwrap();
+        ascending.validate().unwrap();
+        assert_eq!(ascending.iter().collect::<Vec<_>>(), vec![1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(copy.iter().collect::<Vec<_>>(), vec![1, 2, 3, 5, 6, 7, 8]);
+        assert!(ascending < copy);
+        assert!(Tree::<i32>::new() < ascending);
+    }
 }
@@ -0,0 +1,77 @@
+//! A read-only view of an AVL tree's nodes.
+//!
+//! Nodes live in `Rc<RefCell<..>>`s that the tree owns, so a plain `&T`
+//! cannot outlive the borrow of the node holding it. A view keeps its node
+//! alive instead, borrows the tree it came from so the tree cannot change
+//! underneath it, and hands out short `Ref` guards on request. Nothing here
+//! can modify a node.
+
+use std::cell::Ref;
+use std::fmt::{self, Debug};
+use std::marker::PhantomData;
+
+use crate::augment::Monoid;
+use crate::node::{Node, NodePtr};
+
+/// One node of the tree, for code that needs to see its shape: heights,
+/// children and summaries
+pub struct NodeView<'a, T: Clone + Debug, A: Monoid<T> = ()> {
+    node: NodePtr<T, A>,
+    tree: PhantomData<&'a Node<T, A>>,
+}
+
+impl<'a, T: Clone + Debug, A: Monoid<T>> NodeView<'a, T, A> {
+    pub(crate) fn of(node: &Option<NodePtr<T, A>>) -> Option<Self> {
+        node.as_ref().map(|node| NodeView { node: node.clone(), tree: PhantomData })
+    }
+
+    pub fn key(&self) -> Ref<'_, T> {
+        Ref::map(self.node.borrow(), |node| &node.data)
+    }
+
+    /// The height of this node's subtree, 1 for a leaf
+    pub fn height(&self) -> i32 {
+        self.node.borrow().height
+    }
+
+    /// `A`'s summary of this node's subtree
+    pub fn summary(&self) -> Ref<'_, A::Value> {
+        Ref::map(self.node.borrow(), |node| &node.summary)
+    }
+
+    pub fn left(&self) -> Option<NodeView<'a, T, A>> {
+        NodeView::of(&self.node.borrow().left)
+    }
+
+    pub fn right(&self) -> Option<NodeView<'a, T, A>> {
+        NodeView::of(&self.node.borrow().right)
+    }
+}
+
+impl<T: Clone + Debug, A: Monoid<T>> Debug for NodeView<'_, T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NodeView").field("key", &*self.key()).field("height", &self.height()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::augment::Sum;
+    use crate::tree::Tree;
+
+    #[test]
+    fn test_views_show_the_shape() {
+        let mut tree: Tree<u32, Sum> = Tree::augmented();
+        for key in [10, 20, 30, 40] {
+            tree.insert(key);
+        }
+        let root = tree.view().unwrap();
+        assert_eq!((*root.key(), root.height(), *root.summary()), (20, 3, 100));
+        let right = root.right().unwrap();
+        assert_eq!((*right.key(), right.height(), *right.summary()), (30, 2, 70));
+        assert!(right.left().is_none());
+        assert_eq!(*right.right().unwrap().key(), 40);
+        assert_eq!(*root.left().unwrap().key(), 10);
+        assert!(Tree::<i32>::new().view().is_none());
+    }
+}
@@ -1,17 +1,22 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
 use red_black_trees::rbt;
 
 fn criterion_benchmark(c: &mut Criterion) {
     for &size in &[10000, 40000, 70000, 100000, 130000] {
         let mut tree: rbt::RedBlackTreeSet<i64> = rbt::RedBlackTreeSet::new();
         let values: Vec<i64> = (0..size).collect();
+        // A fresh tree per batch, so every insert is of a new key
         c.bench_function(&format!("insert_{}", size), |b| {
-            b.iter(|| {
-                tree = rbt::RedBlackTreeSet::new();
-                for &value in &values {
-                    tree.insert(black_box(value));
-                }
-            });
+            b.iter_batched(
+                rbt::RedBlackTreeSet::new,
+                |mut tree| {
+                    for &value in &values {
+                        tree.insert(black_box(value));
+                    }
+                    tree
+                },
+                BatchSize::LargeInput,
+            );
         });
 
         for &value in &values {
@@ -21,7 +26,7 @@ fn criterion_benchmark(c: &mut Criterion) {
         c.bench_function(&format!("search_{}", size), |b| {
             b.iter(|| {
                 for &value in &values[..(size/10).try_into().unwrap()] {
-                    tree.find(black_box(value));
+                    tree.find(black_box(&value));
                 }
             });
         });
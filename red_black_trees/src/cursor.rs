@@ -0,0 +1,254 @@
+//! Cursors that walk a red-black tree in key order, and that can insert and
+//! remove keys where they stand without searching again.
+//!
+//! A cursor holds a single node and moves by following child links down and
+//! the nodes' `parent` links back up. `None` is the "ghost" position, which
+//! sits past the largest key and before the smallest: moving forward from it
+//! reaches the first key and moving back reaches the last.
+
+use std::fmt::{Debug, Display};
+use std::rc::{Rc, Weak};
+
+use crate::augment::Monoid;
+use crate::rbt::{RedBlackTree, RedBlackTreeSet};
+
+pub struct Cursor<'a, T: Ord + Display + Debug + Clone, A: Monoid<T> = ()> {
+    tree: &'a RedBlackTreeSet<T, A>,
+    node: RedBlackTree<T, A>,
+}
+
+impl<'a, T: Ord + Display + Debug + Clone, A: Monoid<T>> Cursor<'a, T, A> {
+    pub(crate) fn new(tree: &'a RedBlackTreeSet<T, A>) -> Self {
+        Cursor { node: extreme(tree.root.clone(), true), tree }
+    }
+
+    /// The key under the cursor, or `None` at the ghost position
+    pub fn key(&self) -> Option<T> {
+        key_of(&self.node)
+    }
+
+    /// Move to `key` if present, otherwise to the smallest key above it, or
+    /// to the ghost position if there is none. Returns whether `key` was found.
+    pub fn seek(&mut self, key: &T) -> bool {
+        let found;
+        (self.node, found) = seek(&self.tree.root, key);
+        found
+    }
+
+    pub fn move_next(&mut self) {
+        self.node = step(&self.tree.root, &self.node, true);
+    }
+
+    pub fn move_prev(&mut self) {
+        self.node = step(&self.tree.root, &self.node, false);
+    }
+
+    pub fn peek_next(&self) -> Option<T> {
+        key_of(&step(&self.tree.root, &self.node, true))
+    }
+
+    pub fn peek_prev(&self) -> Option<T> {
+        key_of(&step(&self.tree.root, &self.node, false))
+    }
+}
+
+pub struct CursorMut<'a, T: Ord + Display + Debug + Clone, A: Monoid<T> = ()> {
+    tree: &'a mut RedBlackTreeSet<T, A>,
+    node: RedBlackTree<T, A>,
+}
+
+impl<'a, T: Ord + Display + Debug + Clone, A: Monoid<T>> CursorMut<'a, T, A> {
+    pub(crate) fn new(tree: &'a mut RedBlackTreeSet<T, A>) -> Self {
+        CursorMut { node: extreme(tree.root.clone(), true), tree }
+    }
+
+    /// The key under the cursor, or `None` at the ghost position
+    pub fn key(&self) -> Option<T> {
+        key_of(&self.node)
+    }
+
+    /// Move to `key` if present, otherwise to the smallest key above it, or
+    /// to the ghost position if there is none. Returns whether `key` was found.
+    pub fn seek(&mut self, key: &T) -> bool {
+        let found;
+        (self.node, found) = seek(&self.tree.root, key);
+        found
+    }
+
+    pub fn move_next(&mut self) {
+        self.node = step(&self.tree.root, &self.node, true);
+    }
+
+    pub fn move_prev(&mut self) {
+        self.node = step(&self.tree.root, &self.node, false);
+    }
+
+    pub fn peek_next(&self) -> Option<T> {
+        key_of(&step(&self.tree.root, &self.node, true))
+    }
+
+    pub fn peek_prev(&self) -> Option<T> {
+        key_of(&step(&self.tree.root, &self.node, false))
+    }
+
+    /// Remove the key under the cursor and move to the next one. Returns
+    /// `None`, changing nothing, at the ghost position.
+    pub fn remove_current(&mut self) -> Option<T> {
+        let node = self.node.clone()?;
+        let removed = node.borrow().key.clone();
+        // Deleting moves nodes but never changes their keys, so the next
+        // node is still next afterwards
+        self.node = step(&self.tree.root, &self.node, true);
+        self.tree.unlink(node);
+        Some(removed)
+    }
+
+    /// Insert `key` just before the cursor, which stays where it is. Returns
+    /// false, changing nothing, unless `key` falls strictly between the
+    /// previous key and the current one.
+    pub fn insert_before(&mut self, key: T) -> bool {
+        let fits = self.peek_prev().is_none_or(|prev| prev < key) && self.key().is_none_or(|current| key < current);
+        if !fits {
+            return false;
+        }
+        self.insert_beside(key, false);
+        true
+    }
+
+    /// Insert `key` just after the cursor, which stays where it is. Returns
+    /// false, changing nothing, unless `key` falls strictly between the
+    /// current key and the next one.
+    pub fn insert_after(&mut self, key: T) -> bool {
+        let fits = self.key().is_none_or(|current| current < key) && self.peek_next().is_none_or(|next| key < next);
+        if !fits {
+            return false;
+        }
+        self.insert_beside(key, true);
+        true
+    }
+
+    /// Link `key` in next to the cursor on the side `after` picks: as the
+    /// current node's child on that side if it is free, otherwise at the near
+    /// end of the subtree there. From the ghost position, "after" is before
+    /// the smallest key and "before" is after the largest.
+    fn insert_beside(&mut self, key: T, after: bool) {
+        let (parent, as_left) = match &self.node {
+            None => match extreme(self.tree.root.clone(), after) {
+                None => {
+                    self.tree.insert(key);
+                    return;
+                }
+                Some(end) => (end, after),
+            },
+            Some(node) => {
+                let subtree = if after { node.borrow().right.clone() } else { node.borrow().left.clone() };
+                match extreme(subtree, after) {
+                    None => (node.clone(), !after),
+                    Some(end) => (end, after),
+                }
+            }
+        };
+        self.tree.attach(&parent, key, as_left);
+    }
+}
+
+/// The keys in order, cloned out of the tree
+pub struct Iter<'a, T: Ord + Display + Debug + Clone, A: Monoid<T> = ()> {
+    cursor: Cursor<'a, T, A>,
+}
+
+impl<'a, T: Ord + Display + Debug + Clone, A: Monoid<T>> Iter<'a, T, A> {
+    pub(crate) fn new(tree: &'a RedBlackTreeSet<T, A>) -> Self {
+        Iter { cursor: Cursor::new(tree) }
+    }
+
+    /// The keys from `key`, or the smallest one above it, onwards
+    pub(crate) fn starting_at(tree: &'a RedBlackTreeSet<T, A>, key: &T) -> Self {
+        let mut cursor = Cursor::new(tree);
+        cursor.seek(key);
+        Iter { cursor }
+    }
+}
+
+impl<T: Ord + Display + Debug + Clone, A: Monoid<T>> Iterator for Iter<'_, T, A> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let key = self.cursor.key()?;
+        self.cursor.move_next();
+        Some(key)
+    }
+}
+
+fn key_of<T: Clone, A: Monoid<T>>(node: &RedBlackTree<T, A>) -> Option<T> {
+    node.as_ref().map(|node| node.borrow().key.clone())
+}
+
+/// The smallest node under `node` when `forward`, else the largest
+fn extreme<T, A: Monoid<T>>(node: RedBlackTree<T, A>, forward: bool) -> RedBlackTree<T, A> {
+    let mut current = node?;
+    loop {
+        let next = if forward { current.borrow().left.clone() } else { current.borrow().right.clone() };
+        match next {
+            Some(next) => current = next,
+            None => return Some(current),
+        }
+    }
+}
+
+/// The node after `node` when `forward`, else the one before, wrapping
+/// through the ghost position
+fn step<T, A: Monoid<T>>(root: &RedBlackTree<T, A>, node: &RedBlackTree<T, A>, forward: bool) -> RedBlackTree<T, A> {
+    let node = match node {
+        None => return extreme(root.clone(), forward),
+        Some(node) => node.clone(),
+    };
+    let far = if forward { node.borrow().right.clone() } else { node.borrow().left.clone() };
+    if far.is_some() {
+        return extreme(far, forward);
+    }
+    // Climb until arriving from the near side, which makes the parent next
+    let mut child = node;
+    loop {
+        let parent = child.borrow().parent.as_ref().and_then(Weak::upgrade)?;
+        let parent_borrow = parent.borrow();
+        let near = if forward { &parent_borrow.left } else { &parent_borrow.right };
+        if near.as_ref().is_some_and(|near| Rc::ptr_eq(near, &child)) {
+            drop(parent_borrow);
+            return Some(parent);
+        }
+        drop(parent_borrow);
+        child = parent;
+    }
+}
+
+/// The node holding `key`, or the smallest key above it, and whether `key`
+/// itself was found
+fn seek<T: Ord, A: Monoid<T>>(root: &RedBlackTree<T, A>, key: &T) -> (RedBlackTree<T, A>, bool) {
+    let mut above = None;
+    let mut current = root.clone();
+    while let Some(node) = current {
+        let node_borrow = node.borrow();
+        current = match key.cmp(&node_borrow.key) {
+            std::cmp::Ordering::Equal => {
+                drop(node_borrow);
+                return (Some(node), true);
+            }
+            std::cmp::Ordering::Less => {
+                let left = node_borrow.left.clone();
+                drop(node_borrow);
+                above = Some(node);
+                left
+            }
+            std::cmp::Ordering::Greater => node_borrow.right.clone(),
+        };
+    }
+    (above, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rbt::RedBlackTreeSet;
+
+    tree_core::cursor_tests!(RedBlackTreeSet);
+}
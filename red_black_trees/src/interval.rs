@@ -0,0 +1,263 @@
+//! An interval tree: a `RedBlackTreeSet` of half-open intervals ordered by
+//! start, each node augmented with the largest end point in its subtree.
+//!
+//! The tree's own rotations and fix-ups keep the max ends current, and they
+//! let every query skip any subtree whose intervals all end before it starts.
+
+use std::cell::RefCell;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::rc::Rc;
+
+use crate::augment::Monoid;
+use crate::rbt::{RedBlackTreeSet, TreeNode};
+
+/// The times from `start` up to but not including `end`, so back-to-back
+/// reservations such as `[9, 10)` and `[10, 11)` do not overlap
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Interval<T> {
+    pub start: T,
+    pub end: T,
+}
+
+impl<T: Ord> Interval<T> {
+    /// Panics if `end` comes before `start`
+    pub fn new(start: T, end: T) -> Self {
+        assert!(start <= end, "an interval cannot end before it starts");
+        Interval { start, end }
+    }
+
+    pub fn contains(&self, point: &T) -> bool {
+        self.start <= *point && *point < self.end
+    }
+
+    pub fn overlaps(&self, other: &Interval<T>) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+}
+
+impl<T: Display> Display for Interval<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}, {})", self.start, self.end)
+    }
+}
+
+/// Largest end point of a subtree's intervals
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MaxEnd;
+
+impl<T: Clone + Debug + Ord> Monoid<Interval<T>> for MaxEnd {
+    type Value = Option<T>;
+
+    fn empty() -> Option<T> {
+        None
+    }
+
+    fn of(interval: &Interval<T>) -> Option<T> {
+        Some(interval.end.clone())
+    }
+
+    fn combine(left: &Option<T>, right: &Option<T>) -> Option<T> {
+        std::cmp::max(left, right).clone()
+    }
+}
+
+type Node<T> = Rc<RefCell<TreeNode<Interval<T>, MaxEnd>>>;
+
+#[derive(Debug)]
+pub struct IntervalTree<T: Ord + Display + Debug + Clone> {
+    tree: RedBlackTreeSet<Interval<T>, MaxEnd>,
+}
+
+impl<T: Ord + Display + Debug + Clone> IntervalTree<T> {
+    pub fn new() -> Self {
+        IntervalTree { tree: RedBlackTreeSet::augmented() }
+    }
+
+    /// Returns false, leaving the tree unchanged, if the same interval is
+    /// already present; different intervals may share a start
+    pub fn insert_interval(&mut self, interval: Interval<T>) -> bool {
+        self.tree.insert(interval)
+    }
+
+    /// Returns false if the interval was not in the tree
+    pub fn remove_interval(&mut self, interval: &Interval<T>) -> bool {
+        self.tree.delete(interval.clone())
+    }
+
+    /// Every interval containing `point`, by start
+    pub fn overlapping_point(&self, point: &T) -> Vec<Interval<T>> {
+        let mut found = Vec::new();
+        Self::collect(&self.tree.root, point, &|start| start <= point, &|interval| interval.contains(point), &mut found);
+        found
+    }
+
+    /// Every interval overlapping `range`, by start
+    pub fn overlapping(&self, range: &Interval<T>) -> Vec<Interval<T>> {
+        let mut found = Vec::new();
+        Self::collect(&self.tree.root, &range.start, &|start| *start < range.end, &|interval| interval.overlaps(range), &mut found);
+        found
+    }
+
+    /// Push every match below `node` in order, skipping subtrees whose
+    /// intervals all end at or before `after` and stopping at the first
+    /// start that fails `early_enough`, since every start to its right does too
+    fn collect(
+        node: &Option<Node<T>>,
+        after: &T,
+        early_enough: &dyn Fn(&T) -> bool,
+        matches: &dyn Fn(&Interval<T>) -> bool,
+        found: &mut Vec<Interval<T>>,
+    ) {
+        let node = match node {
+            Some(node) => node.borrow(),
+            None => return,
+        };
+        if node.summary.as_ref().is_none_or(|max_end| max_end <= after) {
+            return;
+        }
+        Self::collect(&node.left, after, early_enough, matches, found);
+        if !early_enough(&node.key.start) {
+            return;
+        }
+        if matches(&node.key) {
+            found.push(node.key.clone());
+        }
+        Self::collect(&node.right, after, early_enough, matches, found);
+    }
+
+    /// Whether any interval overlaps `range`, visiting one path: O(log n)
+    pub fn any_overlap(&self, range: &Interval<T>) -> bool {
+        let mut current = self.tree.root.clone();
+        while let Some(node) = current {
+            let node = node.borrow();
+            if node.key.overlaps(range) {
+                return true;
+            }
+            // If the left subtree reaches past the range's start, either it
+            // holds an overlap or every interval in it, and so every interval
+            // to its right, starts at or after the range's end
+            let left_reaches = node.left.as_ref().is_some_and(|left| {
+                left.borrow().summary.as_ref().is_some_and(|max_end| *max_end > range.start)
+            });
+            current = if left_reaches { node.left.clone() } else { node.right.clone() };
+        }
+        false
+    }
+
+    /// The intervals in order of start
+    pub fn intervals(&self) -> Vec<Interval<T>> {
+        fn walk<T: Ord + Debug + Clone>(node: &Option<Node<T>>, found: &mut Vec<Interval<T>>) {
+            if let Some(node) = node {
+                walk(&node.borrow().left, found);
+                found.push(node.borrow().key.clone());
+                walk(&node.borrow().right, found);
+            }
+        }
+        let mut found = Vec::new();
+        walk(&self.tree.root, &mut found);
+        found
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Check the red-black invariants and every stored max end
+    pub fn validate(&self) -> Result<(), String> {
+        self.tree.validate()?;
+        Self::validate_max_end(&self.tree.root).map(|_| ())
+    }
+
+    fn validate_max_end(node: &Option<Node<T>>) -> Result<Option<T>, String> {
+        let node = match node {
+            None => return Ok(None),
+            Some(node) => node.borrow(),
+        };
+        let left = Self::validate_max_end(&node.left)?;
+        let right = Self::validate_max_end(&node.right)?;
+        let max_end = std::cmp::max(Some(node.key.end.clone()), std::cmp::max(left, right));
+        if node.summary != max_end {
+            return Err(format!("{} stores max end {:?} but has {:?}", node.key, node.summary, max_end));
+        }
+        Ok(max_end)
+    }
+}
+
+impl<T: Ord + Display + Debug + Clone> Default for IntervalTree<T> {
+    fn default() -> Self {
+        IntervalTree::new()
+    }
+}
+
+impl<T: Ord + Display + Debug + Clone> Display for IntervalTree<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.tree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_back_to_back_intervals_do_not_overlap() {
+        let mut tree = IntervalTree::new();
+        assert!(tree.insert_interval(Interval::new(9, 10)));
+        assert!(tree.insert_interval(Interval::new(10, 11)));
+        assert!(!tree.insert_interval(Interval::new(9, 10)));
+        assert!(tree.insert_interval(Interval::new(9, 12)));
+
+        assert_eq!(tree.overlapping_point(&10), vec![Interval::new(9, 12), Interval::new(10, 11)]);
+        assert_eq!(tree.overlapping(&Interval::new(8, 9)), vec![]);
+        assert!(!tree.any_overlap(&Interval::new(12, 13)));
+        assert!(tree.any_overlap(&Interval::new(11, 13)));
+
+        assert!(tree.remove_interval(&Interval::new(9, 12)));
+        assert!(!tree.remove_interval(&Interval::new(9, 12)));
+        assert!(!tree.any_overlap(&Interval::new(11, 13)));
+        assert_eq!(tree.intervals(), vec![Interval::new(9, 10), Interval::new(10, 11)]);
+        tree.validate().unwrap();
+    }
+
+    #[test]
+    fn test_queries_match_brute_force() {
+        let mut tree = IntervalTree::new();
+        let mut expected = std::collections::BTreeSet::new();
+        let mut rng = StdRng::seed_from_u64(421);
+        let mut next = |bound: u64| rng.gen_range(0..bound);
+        for step in 0..1000 {
+            let start = next(500);
+            let interval = Interval::new(start, start + next(40));
+            if step % 3 == 2 {
+                assert_eq!(tree.remove_interval(&interval), expected.remove(&interval));
+            } else {
+                assert_eq!(tree.insert_interval(interval.clone()), expected.insert(interval));
+            }
+            tree.validate().unwrap();
+
+            let point = next(550);
+            let containing: Vec<_> = expected.iter().filter(|interval| interval.contains(&point)).cloned().collect();
+            assert_eq!(tree.overlapping_point(&point), containing);
+
+            let low = next(550);
+            let range = Interval::new(low, low + next(20));
+            let overlapping: Vec<_> = expected.iter().filter(|interval| interval.overlaps(&range)).cloned().collect();
+            assert_eq!(tree.any_overlap(&range), !overlapping.is_empty());
+            assert_eq!(tree.overlapping(&range), overlapping);
+        }
+        assert_eq!(tree.intervals(), expected.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_validate_reports_stale_max_end() {
+        let mut tree = IntervalTree::new();
+        for (start, end) in [(5, 8), (1, 20), (7, 9)] {
+            tree.insert_interval(Interval::new(start, end));
+        }
+        let root = tree.tree.root.clone().unwrap();
+        root.borrow_mut().summary = Some(9);
+        assert_eq!(tree.validate(), Err("[5, 8) stores max end Some(9) but has Some(20)".to_string()));
+    }
+}
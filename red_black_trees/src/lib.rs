@@ -1 +1,9 @@
-pub mod rbt;
\ No newline at end of file
+pub use tree_core::augment;
+pub mod cursor;
+pub use tree_core::diagram;
+pub mod interval;
+pub mod map;
+pub mod rbt;
+pub use tree_core::stats;
+pub mod trace;
+pub mod view;
@@ -0,0 +1,89 @@
+//! A sorted map kept in a red-black tree.
+//!
+//! The map itself is `tree_core::map::Map`; this module lets it drive the
+//! tree. `entry` walks down once and remembers where it stopped, so a vacant
+//! entry links its new leaf under that node and runs the insert fix once, and
+//! an occupied one unlinks its node directly.
+
+use std::cmp::Ordering;
+use std::fmt::{Debug, Display};
+
+use crate::cursor::Iter;
+use crate::rbt::{RedBlackTree, RedBlackTreeSet};
+use crate::stats::TreeStats;
+use tree_core::map::{Map, MapTree, Slot};
+
+pub use tree_core::map::{Entry, OccupiedEntry, VacantEntry};
+
+pub type RedBlackTreeMap<K, V> = Map<K, V, RedBlackTreeSet<Slot<K>>>;
+
+/// The node a descent stopped at, `None` in an empty tree
+pub struct Place<K>(RedBlackTree<Slot<K>>);
+
+impl<K: Ord + Display + Debug + Clone> MapTree<K> for RedBlackTreeSet<Slot<K>> {
+    type Place = Place<K>;
+    type Slots<'a>
+        = Iter<'a, Slot<K>>
+    where
+        Self: 'a;
+
+    fn is_empty(&self) -> bool {
+        RedBlackTreeSet::is_empty(self)
+    }
+
+    fn stats(&self) -> TreeStats {
+        RedBlackTreeSet::stats(self)
+    }
+
+    fn reset_stats(&mut self) {
+        RedBlackTreeSet::reset_stats(self);
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        RedBlackTreeSet::validate(self)
+    }
+
+    fn slots(&self) -> Iter<'_, Slot<K>> {
+        self.iter()
+    }
+
+    fn slots_from(&self, start: &Slot<K>) -> Iter<'_, Slot<K>> {
+        Iter::starting_at(self, start)
+    }
+
+    fn lookup(&self, probe: impl Fn(&Slot<K>) -> Ordering) -> Option<usize> {
+        RedBlackTreeSet::lookup(self, probe).map(|node| node.borrow().key.index())
+    }
+
+    fn descend(&mut self, probe: impl Fn(&Slot<K>) -> Ordering) -> (Place<K>, Option<Ordering>) {
+        let (node, ordering) = RedBlackTreeSet::descend(self, probe);
+        (Place(node), ordering)
+    }
+
+    fn index_at(Place(node): &Place<K>) -> usize {
+        node.as_ref().unwrap().borrow().key.index()
+    }
+
+    fn link(&mut self, Place(parent): Place<K>, slot: Slot<K>, as_left: bool) {
+        match parent {
+            None => {
+                self.insert(slot);
+            }
+            Some(parent) => self.attach(&parent, slot, as_left),
+        }
+    }
+
+    fn unlink(&mut self, Place(node): Place<K>) -> Slot<K> {
+        let node = node.unwrap();
+        let slot = node.borrow().key.clone();
+        RedBlackTreeSet::unlink(self, node);
+        slot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RedBlackTreeMap;
+
+    tree_core::map_tests!(RedBlackTreeMap);
+}
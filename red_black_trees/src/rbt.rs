@@ -1,10 +1,19 @@
-use std::cell::RefCell;
-use std::iter::Successors;
+use std::cell::{Cell, RefCell};
+use std::io::{self, IsTerminal};
 use std::rc::{Rc, Weak};
-use std::{clone, fmt};
+use std::fmt;
 use std::fmt::Debug;
-use std::cmp::PartialEq;
+use std::cmp::{Ordering, PartialEq};
+use std::hash::{Hash, Hasher};
 use std::fmt::{Display, Formatter};
+use std::ops::RangeBounds;
+
+use crate::augment::{self, Monoid, Summarized};
+use crate::cursor::{Cursor, CursorMut, Iter};
+use crate::diagram::{self, DiagramOptions, Label, Sketch};
+use crate::stats::TreeStats;
+use crate::trace::{DeleteCase, Direction, InsertCase, Recorder, TraceEvent};
+use crate::view::{KeyRef, NodeView};
 
 
 #[derive(Clone, Debug, PartialEq)]
@@ -23,27 +32,92 @@ impl NodeColor {
     }
 }
 
-type Tree<T> = Rc<RefCell<TreeNode<T>>>;
-type Parent<T> = Option<Weak<RefCell<TreeNode<T>>>>;
-type RedBlackTree<T> = Option<Tree<T>>;
+/// When the terminal printers color keys by `NodeColor`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ColorMode {
+    /// Color only when writing straight to a terminal and `NO_COLOR` is unset
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// Settings for `print_tree_with`, `write_tree_with` and `diagram_with`
+#[derive(Clone, Debug, Default)]
+pub struct PrintOptions {
+    pub color: ColorMode,
+    /// Also print each node's `Rc` strong count
+    pub debug: bool,
+}
+
+impl PrintOptions {
+    fn style(&self, is_terminal: bool) -> Style {
+        let color = match self.color {
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            ColorMode::Auto => is_terminal && std::env::var_os("NO_COLOR").is_none(),
+        };
+        Style { color, debug: self.debug }
+    }
+}
+
+/// `PrintOptions` resolved against the sink being written to
+#[derive(Clone, Copy)]
+struct Style {
+    color: bool,
+    debug: bool,
+}
+
+const RED_KEY: &str = "\x1b[97;41m";
+const BLACK_KEY: &str = "\x1b[97;40m";
+const RESET: &str = "\x1b[0m";
 
-#[derive(Clone, Debug)]
-pub struct TreeNode<T> {
+pub(crate) type Tree<T, A = ()> = Rc<RefCell<TreeNode<T, A>>>;
+type Parent<T, A = ()> = Option<Weak<RefCell<TreeNode<T, A>>>>;
+pub(crate) type RedBlackTree<T, A = ()> = Option<Tree<T, A>>;
+
+#[derive(Debug)]
+pub(crate) struct TreeNode<T, A: Monoid<T> = ()> {
     pub color: NodeColor,
     pub key: T,
-    pub parent: Parent<T>,
-    pub left: RedBlackTree<T>,
-    pub right: RedBlackTree<T>,
+    /// `A`'s summary of this node's subtree
+    pub summary: A::Value,
+    pub parent: Parent<T, A>,
+    pub left: RedBlackTree<T, A>,
+    pub right: RedBlackTree<T, A>,
+}
+
+impl<T, A: Monoid<T>> Summarized<T, A> for TreeNode<T, A> {
+    fn key(&self) -> &T {
+        &self.key
+    }
+
+    fn summary(&self) -> &A::Value {
+        &self.summary
+    }
+
+    fn left(&self) -> &RedBlackTree<T, A> {
+        &self.left
+    }
+
+    fn right(&self) -> &RedBlackTree<T, A> {
+        &self.right
+    }
 }
 
 #[derive(Debug)]
-pub struct RedBlackTreeSet<T: Ord+Display+Debug+Copy> where T: Ord+Display+Debug+Clone+Copy+PartialEq{
-    pub root: RedBlackTree<T>,
+pub struct RedBlackTreeSet<T: Ord+Display+Debug+Clone, A: Monoid<T> = ()> where T: Ord+Display+Debug+Clone+PartialEq{
+    pub(crate) root: RedBlackTree<T, A>,
+    trace: Recorder<TraceEvent<T>>,
+    pub(crate) stats: TreeStats,
+    /// What `find` has cost, kept apart because it only borrows the tree
+    searched: Cell<TreeStats>,
 }
-impl<T: Ord + fmt::Debug> TreeNode<T> where T: Ord+Display+Debug+Clone+Copy {
+impl<T: Ord + fmt::Debug, A: Monoid<T>> TreeNode<T, A> where T: Ord+Display+Debug+Clone {
     fn new(val: T) -> Self {
         TreeNode {
             color: NodeColor::Red,
+            summary: A::of(&val),
             key: val,
             parent: None,
             left: None,
@@ -53,7 +127,7 @@ impl<T: Ord + fmt::Debug> TreeNode<T> where T: Ord+Display+Debug+Clone+Copy {
     }
 }
 
-impl<T: Display> Display for TreeNode<T> {
+impl<T: Display, A: Monoid<T>> Display for TreeNode<T, A> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         let parent_key = match &self.parent {
             Some(weak_parent) => {
@@ -84,261 +158,356 @@ impl<T: Display> Display for TreeNode<T> {
     }
 }
 
-impl<T: Ord + fmt::Debug> RedBlackTreeSet<T> where T: Ord+Display+Debug+Clone+Copy{
+impl<T: Ord + fmt::Debug> RedBlackTreeSet<T> where T: Ord+Display+Debug+Clone{
     pub fn new() -> Self {
-        RedBlackTreeSet { root: None }
+        RedBlackTreeSet::augmented()
     }
+}
 
-    pub fn isEmpty(&self) -> bool {
-        return self.root.is_none()
+impl<T: Ord + fmt::Debug, A: Monoid<T>> RedBlackTreeSet<T, A> where T: Ord+Display+Debug+Clone{
+    /// An empty tree whose nodes keep an `A` summary of their subtrees, for
+    /// `aggregate`
+    pub fn augmented() -> Self {
+        RedBlackTreeSet { root: None, trace: Recorder::disabled(), stats: TreeStats::default(), searched: Cell::default() }
     }
 
-    pub fn find(&mut self, key: T) -> RedBlackTree<T>{
+    /// Counters for every operation since the tree was created or last reset
+    pub fn stats(&self) -> TreeStats {
+        let searched = self.searched.get();
+        TreeStats {
+            searches: self.stats.searches + searched.searches,
+            comparisons: self.stats.comparisons + searched.comparisons,
+            max_depth: std::cmp::max(self.stats.max_depth, searched.max_depth),
+            ..self.stats
+        }
+    }
 
-        let root = self.root.clone();
+    pub fn reset_stats(&mut self) {
+        self.stats.reset();
+        self.searched.take();
+    }
+
+    /// The stored key equal to `key`, which may be any borrowed form of the
+    /// key type, like `&str` for a tree of `String`s
+    pub fn find<Q: ?Sized + Ord>(&self, key: &Q) -> Option<KeyRef<'_, T, A>>
+    where
+        T: std::borrow::Borrow<Q>,
+    {
+        self.lookup(|node_key| key.cmp(node_key.borrow())).map(KeyRef::new)
+    }
 
-        if self.isEmpty(){
-            return None;
+    /// The node `probe` calls `Equal`, counted as a search. `probe` compares
+    /// the sought key with a node's.
+    pub(crate) fn lookup(&self, probe: impl Fn(&T) -> Ordering) -> RedBlackTree<T, A> {
+        let mut comparisons = 0;
+        let mut found = None;
+        let mut current = self.root.clone();
+        while let Some(node) = current {
+            let node_borrow = node.borrow();
+            comparisons += 1;
+            current = match probe(&node_borrow.key) {
+                Ordering::Less => node_borrow.left.clone(),
+                Ordering::Greater => node_borrow.right.clone(),
+                Ordering::Equal => {
+                    found = Some(node.clone());
+                    None
+                }
+            };
         }
+        let mut searched = self.searched.get();
+        searched.searches += 1;
+        searched.comparisons += comparisons;
+        searched.max_depth = std::cmp::max(searched.max_depth, comparisons as usize);
+        self.searched.set(searched);
+        found
+    }
 
-        if root.as_ref().unwrap().borrow().key == key{
-            return self.root.clone();
+    /// The node `probe` calls `Equal`, or the node a new key would hang
+    /// from, and what `probe` said about it. The ordering is `None` only
+    /// when the tree is empty.
+    pub(crate) fn descend(&mut self, probe: impl Fn(&T) -> Ordering) -> (RedBlackTree<T, A>, Option<Ordering>) {
+        let comparisons = self.stats.comparisons;
+        let mut last = None;
+        let mut ordering = None;
+        let mut current = self.root.clone();
+        while let Some(node) = current {
+            let node_borrow = node.borrow();
+            self.stats.comparisons += 1;
+            let this = probe(&node_borrow.key);
+            self.trace.record(|| TraceEvent::Compare { node: node_borrow.key.clone(), ordering: this });
+            current = match this {
+                Ordering::Less => node_borrow.left.clone(),
+                Ordering::Greater => node_borrow.right.clone(),
+                Ordering::Equal => None,
+            };
+            drop(node_borrow);
+            last = Some(node);
+            ordering = Some(this);
         }
-        self.find_recursion(&root, key)
+        self.reached_depth(comparisons, 0);
+        (last, ordering)
+    }
+
+    /// The keys in order, cloned out of the tree
+    pub fn iter(&self) -> Iter<'_, T, A> {
+        Iter::new(self)
+    }
+
+    /// A copy of the subtree under `node`, hung from `parent`, that shares no
+    /// nodes with the original
+    fn deep_clone(node: &RedBlackTree<T, A>, parent: Parent<T, A>) -> RedBlackTree<T, A> {
+        let node = node.as_ref()?.borrow();
+        let copy = Rc::new(RefCell::new(TreeNode {
+            color: node.color.clone(),
+            key: node.key.clone(),
+            summary: node.summary.clone(),
+            parent,
+            left: None,
+            right: None,
+        }));
+        let left = Self::deep_clone(&node.left, Some(Rc::downgrade(&copy)));
+        let right = Self::deep_clone(&node.right, Some(Rc::downgrade(&copy)));
+        copy.borrow_mut().left = left;
+        copy.borrow_mut().right = right;
+        Some(copy)
+    }
+
+    /// A read-only view of the root, from which the whole tree's shape can
+    /// be walked
+    pub fn view(&self) -> Option<NodeView<'_, T, A>> {
+        NodeView::of(&self.root)
     }
 
-    pub fn find_recursion(&mut self, node: &RedBlackTree<T>, key: T) -> RedBlackTree<T>{
+    /// Walk down to `key`, counting the comparisons and the depth reached
+    fn locate(&mut self, key: T) -> RedBlackTree<T, A> {
+        let comparisons = self.stats.comparisons;
+        let root = self.root.clone();
+        let found = self.find_recursion(&root, key);
+        self.reached_depth(comparisons, 0);
+        found
+    }
+
+    /// Raise `max_depth` to the length of the path walked since the
+    /// comparison count was `comparisons_before`, plus `extra` levels
+    fn reached_depth(&mut self, comparisons_before: u64, extra: usize) {
+        let depth = (self.stats.comparisons - comparisons_before) as usize + extra;
+        self.stats.max_depth = std::cmp::max(self.stats.max_depth, depth);
+    }
+
+    fn compare(&mut self, key: &T, node_key: &T) -> Ordering {
+        self.stats.comparisons += 1;
+        let ordering = key.cmp(node_key);
+        self.trace.record(|| TraceEvent::Compare { node: node_key.clone(), ordering });
+        ordering
+    }
+
+    fn find_recursion(&mut self, node: &RedBlackTree<T, A>, key: T) -> RedBlackTree<T, A>{
         match node {
             Some(node) => {
-                if key < node.borrow().key {
-                    self.find_recursion(&node.borrow().left, key)
-                } else if key > node.borrow().key {
-                    self.find_recursion(&node.borrow().right, key)
-                } else {
-                    let tnode = node.borrow().clone();
-                    println!("Found Node: {}", tnode);
-
-                    Some(node.clone())
+                let ordering = self.compare(&key, &node.borrow().key);
+                match ordering {
+                    Ordering::Less => self.find_recursion(&node.borrow().left, key),
+                    Ordering::Greater => self.find_recursion(&node.borrow().right, key),
+                    Ordering::Equal => Some(node.clone()),
                 }
             },
             None => None,
         }
     }
 
-    pub fn insert(&mut self, key: T) {
-        let new_node = Rc::new(RefCell::new(TreeNode::new(key)));
-        if self.find(key).is_none() {
-
-            if let Some(mut root) = self.root.clone() {
-                self.insert_recursive(&root, &new_node);
-                self.fix(new_node.clone());
-            } else {
-                // If the tree is empty, make the new node the root and color it black
-                new_node.borrow_mut().color = NodeColor::Black;
-                self.root = Some(new_node);
-            
+    /// Returns false, leaving the tree unchanged, if `key` is already present
+    pub fn insert(&mut self, key: T) -> bool {
+        let comparisons = self.stats.comparisons;
+        // Search first, so a duplicate allocates nothing
+        let (parent, ordering) = self.descend(|node_key| key.cmp(node_key));
+        if ordering == Some(Ordering::Equal) {
+            return false;
+        }
+        match parent {
+            Some(parent) => self.attach(&parent, key, ordering == Some(Ordering::Less)),
+            None => {
+                self.stats.inserts += 1;
+                self.stats.allocations += 1;
+                // The first node is the root, which starts out black rather
+                // than being recolored
+                let mut root = TreeNode::new(key);
+                root.color = NodeColor::Black;
+                self.root = Some(Rc::new(RefCell::new(root)));
             }
-        } else {
-            println!("{:?} is a duplicate", key);
         }
+        // A new leaf sits one level below the last node it was compared with
+        self.reached_depth(comparisons, 1);
+        true
+    }
+
+    /// Like `insert`, but returns every comparison, rotation, fix-up case and
+    /// recolor the insert performed, in order
+    pub fn insert_traced(&mut self, key: T) -> Vec<TraceEvent<T>> {
+        self.trace = Recorder::enabled();
+        self.insert(key);
+        std::mem::replace(&mut self.trace, Recorder::disabled()).into_events()
     }
 
-    fn insert_recursive(&mut self, node: &Tree<T>, new_node: &Tree<T>) {
-        let mut node_borrowed = node.borrow_mut();
+    /// Like `insert_traced`, but also snapshots the whole tree after each
+    /// fix-up case. Returns whether the key was inserted.
+    pub fn insert_explained(&mut self, key: T) -> (bool, Vec<TraceEvent<T>>) {
+        self.trace = Recorder::with_snapshots();
+        let inserted = self.insert(key);
+        (inserted, std::mem::replace(&mut self.trace, Recorder::disabled()).into_events())
+    }
 
-        if new_node.borrow().key < node_borrowed.key {
-            if let Some(left) = &node_borrowed.left {
-                self.insert_recursive(&left, &new_node);
-            } else {
-                let weak_node = Rc::downgrade(&node.clone()); // Convert Rc to Weak
-                node_borrowed.left = Some(Rc::clone(&new_node));
-                new_node.borrow_mut().parent = Some(weak_node); 
-            }
+    /// Record a diagram of the current tree if the recorder wants one
+    fn snapshot(&mut self) {
+        if self.trace.takes_snapshots() {
+            let diagram = self.render_diagram(&DiagramOptions::new(), Style { color: false, debug: false });
+            self.trace.record(|| TraceEvent::Snapshot { diagram });
+        }
+    }
+
+    /// Link a new node for `key` in as `parent`'s left or right child, which
+    /// must be free and the right place for `key`, and restore the red-black
+    /// rules
+    pub(crate) fn attach(&mut self, parent: &Tree<T, A>, key: T, as_left: bool) {
+        self.stats.inserts += 1;
+        self.stats.allocations += 1;
+        let new_node = Rc::new(RefCell::new(TreeNode::new(key)));
+        new_node.borrow_mut().parent = Some(Rc::downgrade(parent));
+        if as_left {
+            parent.borrow_mut().left = Some(new_node.clone());
         } else {
-            if let Some(right) = &node_borrowed.right {
-                self.insert_recursive(&right, &new_node);
-            } else {
-                let weak_node = Rc::downgrade(&node.clone()); // Convert Rc to Weak
-                node_borrowed.right = Some(Rc::clone(&new_node));
-                new_node.borrow_mut().parent = Some(weak_node); 
-            }
+            parent.borrow_mut().right = Some(new_node.clone());
         }
+        self.settle(new_node);
+    }
 
+    /// Bring the summaries above a newly linked leaf up to date, then fix
+    /// any red-red violation it caused
+    fn settle(&mut self, new_node: Tree<T, A>) {
+        // Every ancestor of the new leaf now summarizes one more key
+        Self::update_summaries(Self::parent_of(&new_node));
+        self.fix(new_node);
     }
-    
 
-    fn fix(&mut self, new_node: Tree<T>) {
+    fn fix(&mut self, new_node: Tree<T, A>) {
         // Refernces https://www.codesdope.com/course/data-structures-red-black-trees-insertion/
         // used the pseudo code to implement the rust algorith for fixing an insert
 
-        let is_root = new_node.borrow().parent.is_none();
+        let mut node_ref = new_node;
 
-        let mut node_ref = new_node.clone();
-        let test = node_ref.borrow().parent.is_some();
-        let t = node_ref.borrow().parent.clone().unwrap().upgrade().unwrap().borrow().key;
- 
+        while let Some(parent) = Self::parent_of(&node_ref).filter(|parent| parent.borrow().color == NodeColor::Red) {
+            // A red parent is never the root, so the grandparent exists
+            let grandparent = Self::parent_of(&parent).unwrap();
+            let parent_is_left = Some(true) == self.is_left_child(&parent);
 
-        while node_ref.borrow().parent.is_some()
-            && node_ref.borrow().parent.clone().unwrap().upgrade().unwrap().borrow().color == NodeColor::Red
-        {
+            //find uncle
+            // if uncle exists it is the grandparent's other child
+            let uncle_ref = if parent_is_left {
+                grandparent.borrow().right.clone()
+            } else {
+                grandparent.borrow().left.clone()
+            };
 
-        
-            let parent = node_ref.borrow().parent.clone().unwrap().upgrade().unwrap().clone();
-            
-            if Some(true) == self.is_left_child(&parent) {
-
-                 //find grand_parent
-                let grandparent = match parent.borrow().parent {
-                    Some(ref parent_node) => Some(parent_node.upgrade().unwrap()),
-                    None => None,
-                };
-
-                //find uncle
-                // if uncle exists it must be right child of gp
-                let uncle_ref = match grandparent {
-                    Some(ref grandparent_node) => {
-                        grandparent_node.borrow().right.clone()
-                    },
-                    None => None,
-                };
-
-                if uncle_ref.is_some() && uncle_ref.as_ref().unwrap().borrow().color == NodeColor::Red{
-                    //flip uncle colour
-                    let uncle = uncle_ref.as_ref().unwrap().clone();
-
-                    let new_uncle_colour = NodeColor::flip_color(uncle.borrow().clone().color);
-                    {
-                        uncle.borrow_mut().color = new_uncle_colour;
-                    }
-                
-    
-                    // Flip parent color
-                    let new_parent_colour = NodeColor::flip_color(parent.borrow().clone().color);
-                    parent.borrow_mut().color = new_parent_colour;
-
-                    // Flip grandparent color
-                    if let Some(ref grandparent) = grandparent {
-                        let new_grandparent_colour = NodeColor::flip_color(grandparent.borrow().clone().color);
-                        {
-                            grandparent.borrow_mut().color = new_grandparent_colour;
-
-                            node_ref = grandparent.clone();
-                        }
-                        
-                        // node_ref = grandparent.borrow().clone();
-                    }
-                    
-                } else { 
-                    // new node is a right child
-                    if Some(false) == self.is_left_child(&new_node) {
-                        let parent_weak = new_node.borrow().parent.clone().unwrap();
-                        let parent_upgrade = parent_weak.upgrade().unwrap();
-                        node_ref = parent_upgrade.clone();
-
-                        self.rotate_left(parent_upgrade);
+            if let Some(uncle) = uncle_ref.filter(|uncle| uncle.borrow().color == NodeColor::Red) {
+                self.record_insert_case(InsertCase::RedUncle, &node_ref);
+                // Flip uncle, parent and grandparent colors
+                self.flip_color(&uncle);
+                self.flip_color(&parent);
+                self.flip_color(&grandparent);
+                self.snapshot();
+                node_ref = grandparent;
+            } else {
+                // new node is an inner child: rotate it to the outside
+                let is_triangle = parent_is_left != (Some(true) == self.is_left_child(&node_ref));
+                if is_triangle {
+                    self.stats.double_rotations += 1;
+                    self.record_insert_case(InsertCase::Triangle, &node_ref);
+                    node_ref = parent;
+                    if parent_is_left {
+                        self.rotate_left(node_ref.clone());
+                    } else {
+                        self.rotate_right(node_ref.clone());
                     }
+                    self.snapshot();
+                }
 
-                    let parent_weak = node_ref.borrow().parent.clone().unwrap();
-                    let parent_upgrade = parent_weak.upgrade().unwrap();
+                // Case 3
+                if !is_triangle {
+                    self.stats.single_rotations += 1;
+                }
+                self.record_insert_case(InsertCase::Line, &node_ref);
+                let parent = Self::parent_of(&node_ref).unwrap();
+                let grandparent = Self::parent_of(&parent).unwrap();
+                self.set_color(&parent, NodeColor::Black);
+                self.set_color(&grandparent, NodeColor::Red);
+                if parent_is_left {
+                    self.rotate_right(grandparent);
+                } else {
+                    self.rotate_left(grandparent);
+                }
+                self.snapshot();
+            }
+        }
 
-                    parent_upgrade.borrow_mut().color = NodeColor::Black;
-                  
+        let root = self.root.clone().unwrap();
+        self.set_color(&root, NodeColor::Black);
+    }
 
-                    // Case 3
-                    let grandparent = match parent_upgrade.borrow().parent {
-                        Some(ref parent_node) => Some(parent_node.upgrade().unwrap()),
-                        None => None,
-                    };
+    fn record_insert_case(&mut self, case: InsertCase, node: &Tree<T, A>) {
+        self.trace.record(|| TraceEvent::InsertFix { case, node: node.borrow().key.clone() });
+    }
 
-                    if let Some(ref grandparent) = grandparent {
-                        grandparent.borrow_mut().color = NodeColor::Red;
-                        self.rotate_right(grandparent.clone());
-                        // node_ref = grandparent.clone();
-                    }
+    fn set_color(&mut self, node: &Tree<T, A>, color: NodeColor) {
+        let from = node.borrow().color.clone();
+        if from != color {
+            let to = color.clone();
+            self.stats.recolors += 1;
+            self.trace.record(|| TraceEvent::Recolor { node: node.borrow().key.clone(), from, to });
+            node.borrow_mut().color = color;
+        }
+    }
 
-                    
-                    
-                }
-            } else {
-                //find grand_parent
-                let grandparent = match parent.borrow().parent {
-                    Some(ref parent_node) => Some(parent_node.upgrade().unwrap()),
-                    None => None,
-                };
-
-                //find uncle
-                // if uncle exists it must be left child of gp
-                let uncle_ref = match grandparent {
-                    Some(ref grandparent_node) => {
-                        grandparent_node.borrow().left.clone()
-                    },
-                    None => None,
-                };
-
-                if uncle_ref.is_some() && uncle_ref.as_ref().unwrap().borrow().color == NodeColor::Red{
-                    //flip uncle colour
-                    let uncle = uncle_ref.as_ref().unwrap().clone();
-
-                    let new_uncle_colour = NodeColor::flip_color(uncle.borrow().clone().color);
-                    {
-                        uncle.borrow_mut().color = new_uncle_colour;
-                    }
-                
-    
-                    // Flip parent color
-                    let new_parent_colour = NodeColor::flip_color(parent.borrow().clone().color);
-                    parent.borrow_mut().color = new_parent_colour;
-
-                    // Flip grandparent color
-                    if let Some(ref grandparent) = grandparent {
-                        let new_grandparent_colour = NodeColor::flip_color(grandparent.borrow().clone().color);
-                        {
-                            grandparent.borrow_mut().color = new_grandparent_colour;
-                            node_ref = grandparent.clone();
-                        }
-                        
-                        // node_ref = grandparent.borrow().clone();
-                    }
-                    
-                } else { 
-                    // new node is a right child
-                    if Some(true) == self.is_left_child(&new_node) {
-                        let parent_weak = new_node.borrow().parent.clone().unwrap();
-                        let parent_upgrade = parent_weak.upgrade().unwrap();
-                        node_ref = parent_upgrade.clone();
-                        self.rotate_right(parent_upgrade);
-                    }
+    fn flip_color(&mut self, node: &Tree<T, A>) {
+        let color = NodeColor::flip_color(node.borrow().color.clone());
+        self.set_color(node, color);
+    }
 
-                    // Case 3
-                    let parent_weak = node_ref.borrow().parent.clone().unwrap();
-                    let parent_upgrade = parent_weak.upgrade().unwrap();
-                    
-                    parent_upgrade.borrow_mut().color = NodeColor::Black;
-                   
-
-                    let grandparent = match parent_upgrade.borrow().parent {
-                        Some(ref parent_node) => Some(parent_node.upgrade().unwrap()),
-                        None => None,
-                    };
-
-                    if let Some(ref grandparent) = grandparent {
-                        grandparent.borrow_mut().color = NodeColor::Red;
-                        self.rotate_left(grandparent.clone());
-                        // node_ref = grandparent.clone();
-                    }
-                    
-                }
-            }
-                
-           
-        }
+    fn parent_of(node: &Tree<T, A>) -> RedBlackTree<T, A> {
+        node.borrow().parent.as_ref().and_then(|parent| parent.upgrade())
+    }
+
+    fn summary_of(node: &RedBlackTree<T, A>) -> A::Value {
+        node.as_ref().map_or_else(A::empty, |node| node.borrow().summary.clone())
+    }
 
-        self.root.as_ref().unwrap().borrow_mut().color = NodeColor::Black;
+    /// Recompute `node`'s summary from its children's, which must be current
+    fn update_summary(node: &Tree<T, A>) {
+        let node_borrow = node.borrow();
+        let summary = A::combine(
+            &A::combine(&Self::summary_of(&node_borrow.left), &A::of(&node_borrow.key)),
+            &Self::summary_of(&node_borrow.right),
+        );
+        drop(node_borrow);
+        node.borrow_mut().summary = summary;
+    }
 
+    /// Recompute the summaries from `node` up to the root
+    fn update_summaries(mut node: RedBlackTree<T, A>) {
+        while let Some(current) = node {
+            Self::update_summary(&current);
+            node = Self::parent_of(&current);
+        }
+    }
+
+    /// Missing (nil) children count as black
+    fn color_of(node: &RedBlackTree<T, A>) -> NodeColor {
+        match node {
+            Some(node) => node.borrow().color.clone(),
+            None => NodeColor::Black,
+        }
     }
-    
-    
-    fn is_left_child(&self, node: &Tree<T>) -> Option<bool> {
+
+
+    fn is_left_child(&self, node: &Tree<T, A>) -> Option<bool> {
         if let Some(parent_weak) = &node.borrow().parent {
             if let Some(parent) = parent_weak.upgrade() {
                 if let Some(left_child) = &parent.borrow().left {
@@ -346,25 +515,25 @@ impl<T: Ord + fmt::Debug> RedBlackTreeSet<T> where T: Ord+Display+Debug+Clone+Co
                 }
             }
         }
-        return Some(false)
+        Some(false)
     }
 
-    pub fn rotate_right(&mut self, x: Tree<T>) {
-        let mut y = x.borrow_mut().left.take().unwrap();
+    pub(crate) fn rotate_right(&mut self, x: Tree<T, A>) {
+        self.trace.record(|| TraceEvent::Rotate { direction: Direction::Right, pivot: x.borrow().key.clone() });
+
+        let y = x.borrow_mut().left.take().unwrap();
         x.borrow_mut().left = y.borrow_mut().right.take();
-        
-        if let Some(ref mut right) = y.borrow_mut().right {
-            // Set parent using weak pointer
-            right.borrow_mut().parent = Some(Rc::downgrade(&x));
+
+        if let Some(ref left) = x.borrow().left {
+            // Set parent of the moved subtree using weak pointer
+            left.borrow_mut().parent = Some(Rc::downgrade(&x));
         }
-        
+
         // Set y's parent using weak pointer
         y.borrow_mut().parent = x.borrow().parent.clone();
-        
-        if let Some(parent_weak) = x.borrow().parent.clone() {
-            // Upgrade parent weak pointer to strong reference
-            let parent_upgrade = parent_weak.upgrade().unwrap();
-            if x.borrow().key < parent_upgrade.borrow().key {
+
+        if let Some(parent_upgrade) = Self::parent_of(&x) {
+            if Some(true) == self.is_left_child(&x) {
                 parent_upgrade.borrow_mut().left = Some(y.clone());
             } else {
                 parent_upgrade.borrow_mut().right = Some(y.clone());
@@ -373,32 +542,33 @@ impl<T: Ord + fmt::Debug> RedBlackTreeSet<T> where T: Ord+Display+Debug+Clone+Co
             // Update root if x was root
             self.root = Some(y.clone());
         }
-        
+
         // Set y's right child
         y.borrow_mut().right = Some(x.clone());
         // Set x's parent using weak pointer
         x.borrow_mut().parent = Some(Rc::downgrade(&y));
+
+        // x is now below y; the subtree above them holds the same keys
+        Self::update_summary(&x);
+        Self::update_summary(&y);
     }
-    
-    
-    
 
-    pub fn rotate_left(&mut self, x: Tree<T>) {
-        let mut y = x.borrow_mut().right.take().unwrap();
+    pub(crate) fn rotate_left(&mut self, x: Tree<T, A>) {
+        self.trace.record(|| TraceEvent::Rotate { direction: Direction::Left, pivot: x.borrow().key.clone() });
+
+        let y = x.borrow_mut().right.take().unwrap();
         x.borrow_mut().right = y.borrow_mut().left.take();
-        
-        if let Some(ref mut left) = y.borrow_mut().left {
-            // Set parent using weak pointer
-            left.borrow_mut().parent = x.borrow().parent.clone();
+
+        if let Some(ref right) = x.borrow().right {
+            // Set parent of the moved subtree using weak pointer
+            right.borrow_mut().parent = Some(Rc::downgrade(&x));
         }
-        
+
         // Set y's parent using weak pointer
         y.borrow_mut().parent = x.borrow().parent.clone();
-        
-        if let Some(parent_weak) = x.borrow().parent.clone() {
-            // Upgrade parent weak pointer to strong reference
-            let parent_upgrade = parent_weak.upgrade().unwrap();
-            if x.borrow().key < parent_upgrade.borrow().key {
+
+        if let Some(parent_upgrade) = Self::parent_of(&x) {
+            if Some(true) == self.is_left_child(&x) {
                 parent_upgrade.borrow_mut().left = Some(y.clone());
             } else {
                 parent_upgrade.borrow_mut().right = Some(y.clone());
@@ -407,313 +577,347 @@ impl<T: Ord + fmt::Debug> RedBlackTreeSet<T> where T: Ord+Display+Debug+Clone+Co
             // Update root if x was root
             self.root = Some(y.clone());
         }
-        
+
         // Set y's left child
         y.borrow_mut().left = Some(x.clone());
         // Set x's parent using weak pointer
         x.borrow_mut().parent = Some(Rc::downgrade(&y));
+
+        // x is now below y; the subtree above them holds the same keys
+        Self::update_summary(&x);
+        Self::update_summary(&y);
     }
-    
-    
-
-    pub fn delete(&mut self, key: T) {
-        if self.find(key).is_none() {
-            println!("Key is not in the tree");
-            return;
-        }
-
-        let mut x: RedBlackTree<T> = None;
-        let found_node = self.find(key);
-        let found_node_ref = found_node.clone();
-
-        let mut parent = None;
-
-        // let found = found_node_ref.as_ref().unwrap().borrow();
-        let z_parent = found_node_ref.as_ref().unwrap().borrow().parent.clone();
-        let z_left = &found_node_ref.as_ref().unwrap().borrow().left;
-        let z_right = &found_node_ref.as_ref().unwrap().borrow().right;
-    
-        let mut y = found_node_ref.clone();
-        let mut y_original_color = y.as_ref().unwrap().borrow().color.clone();
-    
-        if z_left.is_none() {
-            x = z_right.clone();
-            self.transplant(&found_node_ref.clone(), &z_right.clone());
-            if x.is_some(){
-                parent = x.as_ref().unwrap().borrow().parent.clone();
-            }
-        } else if z_right.is_none() {
-            x = z_left.clone();
-            self.transplant(&found_node_ref.clone(), &x.clone());
-            if x.is_some(){
-                parent = x.as_ref().unwrap().borrow().parent.clone();
-            }
-    
-        } else {
-            let y = self.find_minimum(&found_node_ref.clone().as_ref().unwrap().borrow().right.clone()).clone();
-           
-            y_original_color = y.as_ref().unwrap().borrow().color.clone();
-            x = y.as_ref().unwrap().borrow().right.clone();
 
 
-            let minimum_parent = match y.as_ref().unwrap().borrow().parent {
-                Some(ref parent_node) => Some(parent_node.upgrade().unwrap()),
-                None => None,
-            };
+    /// A read-only cursor on the smallest key
+    pub fn cursor(&self) -> Cursor<'_, T, A> {
+        Cursor::new(self)
+    }
 
-            
-            self.find(minimum_parent.as_ref().unwrap().borrow().key);
-            parent = Some(Rc::downgrade(&minimum_parent.as_ref().unwrap()));
-            self.find(minimum_parent.as_ref().unwrap().borrow().key);
-            if minimum_parent.unwrap().borrow().key == found_node_ref.as_ref().unwrap().borrow().key {
-                // Here you have access to the key of the minimum node's parent
-                
-                if let Some(x_ref) = x.as_ref() {
-                    x_ref.borrow_mut().parent = Some(Rc::downgrade(&y.as_ref().unwrap()));
-                    parent = Some(Rc::downgrade(&y.as_ref().unwrap()));
-                }
-            } else {
-                self.transplant(&y.clone(), &y.as_ref().unwrap().borrow().right.clone());
-               
-                y.as_ref().unwrap().borrow_mut().right = z_right.clone();
-                y.as_ref().unwrap().borrow_mut().right.as_ref().unwrap().borrow_mut().parent = Some(Rc::downgrade(&y.as_ref().unwrap()));
-                let minimum_parent = match y.as_ref().unwrap().borrow().parent {
-                    Some(ref parent_node) => Some(parent_node.upgrade().unwrap()),
-                    None => None,
-                };
-                self.find(minimum_parent.as_ref().unwrap().borrow().key);
-            }
+    /// A cursor on the smallest key that can also insert and remove keys
+    pub fn cursor_mut(&mut self) -> CursorMut<'_, T, A> {
+        CursorMut::new(self)
+    }
 
-            let minimum_parent = match y.as_ref().unwrap().borrow().parent {
-                Some(ref parent_node) => Some(parent_node.upgrade().unwrap()),
-                None => None,
-            };
+    /// Combine the summaries of every key in `range`, in ascending order,
+    /// visiting O(log n) nodes
+    pub fn aggregate<R: RangeBounds<T>>(&self, range: R) -> A::Value {
+        augment::aggregate(&self.root, range.start_bound(), range.end_bound())
+    }
 
-            self.find(minimum_parent.as_ref().unwrap().borrow().key);
-            self.transplant(&found_node_ref.clone(), &y.clone());
-            let minimum_parent = match y.as_ref().unwrap().borrow().parent {
-                Some(ref parent_node) => Some(parent_node.upgrade().unwrap()),
-                None => None,
-            };
-            self.find(minimum_parent.as_ref().unwrap().borrow().key);
-            self.find(found_node_ref.as_ref().unwrap().borrow().key);
+    /// Returns false if `key` was not in the tree
+    pub fn delete(&mut self, key: T) -> bool {
+        match self.locate(key) {
+            Some(z) => {
+                self.unlink(z);
+                true
+            }
+            None => false,
+        }
+    }
 
-            
-            y.as_ref().unwrap().borrow_mut().left = z_left.clone();
-            y.as_ref().unwrap().borrow_mut().left.as_ref().unwrap().borrow_mut().parent = Some(Rc::downgrade(&y.as_ref().unwrap()));
-            y.as_ref().unwrap().borrow_mut().color = found_node_ref.as_ref().unwrap().borrow().color.clone();
+    /// Remove node `z` from the tree and restore the red-black rules. If `z`
+    /// has two children its successor node moves into its place, so every
+    /// other node keeps its key.
+    pub(crate) fn unlink(&mut self, z: Tree<T, A>) {
+        self.stats.deletes += 1;
+        let z_left = z.borrow().left.clone();
+        let z_right = z.borrow().right.clone();
+
+        // x moves into y's old position; x may be nil, so its parent is tracked separately
+        let x: RedBlackTree<T, A>;
+        let x_parent: RedBlackTree<T, A>;
+        let mut y_original_color = z.borrow().color.clone();
+
+        match (&z_left, &z_right) {
+            (None, _) => {
+                x = z_right.clone();
+                x_parent = Self::parent_of(&z);
+                self.transplant(&z, &z_right);
+            }
+            (_, None) => {
+                x = z_left.clone();
+                x_parent = Self::parent_of(&z);
+                self.transplant(&z, &z_left);
+            }
+            (Some(z_left), Some(z_right)) => {
+                // z has two children: splice out its successor y and put y in z's place
+                let y = self.find_minimum(&Some(z_right.clone())).unwrap();
+                y_original_color = y.borrow().color.clone();
+                x = y.borrow().right.clone();
+
+                if Rc::ptr_eq(&Self::parent_of(&y).unwrap(), &z) {
+                    x_parent = Some(y.clone());
+                } else {
+                    x_parent = Self::parent_of(&y);
+                    self.transplant(&y, &x);
+                    z_right.borrow_mut().parent = Some(Rc::downgrade(&y));
+                    y.borrow_mut().right = Some(z_right.clone());
+                }
+
+                self.transplant(&z, &Some(y.clone()));
+                z_left.borrow_mut().parent = Some(Rc::downgrade(&y));
+                y.borrow_mut().left = Some(z_left.clone());
+                let z_color = z.borrow().color.clone();
+                self.set_color(&y, z_color);
+            }
         }
 
+        // Every node whose subtree lost a key or gained y is on x's parent's
+        // path to the root
+        Self::update_summaries(x_parent.clone());
+        self.snapshot();
         if y_original_color == NodeColor::Black {
-            self.fix_delete(x, parent);
+            self.fix_delete(x, x_parent);
         }
     }
-    
 
-    fn fix_delete(&mut self, x: RedBlackTree<T>, parent: Parent<T>) {
-        let mut x_ref = x.clone();
-        let parent_ref_weak = parent.clone();
-        
-        while parent_ref_weak.as_ref().unwrap().upgrade().as_ref().unwrap().borrow().parent.is_some() && (x_ref.is_none() || x_ref.as_ref().unwrap().borrow().color == NodeColor::Black) {
-            
+    /// Like `delete`, but returns every comparison, rotation, fix-up case and
+    /// recolor the delete performed, in order
+    pub fn delete_traced(&mut self, key: T) -> Vec<TraceEvent<T>> {
+        self.trace = Recorder::enabled();
+        self.delete(key);
+        std::mem::replace(&mut self.trace, Recorder::disabled()).into_events()
+    }
+
+
+    /// Like `delete_traced`, but also snapshots the whole tree once the node
+    /// is unlinked and after each fix-up case. Returns whether the key was
+    /// removed.
+    pub fn delete_explained(&mut self, key: T) -> (bool, Vec<TraceEvent<T>>) {
+        self.trace = Recorder::with_snapshots();
+        let removed = self.delete(key);
+        (removed, std::mem::replace(&mut self.trace, Recorder::disabled()).into_events())
+    }
+
+    fn fix_delete(&mut self, x: RedBlackTree<T, A>, parent: RedBlackTree<T, A>) {
+        let mut x_ref = x;
+        let mut parent_ref = parent;
+
+        // x is the root once it has no parent
+        while Self::color_of(&x_ref) == NodeColor::Black {
+            let parent = match parent_ref {
+                Some(ref parent) => parent.clone(),
+                None => break,
+            };
+
             // cant use is left function since x may be null
-            if x_ref.is_none() || (x_ref.as_ref().unwrap().borrow().key == parent.as_ref().unwrap().upgrade().unwrap().borrow().left.as_ref().unwrap().borrow().key) {
-                // right sibling exists and is red
+            let x_is_left = match (&x_ref, &parent.borrow().left) {
+                (Some(x_node), Some(left)) => Rc::ptr_eq(x_node, left),
+                (None, left) => left.is_none(),
+                (Some(_), None) => false,
+            };
 
-                let mut right_sibling = match parent {
-                    Some(ref parent_node) => {
-                        Some(parent_node.upgrade().as_ref().unwrap().borrow().right.as_ref().unwrap().clone())},
-                    None => None,
-                };
+            if x_is_left {
+                // A doubly black x always has a sibling
+                let mut w = parent.borrow().right.clone().unwrap();
 
                 // case 1 sibling is red
-                if right_sibling.is_some() && right_sibling.as_ref().unwrap().borrow().color == NodeColor::Red {
-                    let mut w = right_sibling.as_ref().unwrap().clone();
-                    w.borrow_mut().color = NodeColor::Black;
-                    let parent_upgrade = parent_ref_weak.clone().unwrap().upgrade().clone();
-
-                    
-                    self.rotate_left(parent_upgrade.as_ref().unwrap().clone());
-                    parent_upgrade.as_ref().unwrap().borrow_mut().color = NodeColor::Red;
-                    w = parent_upgrade.as_ref().expect("").borrow().right.as_ref().unwrap().clone();    
+                if w.borrow().color == NodeColor::Red {
+                    self.stats.single_rotations += 1;
+                    self.record_delete_case(DeleteCase::RedSibling, &parent);
+                    self.set_color(&w, NodeColor::Black);
+                    self.set_color(&parent, NodeColor::Red);
+                    self.rotate_left(parent.clone());
+                    self.snapshot();
+                    w = parent.borrow().right.clone().unwrap();
                 }
 
-                
-                 
+                let w_left = w.borrow().left.clone();
+                let w_right = w.borrow().right.clone();
+
                 // case 2: Both children of the sibling are black
-                if let Some(ref right_sibling_node) = right_sibling {
-                    if (right_sibling_node.borrow().right.is_none() || right_sibling_node.borrow().right.as_ref().unwrap().borrow().color == NodeColor::Black) &&
-                    (right_sibling_node.borrow().left.is_none() || right_sibling_node.borrow().left.as_ref().unwrap().borrow().color == NodeColor::Black) {
-                        let mut w = right_sibling_node.clone();
-                        w.borrow_mut().color = NodeColor::Red;
-    
-                        let parent_upgrade = parent_ref_weak.clone().unwrap().upgrade().clone();
-
-                        x_ref = parent_upgrade.clone();
-                        
-                    } else {
-                        // Case 3: Sibling's right child is black
-                        if let Some(ref w_node) = right_sibling {
-                            if w_node.borrow().right.is_some() && w_node.borrow().right.as_ref().unwrap().borrow().color == NodeColor::Black {
-                                w_node.borrow_mut().left.as_mut().unwrap().borrow_mut().color = NodeColor::Black;
-                                w_node.borrow_mut().color = NodeColor::Red;
-                                self.rotate_right(w_node.clone());
-
-                                let parent_upgrade = parent_ref_weak.clone().unwrap().upgrade().clone();
-
-                                right_sibling = parent_upgrade.as_ref().unwrap().borrow().right.clone(); // Update w after rotation
-
-                            }
-                        }
-
-                        // Case 4: Sibling's right child is red
-                        if let Some(ref w_node) = right_sibling {
-                            let parent_upgrde = parent_ref_weak.as_ref().unwrap().upgrade().clone();
-                            right_sibling.as_ref().unwrap().borrow_mut().color = parent_upgrde.as_ref().unwrap().borrow().color.clone();
-                            parent_upgrde.as_ref().unwrap().borrow_mut().color = NodeColor::Black;
-                            right_sibling.as_ref().unwrap().borrow_mut().right.as_mut().unwrap().borrow_mut().color = NodeColor::Black;
-                            self.rotate_left(parent_upgrde.as_ref().unwrap().clone());
-                        }
+                if Self::color_of(&w_left) == NodeColor::Black && Self::color_of(&w_right) == NodeColor::Black {
+                    self.record_delete_case(DeleteCase::BlackSiblingBlackChildren, &parent);
+                    self.set_color(&w, NodeColor::Red);
+                    self.snapshot();
+                    parent_ref = Self::parent_of(&parent);
+                    x_ref = Some(parent);
+                } else {
+                    // Case 3: Sibling's right child is black
+                    let near_child_red = Self::color_of(&w_right) == NodeColor::Black;
+                    if near_child_red {
+                        self.stats.double_rotations += 1;
+                        self.record_delete_case(DeleteCase::BlackSiblingRedNearChild, &parent);
+                        self.set_color(w_left.as_ref().unwrap(), NodeColor::Black);
+                        self.set_color(&w, NodeColor::Red);
+                        self.rotate_right(w.clone());
+                        self.snapshot();
+                        w = parent.borrow().right.clone().unwrap(); // Update w after rotation
                     }
-                }
-            
 
-     
+                    // Case 4: Sibling's right child is red
+                    if !near_child_red {
+                        self.stats.single_rotations += 1;
+                    }
+                    self.record_delete_case(DeleteCase::BlackSiblingRedFarChild, &parent);
+                    let parent_color = parent.borrow().color.clone();
+                    self.set_color(&w, parent_color);
+                    self.set_color(&parent, NodeColor::Black);
+                    let w_right = w.borrow().right.clone().unwrap();
+                    self.set_color(&w_right, NodeColor::Black);
+                    self.rotate_left(parent.clone());
+                    self.snapshot();
+
+                    x_ref = self.root.clone();
+                    parent_ref = None;
+                }
             } else {
-                // right sibling exists and is red
-                let mut left_sibling = match parent {
-                    Some(ref parent_node) => {
-                        Some(parent_node.upgrade().as_ref().unwrap().borrow().right.as_ref().unwrap().clone())},
-                    None => None,
-                };
+                // A doubly black x always has a sibling
+                let mut w = parent.borrow().left.clone().unwrap();
 
                 // case 1 sibling is red
-                if left_sibling.is_some() && left_sibling.as_ref().unwrap().borrow().color == NodeColor::Red {
-                    let mut w = left_sibling.as_ref().unwrap().clone();
-                    w.borrow_mut().color = NodeColor::Black;
-                    let parent_upgrade = parent_ref_weak.clone().unwrap().upgrade().clone();
-
-                    
-                    self.rotate_right(parent_upgrade.as_ref().unwrap().clone());
-                    parent_upgrade.as_ref().unwrap().borrow_mut().color = NodeColor::Red;
-                    w = parent_upgrade.as_ref().expect("").borrow().right.as_ref().unwrap().clone();    
+                if w.borrow().color == NodeColor::Red {
+                    self.stats.single_rotations += 1;
+                    self.record_delete_case(DeleteCase::RedSibling, &parent);
+                    self.set_color(&w, NodeColor::Black);
+                    self.set_color(&parent, NodeColor::Red);
+                    self.rotate_right(parent.clone());
+                    self.snapshot();
+                    w = parent.borrow().left.clone().unwrap();
                 }
 
-                
-                
+                let w_left = w.borrow().left.clone();
+                let w_right = w.borrow().right.clone();
+
                 // case 2: Both children of the sibling are black
-                if let Some(ref left_sibling_node) = left_sibling {
-                    if (left_sibling_node.borrow().right.is_none() || left_sibling_node.borrow().right.as_ref().unwrap().borrow().color == NodeColor::Black) &&
-                    (left_sibling_node.borrow().left.is_none() || left_sibling_node.borrow().left.as_ref().unwrap().borrow().color == NodeColor::Black) {
-                        let mut w = left_sibling_node.clone();
-                        w.borrow_mut().color = NodeColor::Red;
-                        let parent_upgrade = parent_ref_weak.clone().unwrap().upgrade().clone();
-                        x_ref = parent_upgrade.clone();
+                if Self::color_of(&w_left) == NodeColor::Black && Self::color_of(&w_right) == NodeColor::Black {
+                    self.record_delete_case(DeleteCase::BlackSiblingBlackChildren, &parent);
+                    self.set_color(&w, NodeColor::Red);
+                    self.snapshot();
+                    parent_ref = Self::parent_of(&parent);
+                    x_ref = Some(parent);
+                } else {
+                    // Case 3: Sibling's left child is black
+                    let near_child_red = Self::color_of(&w_left) == NodeColor::Black;
+                    if near_child_red {
+                        self.stats.double_rotations += 1;
+                        self.record_delete_case(DeleteCase::BlackSiblingRedNearChild, &parent);
+                        self.set_color(w_right.as_ref().unwrap(), NodeColor::Black);
+                        self.set_color(&w, NodeColor::Red);
+                        self.rotate_left(w.clone());
+                        self.snapshot();
+                        w = parent.borrow().left.clone().unwrap(); // Update w after rotation
+                    }
 
-                    } else {
-                        // Case 3: Sibling's right child is black
-                        if let Some(ref w_node) = left_sibling {
-                            if w_node.borrow().left.is_some() && w_node.borrow().left.as_ref().unwrap().borrow().color == NodeColor::Black {
-                                w_node.borrow_mut().left.as_mut().unwrap().borrow_mut().color = NodeColor::Black;
-                                w_node.borrow_mut().color = NodeColor::Red;
-                                self.rotate_left(w_node.clone());
-
-                                let parent_upgrade = parent_ref_weak.clone().unwrap().upgrade().clone();
-
-                                left_sibling = parent_upgrade.as_ref().unwrap().borrow().left.clone(); // Update w after rotation
-                            }
-                        }
-
-                        // Case 4: Sibling's right child is red
-                        if let Some(ref w_node) = left_sibling {
-                            let parent_upgrde = parent_ref_weak.as_ref().unwrap().upgrade().clone();
-                            left_sibling.as_ref().unwrap().borrow_mut().color = parent_upgrde.as_ref().unwrap().borrow().color.clone();
-                            parent_upgrde.as_ref().unwrap().borrow_mut().color = NodeColor::Black;
-                            left_sibling.as_ref().unwrap().borrow_mut().left.as_mut().unwrap().borrow_mut().color = NodeColor::Black;
-                            self.rotate_right(parent_upgrde.as_ref().unwrap().clone());
-                            
-                            x_ref = self.root.clone();
-
-                        }
+                    // Case 4: Sibling's left child is red
+                    if !near_child_red {
+                        self.stats.single_rotations += 1;
                     }
+                    self.record_delete_case(DeleteCase::BlackSiblingRedFarChild, &parent);
+                    let parent_color = parent.borrow().color.clone();
+                    self.set_color(&w, parent_color);
+                    self.set_color(&parent, NodeColor::Black);
+                    let w_left = w.borrow().left.clone().unwrap();
+                    self.set_color(&w_left, NodeColor::Black);
+                    self.rotate_right(parent.clone());
+                    self.snapshot();
+
+                    x_ref = self.root.clone();
+                    parent_ref = None;
                 }
             }
-        }   
-        if x_ref.is_some(){
-            x_ref.as_ref().unwrap().borrow_mut().color = NodeColor::Black;
         }
-        
+        if let Some(x_node) = &x_ref {
+            self.set_color(x_node, NodeColor::Black);
+        }
     }
-    
 
+    fn record_delete_case(&mut self, case: DeleteCase, parent: &Tree<T, A>) {
+        self.trace.record(|| TraceEvent::DeleteFix { case, parent: parent.borrow().key.clone() });
+    }
 
-    fn transplant(&mut self, u: &RedBlackTree<T>, v: &RedBlackTree<T>) {
-        let u_node = u.as_ref().unwrap().clone();
-        let u_parent = u.as_ref().unwrap().borrow().parent.clone();
-            
-        
-        let u_parent_upgrade = match u_parent.clone() {
-            Some(upgrade_parent) => upgrade_parent.upgrade().unwrap(),
-            None => return, // Handle the case when u_parent is None, if needed
-        };
-        
-    
-        // Check if u is root
-        if u_parent.is_none() {
-            self.root = v.clone();
-        } 
-
-        
-        if Some(true) == self.is_left_child(&u_node) {
-            u_parent_upgrade.borrow_mut().left = v.clone();
-        } else {
-            u_parent_upgrade.borrow_mut().right = v.clone();
+
+    /// Replace the subtree rooted at `u` with the one rooted at `v`
+    fn transplant(&mut self, u: &Tree<T, A>, v: &RedBlackTree<T, A>) {
+        match Self::parent_of(u) {
+            // u is root
+            None => self.root = v.clone(),
+            Some(u_parent) => {
+                if Some(true) == self.is_left_child(u) {
+                    u_parent.borrow_mut().left = v.clone();
+                } else {
+                    u_parent.borrow_mut().right = v.clone();
+                }
+            }
         }
-    
-        // Set the parent of v to be the parent of u
-        if let Some(v_node) = v.clone() {
-                v_node.borrow_mut().parent = Some(Rc::downgrade(&u_parent_upgrade.clone()));
 
-        
+        // Set the parent of v to be the parent of u
+        if let Some(v_node) = v {
+            v_node.borrow_mut().parent = u.borrow().parent.clone();
         }
     }
-    
 
-    pub fn find_minimum(&mut self, tree: &RedBlackTree<T>) -> RedBlackTree<T> {
-        let root = self.root.clone();
+
+    fn find_minimum(&mut self, tree: &RedBlackTree<T, A>) -> RedBlackTree<T, A> {
         self.find_minimum_recursion(tree.clone())
     }
-    
-    pub fn find_minimum_recursion(&mut self, tree: RedBlackTree<T>) -> RedBlackTree<T> {
+
+    fn find_minimum_recursion(&mut self, tree: RedBlackTree<T, A>) -> RedBlackTree<T, A> {
         match tree {
             Some(node) => {
                 if node.borrow().left.is_none() {
-                    let tnode = node.borrow().clone();
                     Some(node.clone())
                 } else {
-                    let tnode = node.borrow().clone();
                     self.find_minimum_recursion(node.borrow().left.clone())
                 }
             },
             None => None,
         }
     }
-    
 
+
+    /// Write the tree diagram (the same one `Display` produces) to `out`
+    pub fn write_tree(&self, out: &mut dyn io::Write) -> io::Result<()> {
+        write!(out, "{}", self)
+    }
+
+    /// Write the tree diagram to `out`. `ColorMode::Auto` writes plain text,
+    /// since an arbitrary writer cannot be checked for a terminal.
+    pub fn write_tree_with(&self, out: &mut dyn io::Write, options: &PrintOptions) -> io::Result<()> {
+        let rendered = self.render_tree(options.style(false));
+        out.write_all(rendered.as_bytes())
+    }
+
+    /// Print the tree to stdout, coloring keys when stdout is a terminal
     pub fn print_tree(&self) {
+        self.print_tree_with(&PrintOptions::default());
+    }
+
+    pub fn print_tree_with(&self, options: &PrintOptions) {
+        print!("{}", self.render_tree(options.style(io::stdout().is_terminal())));
+    }
+
+    fn render_tree(&self, style: Style) -> String {
+        let mut rendered = String::new();
+        self.fmt_tree(&mut rendered, style).expect("writing to a String cannot fail");
+        rendered
+    }
+
+    fn fmt_tree(&self, f: &mut dyn fmt::Write, style: Style) -> fmt::Result {
         if let Some(root) = &self.root {
-            println!("Red-Black Tree:");
-            self.print_recursive(root.clone(), "", true);
+            writeln!(f, "Red-Black Tree:")?;
+            self.fmt_recursive(f, root.clone(), "", true, style)
         } else {
-            println!("Empty tree");
+            writeln!(f, "Empty tree")
         }
     }
 
-    fn print_recursive(&self, node: Tree<T>, prefix: &str, is_left: bool) {
+    fn fmt_recursive(&self, f: &mut dyn fmt::Write, node: Tree<T, A>, prefix: &str, is_left: bool, style: Style) -> fmt::Result {
         let node_borrowed = node.borrow();
 
         // Print the node's key and color
-        println!("{}{}{:?} ({:?}), RC-count = {}", prefix, if is_left { "L├── " } else { "R└── " }, node_borrowed.key, node_borrowed.color,Rc::strong_count(&node));
+        write!(f, "{}{}", prefix, if is_left { "L├── " } else { "R└── " })?;
+        if style.color {
+            write!(f, "{}{:?}{}", Self::color_code(&node_borrowed.color), node_borrowed.key, RESET)?;
+        } else {
+            write!(f, "{:?} ({:?})", node_borrowed.key, node_borrowed.color)?;
+        }
+        if style.debug {
+            // One count is held by the `node` handle passed into this call
+            write!(f, ", RC-count = {}", Rc::strong_count(&node) - 1)?;
+        }
+        writeln!(f)?;
 
         // Calculate the prefix for child nodes
         let mut child_prefix = prefix.to_string();
@@ -721,12 +925,68 @@ impl<T: Ord + fmt::Debug> RedBlackTreeSet<T> where T: Ord+Display+Debug+Clone+Co
 
         // Recursively print the left subtree
         if let Some(left) = &node_borrowed.left {
-            self.print_recursive(left.clone(), &child_prefix, true);
+            self.fmt_recursive(f, left.clone(), &child_prefix, true, style)?;
         }
 
         // Recursively print the right subtree
         if let Some(right) = &node_borrowed.right {
-            self.print_recursive(right.clone(), &child_prefix, false);
+            self.fmt_recursive(f, right.clone(), &child_prefix, false, style)?;
+        }
+        Ok(())
+    }
+
+    fn color_code(color: &NodeColor) -> &'static str {
+        match color {
+            NodeColor::Red => RED_KEY,
+            NodeColor::Black => BLACK_KEY,
+        }
+    }
+
+    /// Render the tree top-down, with each key centered over its children and
+    /// tagged `(R)` or `(B)` for its color
+    pub fn diagram(&self, options: &DiagramOptions) -> String {
+        self.render_diagram(options, Style { color: false, debug: false })
+    }
+
+    /// Render the diagram with keys colored by `NodeColor` instead of tagged.
+    /// As with `write_tree_with`, `ColorMode::Auto` renders plain text.
+    pub fn diagram_with(&self, options: &DiagramOptions, print: &PrintOptions) -> String {
+        self.render_diagram(options, print.style(false))
+    }
+
+    /// Print the diagram to stdout, coloring keys when stdout is a terminal
+    pub fn print_diagram(&self, options: &DiagramOptions, print: &PrintOptions) {
+        print!("{}", self.render_diagram(options, print.style(io::stdout().is_terminal())));
+    }
+
+    fn render_diagram(&self, options: &DiagramOptions, style: Style) -> String {
+        match &self.root {
+            Some(root) => diagram::render(&self.sketch(root, style), options),
+            None => String::new(),
+        }
+    }
+
+    pub fn write_diagram(&self, out: &mut dyn io::Write, options: &DiagramOptions) -> io::Result<()> {
+        out.write_all(self.diagram(options).as_bytes())
+    }
+
+    fn sketch(&self, node: &Tree<T, A>, style: Style) -> Sketch {
+        let node_borrowed = node.borrow();
+        let key = format!("{:?}", node_borrowed.key);
+        let label = if style.color {
+            let width = key.chars().count();
+            Label::styled(format!("{}{}{}", Self::color_code(&node_borrowed.color), key, RESET), width)
+        } else {
+            let color = match node_borrowed.color {
+                NodeColor::Red => "R",
+                NodeColor::Black => "B",
+            };
+            Label::plain(format!("{}({})", key, color))
+        };
+        Sketch {
+            label,
+            left: node_borrowed.left.as_ref().map(|left| Box::new(self.sketch(left, style))),
+            right: node_borrowed.right.as_ref().map(|right| Box::new(self.sketch(right, style))),
         }
     }
 
@@ -735,7 +995,7 @@ impl<T: Ord + fmt::Debug> RedBlackTreeSet<T> where T: Ord+Display+Debug+Clone+Co
         self.leaves_recursion(&root)
     }
 
-    pub fn leaves_recursion(&self, node: &RedBlackTree<T>) -> i32 {
+    fn leaves_recursion(&self, node: &RedBlackTree<T, A>) -> i32 {
         match node {
             Some(node) => {
                 if node.borrow().left.is_none() && node.borrow().right.is_none() {
@@ -753,12 +1013,62 @@ impl<T: Ord + fmt::Debug> RedBlackTreeSet<T> where T: Ord+Display+Debug+Clone+Co
         self.root.is_none()
     }
 
+    #[deprecated(note = "renamed to `is_empty`")]
+    #[allow(non_snake_case)]
+    pub fn isEmpty(&self) -> bool {
+        self.is_empty()
+    }
+
+    /// Check key order, parent links and the red-black rules, describing the
+    /// first problem found
+    pub fn validate(&self) -> Result<(), String> {
+        if Self::color_of(&self.root) == NodeColor::Red {
+            return Err("the root is red".to_string());
+        }
+        Self::validate_recursion(&self.root, &None, None, None).map(|_| ())
+    }
+
+    /// Returns the black height of a valid subtree whose keys must lie
+    /// strictly between `low` and `high`
+    fn validate_recursion(node: &RedBlackTree<T, A>, parent: &RedBlackTree<T, A>, low: Option<&T>, high: Option<&T>) -> Result<usize, String> {
+        let node = match node {
+            None => return Ok(1),
+            Some(node) => node,
+        };
+        let node_borrowed = node.borrow();
+        let key = &node_borrowed.key;
+        if low.is_some_and(|low| key <= low) || high.is_some_and(|high| key >= high) {
+            return Err(format!("{:?} is out of order", key));
+        }
+        let linked_parent = node_borrowed.parent.as_ref().and_then(|parent| parent.upgrade());
+        let parent_matches = match (&linked_parent, parent) {
+            (Some(linked), Some(parent)) => Rc::ptr_eq(linked, parent),
+            (None, None) => true,
+            _ => false,
+        };
+        if !parent_matches {
+            return Err(format!("{:?} has a stale parent link", key));
+        }
+        if node_borrowed.color == NodeColor::Red
+            && (Self::color_of(&node_borrowed.left) == NodeColor::Red || Self::color_of(&node_borrowed.right) == NodeColor::Red)
+        {
+            return Err(format!("red {:?} has a red child", key));
+        }
+        let this = Some(node.clone());
+        let left = Self::validate_recursion(&node_borrowed.left, &this, low, Some(key))?;
+        let right = Self::validate_recursion(&node_borrowed.right, &this, Some(key), high)?;
+        if left != right {
+            return Err(format!("black heights differ below {:?}: {} on the left, {} on the right", key, left, right));
+        }
+        Ok(left + usize::from(node_borrowed.color == NodeColor::Black))
+    }
+
     pub fn get_tree_height(&self) -> i32 {
         let root = self.root.clone();
         self.get_tree_height_recursion(&root)
     }
 
-    pub fn get_tree_height_recursion(&self, node: &RedBlackTree<T>) -> i32 {
+    fn get_tree_height_recursion(&self, node: &RedBlackTree<T, A>) -> i32 {
         match node {
             Some(node) => {
                 let left_height = self.get_tree_height_recursion(&node.borrow().left);
@@ -774,25 +1084,545 @@ impl<T: Ord + fmt::Debug> RedBlackTreeSet<T> where T: Ord+Display+Debug+Clone+Co
         }
     }
 
+    /// Write every key and its color in ascending order, one per line, to `out`
+    pub fn write_in_order_traversal(&self, out: &mut dyn io::Write) -> io::Result<()> {
+        let mut rendered = String::new();
+        self.fmt_in_order(&mut rendered, &self.root).expect("writing to a String cannot fail");
+        out.write_all(rendered.as_bytes())
+    }
+
     pub fn print_in_order_traversal(&self) {
-        let root = self.root.clone();
-        self.print_in_order_traversal_recursion(&root);
+        self.write_in_order_traversal(&mut io::stdout()).unwrap();
+    }
+
+    fn fmt_in_order(&self, f: &mut dyn fmt::Write, node: &RedBlackTree<T, A>) -> fmt::Result {
+        if let Some(node) = node {
+            self.fmt_in_order(f, &node.borrow().left)?;
+            writeln!(
+                f,
+                "Key: {:?}, Color: {:?}",
+                node.borrow().key,
+                node.borrow().color
+            )?;
+            self.fmt_in_order(f, &node.borrow().right)?;
+        }
+        Ok(())
+    }
+
+}
+
+impl<T, A: Monoid<T>> Default for RedBlackTreeSet<T, A> where T: Ord+Display+Debug+Clone {
+    fn default() -> Self {
+        RedBlackTreeSet::augmented()
+    }
+}
+
+/// A copy with the same shape and colors that shares no nodes with the
+/// original. Its stats start from zero.
+impl<T, A: Monoid<T>> Clone for RedBlackTreeSet<T, A> where T: Ord+Display+Debug+Clone {
+    fn clone(&self) -> Self {
+        RedBlackTreeSet {
+            root: Self::deep_clone(&self.root, None),
+            trace: Recorder::disabled(),
+            stats: TreeStats::default(),
+            searched: Cell::default(),
+        }
+    }
+}
+
+/// Trees are equal when they hold the same keys, whatever their shape
+impl<T, A: Monoid<T>> PartialEq for RedBlackTreeSet<T, A> where T: Ord+Display+Debug+Clone {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl<T, A: Monoid<T>> Eq for RedBlackTreeSet<T, A> where T: Ord+Display+Debug+Clone {}
+
+impl<T, A: Monoid<T>> PartialOrd for RedBlackTreeSet<T, A> where T: Ord+Display+Debug+Clone {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Compares the keys in order, like `BTreeSet`
+impl<T, A: Monoid<T>> Ord for RedBlackTreeSet<T, A> where T: Ord+Display+Debug+Clone {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+impl<T, A: Monoid<T>> Hash for RedBlackTreeSet<T, A> where T: Ord+Display+Debug+Clone+Hash {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut len = 0usize;
+        for key in self.iter() {
+            key.hash(state);
+            len += 1;
+        }
+        len.hash(state);
     }
+}
+
+impl<T, A: Monoid<T>> Extend<T> for RedBlackTreeSet<T, A> where T: Ord+Display+Debug+Clone {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for key in iter {
+            self.insert(key);
+        }
+    }
+}
+
+impl<T, A: Monoid<T>> FromIterator<T> for RedBlackTreeSet<T, A> where T: Ord+Display+Debug+Clone {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut tree = RedBlackTreeSet::augmented();
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl<'a, T, A: Monoid<T>> IntoIterator for &'a RedBlackTreeSet<T, A> where T: Ord+Display+Debug+Clone {
+    type Item = T;
+    type IntoIter = Iter<'a, T, A>;
+
+    fn into_iter(self) -> Iter<'a, T, A> {
+        self.iter()
+    }
+}
+
+impl<T, A: Monoid<T>> Display for RedBlackTreeSet<T, A> where T: Ord+Display+Debug+Clone {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.fmt_tree(f, Style { color: false, debug: false })
+    }
+}
 
-    pub fn print_in_order_traversal_recursion(&self, node: &RedBlackTree<T>) {
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::ops::Bound;
+
+    /// Check ordering, parent links and the red-black rules; returns the
+    /// black height of `node`
+    fn check_subtree(node: &RedBlackTree<u32>, parent: &RedBlackTree<u32>, low: Option<u32>, high: Option<u32>) -> usize {
         match node {
+            None => 1,
             Some(node) => {
-                self.print_in_order_traversal_recursion(&node.borrow().left);
-                println!(
-                    "Key: {:?}, Color: {:?}",
-                    node.borrow().key,
-                    node.borrow().color
-                );
-                self.print_in_order_traversal_recursion(&node.borrow().right);
+                let node_borrowed = node.borrow();
+                let key = node_borrowed.key;
+                assert!(low.is_none_or(|low| key > low), "{} is out of order", key);
+                assert!(high.is_none_or(|high| key < high), "{} is out of order", key);
+                let linked_parent = node_borrowed.parent.as_ref().and_then(|parent| parent.upgrade());
+                match (&linked_parent, parent) {
+                    (Some(linked), Some(parent)) => assert!(Rc::ptr_eq(linked, parent), "{} has a stale parent", key),
+                    (None, None) => {}
+                    _ => panic!("{} has a stale parent", key),
+                }
+                if node_borrowed.color == NodeColor::Red {
+                    for child in [&node_borrowed.left, &node_borrowed.right].into_iter().flatten() {
+                        assert_eq!(child.borrow().color, NodeColor::Black, "red {} has a red child", key);
+                    }
+                }
+                let this = Some(node.clone());
+                let left = check_subtree(&node_borrowed.left, &this, low, Some(key));
+                let right = check_subtree(&node_borrowed.right, &this, Some(key), high);
+                assert_eq!(left, right, "black heights differ below {}", key);
+                left + usize::from(node_borrowed.color == NodeColor::Black)
             }
-            None => {}
         }
     }
 
-}
+    fn assert_valid(tree: &RedBlackTreeSet<u32>) {
+        if let Some(root) = &tree.root {
+            assert_eq!(root.borrow().color, NodeColor::Black);
+        }
+        check_subtree(&tree.root, &None, None, None);
+        tree.validate().unwrap();
+    }
+
+    fn keys(tree: &RedBlackTreeSet<u32>) -> Vec<u32> {
+        let mut out = Vec::new();
+        tree.write_in_order_traversal(&mut out).unwrap();
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| line.trim_start_matches("Key: ").split(',').next().unwrap().parse().unwrap())
+            .collect()
+    }
 
+    #[test]
+    fn test_random_inserts_and_deletes_keep_invariants() {
+        let mut tree = RedBlackTreeSet::new();
+        let mut expected = std::collections::BTreeSet::new();
+        let mut rng = StdRng::seed_from_u64(421);
+        for step in 0..2000 {
+            let key: u32 = rng.gen_range(0..200);
+            if step % 3 == 2 {
+                tree.delete(key);
+                expected.remove(&key);
+            } else {
+                tree.insert(key);
+                expected.insert(key);
+            }
+            assert_valid(&tree);
+        }
+        assert_eq!(keys(&tree), expected.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_delete_root_until_empty() {
+        let mut tree = RedBlackTreeSet::new();
+        for key in 1..=20 {
+            tree.insert(key);
+        }
+        while let Some(root) = tree.root.clone() {
+            let key = root.borrow().key;
+            tree.delete(key);
+            assert_valid(&tree);
+        }
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn test_aggregate_matches_brute_force() {
+        use crate::augment::{Count, Max, Min, Sum};
+        let mut sums: RedBlackTreeSet<u64, Sum> = RedBlackTreeSet::augmented();
+        let mut counts: RedBlackTreeSet<u64, Count> = RedBlackTreeSet::augmented();
+        let mut mins: RedBlackTreeSet<u64, Min> = RedBlackTreeSet::augmented();
+        let mut maxes: RedBlackTreeSet<u64, Max> = RedBlackTreeSet::default();
+        let mut expected = std::collections::BTreeSet::new();
+        let mut rng = StdRng::seed_from_u64(421);
+        for step in 0..1000 {
+            let key: u64 = rng.gen_range(0..200);
+            if step % 3 == 2 {
+                let removed = expected.remove(&key);
+                for changed in [sums.delete(key), counts.delete(key), mins.delete(key), maxes.delete(key)] {
+                    assert_eq!(changed, removed);
+                }
+            } else {
+                sums.insert(key);
+                counts.insert(key);
+                mins.insert(key);
+                maxes.insert(key);
+                expected.insert(key);
+            }
+            let (low, high) = (key.saturating_sub(40), key + 25);
+            let inside: Vec<u64> = expected.range(low..high).copied().collect();
+            assert_eq!(sums.aggregate(low..high), inside.iter().sum::<u64>());
+            assert_eq!(counts.aggregate(low..high), inside.len());
+            assert_eq!(mins.aggregate(low..high), inside.first().copied());
+            assert_eq!(maxes.aggregate(low..high), inside.last().copied());
+        }
+        assert_eq!(sums.aggregate(..), expected.iter().sum::<u64>());
+        assert_eq!(counts.aggregate((Bound::Excluded(10), Bound::Included(20))), expected.range(11..=20).count());
+        assert_eq!(mins.aggregate(300..), None);
+        assert_eq!(RedBlackTreeSet::<u64, Sum>::augmented().aggregate(..), 0);
+    }
+
+    #[test]
+    fn test_insert_trace_line_case() {
+        let mut tree = RedBlackTreeSet::new();
+        tree.insert(10);
+        tree.insert(20);
+        let trace = tree.insert_traced(30);
+        assert_eq!(
+            trace,
+            vec![
+                TraceEvent::Compare { node: 10, ordering: Ordering::Greater },
+                TraceEvent::Compare { node: 20, ordering: Ordering::Greater },
+                TraceEvent::InsertFix { case: InsertCase::Line, node: 30 },
+                TraceEvent::Recolor { node: 20, from: NodeColor::Red, to: NodeColor::Black },
+                TraceEvent::Recolor { node: 10, from: NodeColor::Black, to: NodeColor::Red },
+                TraceEvent::Rotate { direction: Direction::Left, pivot: 10 },
+            ]
+        );
+        // Tracing is per call
+        assert!(tree.insert_traced(30).iter().all(|event| matches!(event, TraceEvent::Compare { .. })));
+    }
+
+    #[test]
+    fn test_explained_operations_snapshot_each_case() {
+        let mut tree = RedBlackTreeSet::new();
+        tree.insert(10);
+        tree.insert(20);
+        let (inserted, events) = tree.insert_explained(30);
+        assert!(inserted);
+        assert_eq!(
+            &events[2..],
+            &[
+                TraceEvent::InsertFix { case: InsertCase::Line, node: 30 },
+                TraceEvent::Recolor { node: 20, from: NodeColor::Red, to: NodeColor::Black },
+                TraceEvent::Recolor { node: 10, from: NodeColor::Black, to: NodeColor::Red },
+                TraceEvent::Rotate { direction: Direction::Left, pivot: 10 },
+                TraceEvent::Snapshot { diagram: tree.diagram(&DiagramOptions::new()) },
+            ]
+        );
+
+        let (removed, events) = tree.delete_explained(10);
+        assert!(removed);
+        let snapshots: Vec<&TraceEvent<u32>> =
+            events.iter().filter(|event| matches!(event, TraceEvent::Snapshot { .. })).collect();
+        // 10 was a red leaf, so there is no fix-up after unlinking it
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(*snapshots[0], TraceEvent::Snapshot { diagram: "20(B)\n     \\\n   30(R)\n".to_string() });
+        assert_eq!(tree.delete_explained(10), (false, vec![TraceEvent::Compare { node: 20, ordering: Ordering::Less }]));
+    }
+
+    #[test]
+    fn test_insert_trace_triangle_and_red_uncle() {
+        let mut tree = RedBlackTreeSet::new();
+        tree.insert(30);
+        tree.insert(10);
+        let trace = tree.insert_traced(20);
+        assert_eq!(
+            &trace[2..],
+            &[
+                TraceEvent::InsertFix { case: InsertCase::Triangle, node: 20 },
+                TraceEvent::Rotate { direction: Direction::Left, pivot: 10 },
+                TraceEvent::InsertFix { case: InsertCase::Line, node: 10 },
+                TraceEvent::Recolor { node: 20, from: NodeColor::Red, to: NodeColor::Black },
+                TraceEvent::Recolor { node: 30, from: NodeColor::Black, to: NodeColor::Red },
+                TraceEvent::Rotate { direction: Direction::Right, pivot: 30 },
+            ]
+        );
+
+        let trace = tree.insert_traced(5);
+        assert_eq!(
+            &trace[2..],
+            &[
+                TraceEvent::InsertFix { case: InsertCase::RedUncle, node: 5 },
+                TraceEvent::Recolor { node: 30, from: NodeColor::Red, to: NodeColor::Black },
+                TraceEvent::Recolor { node: 10, from: NodeColor::Red, to: NodeColor::Black },
+                TraceEvent::Recolor { node: 20, from: NodeColor::Black, to: NodeColor::Red },
+                TraceEvent::Recolor { node: 20, from: NodeColor::Red, to: NodeColor::Black },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_delete_trace_fix_cases() {
+        let mut tree = RedBlackTreeSet::new();
+        for key in [20, 10, 30, 40] {
+            tree.insert(key);
+        }
+        // 10 is a black leaf whose sibling 30 has a red far child
+        let trace = tree.delete_traced(10);
+        assert_eq!(
+            trace,
+            vec![
+                TraceEvent::Compare { node: 20, ordering: Ordering::Less },
+                TraceEvent::Compare { node: 10, ordering: Ordering::Equal },
+                TraceEvent::DeleteFix { case: DeleteCase::BlackSiblingRedFarChild, parent: 20 },
+                TraceEvent::Recolor { node: 40, from: NodeColor::Red, to: NodeColor::Black },
+                TraceEvent::Rotate { direction: Direction::Left, pivot: 20 },
+            ]
+        );
+        assert_valid(&tree);
+
+        // Both children of the sibling are black: recolor and move up
+        let trace = tree.delete_traced(20);
+        assert!(trace.contains(&TraceEvent::DeleteFix { case: DeleteCase::BlackSiblingBlackChildren, parent: 30 }));
+        assert_valid(&tree);
+    }
+
+    #[test]
+    fn test_string_keys() {
+        let mut tree = RedBlackTreeSet::new();
+        for word in ["pear", "apple", "fig", "kiwi", "banana", "cherry"] {
+            assert!(tree.insert(word.to_string()));
+        }
+        assert!(!tree.insert("fig".to_string()));
+        assert!(tree.delete("apple".to_string()));
+        assert!(tree.find("kiwi").is_some());
+        assert_eq!(tree.validate(), Ok(()));
+        let mut out = Vec::new();
+        tree.write_in_order_traversal(&mut out).unwrap();
+        let keys: Vec<String> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| line.trim_start_matches("Key: ").split(',').next().unwrap().to_string())
+            .collect();
+        // Keys are printed with `Debug`, so strings keep their quotes
+        assert_eq!(keys, ["\"banana\"", "\"cherry\"", "\"fig\"", "\"kiwi\"", "\"pear\""]);
+    }
+
+    #[test]
+    fn test_validate_reports_broken_trees() {
+        let mut tree = RedBlackTreeSet::new();
+        for key in [20, 10, 30, 5] {
+            tree.insert(key);
+        }
+        assert_eq!(tree.validate(), Ok(()));
+
+        let root = tree.root.clone().unwrap();
+        let ten = root.borrow().left.clone().unwrap();
+        ten.borrow_mut().color = NodeColor::Red;
+        assert_eq!(tree.validate(), Err("red 10 has a red child".to_string()));
+
+        ten.borrow_mut().color = NodeColor::Black;
+        root.borrow().right.as_ref().unwrap().borrow_mut().color = NodeColor::Red;
+        assert_eq!(
+            tree.validate(),
+            Err("black heights differ below 20: 2 on the left, 1 on the right".to_string())
+        );
+
+        root.borrow_mut().color = NodeColor::Red;
+        assert_eq!(tree.validate(), Err("the root is red".to_string()));
+    }
+
+    #[test]
+    fn test_insert_and_delete_report_changes() {
+        let mut tree = RedBlackTreeSet::new();
+        assert!(tree.insert(5));
+        assert!(tree.insert(3));
+        assert!(!tree.insert(5));
+        assert!(tree.delete(5));
+        assert!(!tree.delete(5));
+        assert!(!tree.delete(8));
+        assert!(tree.find(&3).is_some());
+    }
+
+    #[test]
+    fn test_stats_count_operations() {
+        let mut tree = RedBlackTreeSet::new();
+        // 30 is a line case under 10, 25 meets a red uncle and 27 is a triangle
+        for key in [10, 20, 30, 25, 27] {
+            tree.insert(key);
+        }
+        // Neither a duplicate insert nor a missing delete changes the tree
+        tree.insert(27);
+        tree.delete(40);
+        let stats = tree.stats();
+        assert_eq!((stats.inserts, stats.deletes), (5, 0));
+        assert_eq!(stats.allocations, 5);
+        assert_eq!(stats.single_rotations, 1);
+        assert_eq!(stats.double_rotations, 1);
+        assert_eq!(stats.rotations(), 3);
+        // The first root starts out black, so it is not a recolor
+        assert_eq!(stats.recolors, 8);
+        assert_eq!(stats.max_depth, 4);
+
+        tree.reset_stats();
+        assert!(tree.find(&27).is_some());
+        assert_eq!(tree.stats(), TreeStats { searches: 1, comparisons: 2, max_depth: 2, ..TreeStats::default() });
+    }
+
+    #[test]
+    fn test_display_empty_tree() {
+        let tree: RedBlackTreeSet<u32> = RedBlackTreeSet::new();
+        assert_eq!(tree.to_string(), "Empty tree\n");
+    }
+
+    #[test]
+    fn test_write_tree_matches_display() {
+        let mut tree = RedBlackTreeSet::new();
+        tree.insert(10);
+        tree.insert(5);
+        tree.insert(15);
+
+        let expected = "Red-Black Tree:\n\
+                        L├── 10 (Black)\n\
+                        │   L├── 5 (Red)\n\
+                        │   R└── 15 (Red)\n";
+        assert_eq!(tree.to_string(), expected);
+        let mut out = Vec::new();
+        tree.write_tree(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+
+        // Auto never colors a writer that is not known to be a terminal
+        let mut out = Vec::new();
+        tree.write_tree_with(&mut out, &PrintOptions::default()).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_write_tree_with_color_and_debug() {
+        let mut tree = RedBlackTreeSet::new();
+        tree.insert(10);
+        tree.insert(5);
+
+        let options = PrintOptions { color: ColorMode::Always, debug: true };
+        let mut out = Vec::new();
+        tree.write_tree_with(&mut out, &options).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Red-Black Tree:\n\
+             L├── \x1b[97;40m10\x1b[0m, RC-count = 1\n\
+             │   L├── \x1b[97;41m5\x1b[0m, RC-count = 1\n"
+        );
+
+        let options = PrintOptions { color: ColorMode::Never, debug: true };
+        let mut out = Vec::new();
+        tree.write_tree_with(&mut out, &options).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Red-Black Tree:\nL├── 10 (Black), RC-count = 1\n│   L├── 5 (Red), RC-count = 1\n"
+        );
+    }
+
+    #[test]
+    fn test_diagram() {
+        let mut tree = RedBlackTreeSet::new();
+        assert_eq!(tree.diagram(&DiagramOptions::new()), "");
+        for key in [10, 5, 15, 20] {
+            tree.insert(key);
+        }
+        let expected = "   10(B)\n  /     \\\n5(B)  15(B)\n           \\\n         20(R)\n";
+        assert_eq!(tree.diagram(&DiagramOptions::new()), expected);
+
+        let mut out = Vec::new();
+        tree.write_diagram(&mut out, &DiagramOptions::new().max_width(12)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "  10(B)\n /     \\\n...   ...\n");
+
+        // Colored labels are laid out by the width of the bare key
+        let colored = tree.diagram_with(&DiagramOptions::new(), &PrintOptions { color: ColorMode::Always, debug: false });
+        let stripped = colored.replace(BLACK_KEY, "").replace(RED_KEY, "").replace(RESET, "");
+        assert_eq!(stripped, " 10\n/  \\\n5 15\n    \\\n   20\n");
+    }
+
+    #[test]
+    fn test_write_in_order_traversal() {
+        let mut tree = RedBlackTreeSet::new();
+        for key in [20, 10, 30, 5] {
+            tree.insert(key);
+        }
+        let mut out = Vec::new();
+        tree.write_in_order_traversal(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Key: 5, Color: Red\nKey: 10, Color: Black\nKey: 20, Color: Black\nKey: 30, Color: Black\n"
+        );
+    }
+
+    #[test]
+    fn test_clone_is_deep_and_equality_ignores_shape() {
+        use std::collections::hash_map::DefaultHasher;
+        fn hash_of(tree: &RedBlackTreeSet<i32>) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            tree.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let ascending: RedBlackTreeSet<i32> = (1..=7).collect();
+        let mut shuffled = RedBlackTreeSet::new();
+        shuffled.extend([5, 2, 7, 1, 4, 6, 3]);
+        // Different shapes, same keys
+        assert_ne!(ascending.to_string(), shuffled.to_string());
+        assert_eq!(ascending, shuffled);
+        assert_eq!(hash_of(&ascending), hash_of(&shuffled));
+
+        // The copy's parent links point into the copy, which validate checks
+        let mut copy = ascending.clone();
+        assert_eq!(copy.to_string(), ascending.to_string());
+        // The original's inserts are not the copy's
+        assert_eq!(copy.stats(), TreeStats::default());
+        copy.validate().unwrap();
+        copy.delete(4);
+        copy.insert(8);
+        copy.validate().unwrap();
+        ascending.validate().unwrap();
+        assert_eq!(ascending.iter().collect::<Vec<_>>(), vec![1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(copy.iter().collect::<Vec<_>>(), vec![1, 2, 3, 5, 6, 7, 8]);
+        assert!(ascending < copy);
+        assert!(RedBlackTreeSet::<i32>::new() < ascending);
+    }
+}
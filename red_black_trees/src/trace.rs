@@ -0,0 +1,50 @@
+//! Structured record of what a single `insert` or `delete` did, returned by
+//! `RedBlackTreeSet::insert_traced` and `RedBlackTreeSet::delete_traced`, or
+//! with intermediate diagrams by the `_explained` variants.
+
+use std::cmp::Ordering;
+
+use crate::rbt::NodeColor;
+
+pub use tree_core::trace::Direction;
+pub(crate) use tree_core::trace::Recorder;
+
+/// The insert fix-up cases, for a red node whose parent is also red
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InsertCase {
+    /// Case 1: the uncle is red, so parent, uncle and grandparent are recolored
+    RedUncle,
+    /// Case 2: the node is an inner grandchild and is rotated to the outside
+    Triangle,
+    /// Case 3: the node is an outer grandchild; the grandparent is rotated
+    Line,
+}
+
+/// The delete fix-up cases, for a doubly black node and its sibling `w`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DeleteCase {
+    /// Case 1: `w` is red
+    RedSibling,
+    /// Case 2: `w` is black and both of its children are black
+    BlackSiblingBlackChildren,
+    /// Case 3: `w` is black, its near child is red and its far child is black
+    BlackSiblingRedNearChild,
+    /// Case 4: `w` is black and its far child is red
+    BlackSiblingRedFarChild,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TraceEvent<T> {
+    /// The operation's key was compared against `node` on the way down
+    Compare { node: T, ordering: Ordering },
+    /// `rotate_left` or `rotate_right` was called with `pivot`
+    Rotate { direction: Direction, pivot: T },
+    /// `fix` applied `case` to the red `node`
+    InsertFix { case: InsertCase, node: T },
+    /// `fix_delete` applied `case` below `parent`
+    DeleteFix { case: DeleteCase, parent: T },
+    Recolor { node: T, from: NodeColor, to: NodeColor },
+    /// Diagram of the whole tree after a delete unlinked its node or a
+    /// fix-up case finished. Only recorded by the `_explained` operations.
+    Snapshot { diagram: String },
+}
@@ -0,0 +1,106 @@
+//! Read-only handles into a red-black tree.
+//!
+//! Nodes live in `Rc<RefCell<..>>`s that the tree owns, so a plain `&T`
+//! cannot outlive the borrow of the node holding it. These handles keep the
+//! node alive instead, borrow the tree they came from so it cannot change
+//! underneath them, and hand out short `Ref` guards on request. Nothing here
+//! can modify a node.
+
+use std::cell::Ref;
+use std::fmt::{self, Debug};
+use std::marker::PhantomData;
+use std::rc::Weak;
+
+use crate::augment::Monoid;
+use crate::rbt::{NodeColor, RedBlackTree, Tree, TreeNode};
+
+/// The key a lookup found
+pub struct KeyRef<'a, T, A: Monoid<T> = ()> {
+    node: Tree<T, A>,
+    tree: PhantomData<&'a TreeNode<T, A>>,
+}
+
+impl<'a, T, A: Monoid<T>> KeyRef<'a, T, A> {
+    pub(crate) fn new(node: Tree<T, A>) -> Self {
+        KeyRef { node, tree: PhantomData }
+    }
+
+    pub fn get(&self) -> Ref<'_, T> {
+        Ref::map(self.node.borrow(), |node| &node.key)
+    }
+}
+
+impl<T: Debug, A: Monoid<T>> Debug for KeyRef<'_, T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("KeyRef").field(&*self.get()).finish()
+    }
+}
+
+/// One node of the tree, for code that needs to see its shape: colors,
+/// children, parents and summaries
+pub struct NodeView<'a, T, A: Monoid<T> = ()> {
+    node: Tree<T, A>,
+    tree: PhantomData<&'a TreeNode<T, A>>,
+}
+
+impl<'a, T, A: Monoid<T>> NodeView<'a, T, A> {
+    pub(crate) fn of(node: &RedBlackTree<T, A>) -> Option<Self> {
+        node.as_ref().map(|node| NodeView { node: node.clone(), tree: PhantomData })
+    }
+
+    pub fn key(&self) -> Ref<'_, T> {
+        Ref::map(self.node.borrow(), |node| &node.key)
+    }
+
+    pub fn color(&self) -> NodeColor {
+        self.node.borrow().color.clone()
+    }
+
+    /// `A`'s summary of this node's subtree
+    pub fn summary(&self) -> Ref<'_, A::Value> {
+        Ref::map(self.node.borrow(), |node| &node.summary)
+    }
+
+    pub fn left(&self) -> Option<NodeView<'a, T, A>> {
+        NodeView::of(&self.node.borrow().left)
+    }
+
+    pub fn right(&self) -> Option<NodeView<'a, T, A>> {
+        NodeView::of(&self.node.borrow().right)
+    }
+
+    pub fn parent(&self) -> Option<NodeView<'a, T, A>> {
+        let parent = self.node.borrow().parent.as_ref().and_then(Weak::upgrade);
+        NodeView::of(&parent)
+    }
+}
+
+impl<T: Debug, A: Monoid<T>> Debug for NodeView<'_, T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NodeView").field("key", &*self.key()).field("color", &self.color()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rbt::{NodeColor, RedBlackTreeSet};
+
+    #[test]
+    fn test_views_show_the_shape() {
+        let mut tree = RedBlackTreeSet::new();
+        for key in [10, 20, 30] {
+            tree.insert(key);
+        }
+        let root = tree.view().unwrap();
+        assert_eq!((*root.key(), root.color()), (20, NodeColor::Black));
+        let left = root.left().unwrap();
+        assert_eq!((*left.key(), left.color()), (10, NodeColor::Red));
+        assert!(left.left().is_none());
+        assert_eq!(*left.parent().unwrap().key(), 20);
+        assert!(root.parent().is_none());
+
+        assert_eq!(*tree.find(&30).unwrap().get(), 30);
+        assert!(tree.find(&25).is_none());
+        assert!(RedBlackTreeSet::<i32>::new().view().is_none());
+    }
+}
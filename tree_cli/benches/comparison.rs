@@ -0,0 +1,301 @@
+//! AVL tree vs red-black tree vs `BTreeSet` on the same workloads. Every
+//! measurement that changes a set starts from a fresh one built outside the
+//! timed region, and returns it so dropping it is not timed either.
+
+use std::collections::BTreeSet;
+
+use criterion::measurement::WallTime;
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkGroup, BenchmarkId, Criterion};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+use avl_trees::tree::Tree;
+use red_black_trees::rbt::RedBlackTreeSet;
+use tree_cli::workload::{Distribution, Mix, Op, Workload};
+
+const SIZES: [usize; 3] = [1_000, 10_000, 100_000];
+const SEED: u64 = 421;
+
+/// The operations measured, over each tree's own API
+trait Set: Sized {
+    const NAME: &'static str;
+    fn new() -> Self;
+    fn insert(&mut self, key: i64);
+    fn delete(&mut self, key: i64);
+    fn contains(&self, key: &i64) -> bool;
+    /// Number of keys from `low` to `high`, both inclusive
+    fn range(&self, low: i64, high: i64) -> usize;
+    /// Visit every key in order, returning their sum
+    fn walk(&self) -> i64;
+
+    fn from_keys(keys: &[i64]) -> Self {
+        let mut set = Self::new();
+        for &key in keys {
+            set.insert(key);
+        }
+        set
+    }
+}
+
+impl Set for Tree<i64> {
+    const NAME: &'static str = "avl";
+
+    fn new() -> Self {
+        Tree::new()
+    }
+
+    fn insert(&mut self, key: i64) {
+        Tree::insert(self, key);
+    }
+
+    fn delete(&mut self, key: i64) {
+        Tree::delete(self, key);
+    }
+
+    fn contains(&self, key: &i64) -> bool {
+        self.search(key)
+    }
+
+    fn range(&self, low: i64, high: i64) -> usize {
+        let mut cursor = self.cursor();
+        cursor.seek(&low);
+        let mut count = 0;
+        while cursor.key().is_some_and(|key| key <= high) {
+            count += 1;
+            cursor.move_next();
+        }
+        count
+    }
+
+    fn walk(&self) -> i64 {
+        self.iter().sum()
+    }
+}
+
+impl Set for RedBlackTreeSet<i64> {
+    const NAME: &'static str = "rb";
+
+    fn new() -> Self {
+        RedBlackTreeSet::new()
+    }
+
+    fn insert(&mut self, key: i64) {
+        RedBlackTreeSet::insert(self, key);
+    }
+
+    fn delete(&mut self, key: i64) {
+        RedBlackTreeSet::delete(self, key);
+    }
+
+    fn contains(&self, key: &i64) -> bool {
+        self.find(key).is_some()
+    }
+
+    fn range(&self, low: i64, high: i64) -> usize {
+        let mut cursor = self.cursor();
+        cursor.seek(&low);
+        let mut count = 0;
+        while cursor.key().is_some_and(|key| key <= high) {
+            count += 1;
+            cursor.move_next();
+        }
+        count
+    }
+
+    fn walk(&self) -> i64 {
+        self.iter().sum()
+    }
+}
+
+impl Set for BTreeSet<i64> {
+    const NAME: &'static str = "btreeset";
+
+    fn new() -> Self {
+        BTreeSet::new()
+    }
+
+    fn insert(&mut self, key: i64) {
+        BTreeSet::insert(self, key);
+    }
+
+    fn delete(&mut self, key: i64) {
+        self.remove(&key);
+    }
+
+    fn contains(&self, key: &i64) -> bool {
+        BTreeSet::contains(self, key)
+    }
+
+    fn range(&self, low: i64, high: i64) -> usize {
+        BTreeSet::range(self, low..=high).count()
+    }
+
+    fn walk(&self) -> i64 {
+        self.iter().sum()
+    }
+}
+
+fn keys(distribution: Distribution, size: usize) -> Vec<i64> {
+    Workload::new(distribution).seed(SEED).keys(size)
+}
+
+type Group<'a> = BenchmarkGroup<'a, WallTime>;
+
+fn insert<S: Set>(group: &mut Group, size: usize, keys: &[i64]) {
+    group.bench_with_input(BenchmarkId::new(S::NAME, size), keys, |b, keys| {
+        b.iter_batched(
+            S::new,
+            |mut set| {
+                for &key in keys {
+                    set.insert(black_box(key));
+                }
+                set
+            },
+            BatchSize::LargeInput,
+        );
+    });
+}
+
+fn delete<S: Set>(group: &mut Group, size: usize, keys: &[i64], doomed: &[i64]) {
+    group.bench_with_input(BenchmarkId::new(S::NAME, size), doomed, |b, doomed| {
+        b.iter_batched(
+            || S::from_keys(keys),
+            |mut set| {
+                for &key in doomed {
+                    set.delete(black_box(key));
+                }
+                set
+            },
+            BatchSize::LargeInput,
+        );
+    });
+}
+
+fn mixed<S: Set>(group: &mut Group, size: usize, ops: &[Op<i64>]) {
+    group.bench_with_input(BenchmarkId::new(S::NAME, size), ops, |b, ops| {
+        b.iter_batched(
+            S::new,
+            |mut set| {
+                for &op in ops {
+                    match op {
+                        Op::Insert(key) => set.insert(black_box(key)),
+                        Op::Delete(key) => set.delete(black_box(key)),
+                        Op::Search(key) => {
+                            black_box(set.contains(black_box(&key)));
+                        }
+                    }
+                }
+                set
+            },
+            BatchSize::LargeInput,
+        );
+    });
+}
+
+/// The measurements that only read the set
+#[derive(Clone, Copy)]
+enum Read {
+    Search,
+    Range,
+    Iterate,
+}
+
+impl Read {
+    fn name(self) -> &'static str {
+        match self {
+            Read::Search => "search",
+            Read::Range => "range",
+            Read::Iterate => "iterate",
+        }
+    }
+}
+
+/// Reads leave the set as it was, so one set per size serves every
+/// iteration
+fn reads<S: Set>(group: &mut Group, read: Read, size: usize, keys: &[i64]) {
+    let set = S::from_keys(keys);
+    let id = BenchmarkId::new(S::NAME, size);
+    match read {
+        Read::Search => group.bench_function(id, |b| {
+            b.iter(|| {
+                for &key in &keys[..size / 10] {
+                    black_box(set.contains(black_box(&key)));
+                }
+            });
+        }),
+        Read::Range => {
+            // A tenth of the key space, which uniform keys span ten times over
+            let (low, high) = (size as i64, 2 * size as i64);
+            group.bench_function(id, |b| {
+                b.iter(|| black_box(set.range(black_box(low), black_box(high))));
+            })
+        }
+        Read::Iterate => group.bench_function(id, |b| {
+            b.iter(|| black_box(set.walk()));
+        }),
+    };
+}
+
+fn inserts(c: &mut Criterion) {
+    for distribution in [Distribution::Uniform, Distribution::Sorted, Distribution::Reverse] {
+        let mut group = c.benchmark_group(format!("insert/{}", distribution));
+        for size in SIZES {
+            let keys = keys(distribution, size);
+            insert::<Tree<i64>>(&mut group, size, &keys);
+            insert::<RedBlackTreeSet<i64>>(&mut group, size, &keys);
+            insert::<BTreeSet<i64>>(&mut group, size, &keys);
+        }
+        group.finish();
+    }
+}
+
+fn deletes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("delete/uniform");
+    group.sample_size(10);
+    for size in SIZES {
+        let keys = keys(Distribution::Uniform, size);
+        // Every key, in a different random order from the inserts
+        let mut doomed = keys.clone();
+        doomed.shuffle(&mut StdRng::seed_from_u64(SEED));
+        delete::<Tree<i64>>(&mut group, size, &keys, &doomed);
+        delete::<RedBlackTreeSet<i64>>(&mut group, size, &keys, &doomed);
+        delete::<BTreeSet<i64>>(&mut group, size, &keys, &doomed);
+    }
+    group.finish();
+}
+
+fn mixes(c: &mut Criterion) {
+    for (name, mix) in [("50-25-25", Mix::new(50, 25, 25)), ("20-10-70", Mix::new(20, 10, 70))] {
+        for distribution in [Distribution::Uniform, Distribution::Zipfian] {
+            let mut group = c.benchmark_group(format!("mixed/{}/{}", distribution, name));
+            for size in SIZES {
+                let ops = Workload::new(distribution).seed(SEED).mix(mix).ops(size);
+                mixed::<Tree<i64>>(&mut group, size, &ops);
+                mixed::<RedBlackTreeSet<i64>>(&mut group, size, &ops);
+                mixed::<BTreeSet<i64>>(&mut group, size, &ops);
+            }
+            group.finish();
+        }
+    }
+}
+
+fn lookups(c: &mut Criterion) {
+    for read in [Read::Search, Read::Range, Read::Iterate] {
+        let mut group = c.benchmark_group(read.name());
+        for size in SIZES {
+            let keys = keys(Distribution::Uniform, size);
+            reads::<Tree<i64>>(&mut group, read, size, &keys);
+            reads::<RedBlackTreeSet<i64>>(&mut group, read, size, &keys);
+            reads::<BTreeSet<i64>>(&mut group, read, size, &keys);
+        }
+        group.finish();
+    }
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = inserts, deletes, mixes, lookups
+}
+criterion_main!(benches);
@@ -0,0 +1,228 @@
+//! Non-interactive mode: read commands from a script and write one JSON
+//! object per command, so scenarios can be kept as plain text files and
+//! checked from CI.
+//!
+//! ```text
+//! insert 5 3 9     {"line":1,"command":"insert","inserted":[5,3,9],"duplicates":[]}
+//! expect height 2  {"line":2,"command":"expect","ok":true,"expected":"height 2","actual":"height 2"}
+//! ```
+
+use std::fmt::Display;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+use crate::command::{Command, HELP};
+use crate::search_tree::{Key, TreeKind};
+use crate::session::{Edit, Reply, Session};
+
+/// What a script run found, used to pick the exit code
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BatchSummary {
+    pub commands: usize,
+    /// Failed `validate` and `expect` directives
+    pub failures: usize,
+    /// Lines that could not be parsed
+    pub errors: usize,
+}
+
+impl BatchSummary {
+    pub fn passed(&self) -> bool {
+        self.failures == 0 && self.errors == 0
+    }
+}
+
+/// Run every command in `input` against a new tree of type `kind`, stopping
+/// early at `exit`
+pub fn run<T>(input: impl BufRead, out: &mut dyn Write, kind: TreeKind) -> io::Result<BatchSummary>
+where
+    T: Key + FromStr + Serialize,
+{
+    let mut session: Session<T> = Session::new(kind);
+    let mut summary = BatchSummary::default();
+
+    for (index, line) in input.lines().enumerate() {
+        let line = line?;
+        let mut record = Map::new();
+        record.insert("line".to_string(), json!(index + 1));
+
+        match Command::<T>::parse(&line) {
+            Ok(None) => continue,
+            Ok(Some(command)) => {
+                summary.commands += 1;
+                record.insert("command".to_string(), json!(command.name()));
+                let reply = session.execute(command);
+                if reply.is_failure() {
+                    summary.failures += 1;
+                }
+                if reply == Reply::Exit {
+                    break;
+                }
+                add_reply(&mut record, reply);
+            }
+            Err(err) => {
+                summary.errors += 1;
+                record.insert("error".to_string(), json!(err.to_string()));
+            }
+        }
+        writeln!(out, "{}", Value::Object(record))?;
+    }
+    Ok(summary)
+}
+
+fn add_reply<T: Serialize + Display>(record: &mut Map<String, Value>, reply: Reply<T>) {
+    let mut set = |field: &str, value: Value| {
+        record.insert(field.to_string(), value);
+    };
+    match reply {
+        Reply::Inserted { inserted, duplicates } => {
+            set("inserted", json!(inserted));
+            set("duplicates", json!(duplicates));
+        }
+        Reply::Deleted { deleted, missing } => {
+            set("deleted", json!(deleted));
+            set("missing", json!(missing));
+        }
+        Reply::Found { found, missing } => {
+            set("found", json!(found));
+            set("missing", json!(missing));
+        }
+        Reply::Keys(keys) => set("keys", json!(keys)),
+        Reply::Filled { inserted, deleted, searched, found } => {
+            set("inserted", json!(inserted));
+            set("deleted", json!(deleted));
+            set("searched", json!(searched));
+            set("found", json!(found));
+        }
+        Reply::Tree(tree) => set("tree", json!(tree)),
+        Reply::Stats(stats) => set(
+            "stats",
+            json!({
+                "inserts": stats.inserts,
+                "deletes": stats.deletes,
+                "searches": stats.searches,
+                "comparisons": stats.comparisons,
+                "single_rotations": stats.single_rotations,
+                "double_rotations": stats.double_rotations,
+                "recolors": stats.recolors,
+                "allocations": stats.allocations,
+                "max_depth": stats.max_depth,
+            }),
+        ),
+        Reply::StatsReset | Reply::Cleared | Reply::Exit => (),
+        Reply::Leaves(leaves) => set("leaves", json!(leaves)),
+        Reply::Height(height) => set("height", json!(height)),
+        Reply::IsEmpty(empty) => set("empty", json!(empty)),
+        Reply::Switched(kind) => set("tree_type", json!(kind.name())),
+        Reply::Undone(edit) | Reply::Redone(edit) => set("edit", json!(edit.map(|edit| edit.to_string()))),
+        Reply::History { applied, undone } => {
+            set("applied", json!(applied.iter().map(Edit::to_string).collect::<Vec<_>>()));
+            set("undone", json!(undone.iter().map(Edit::to_string).collect::<Vec<_>>()));
+        }
+        Reply::Replayed(count) => set("replayed", json!(count)),
+        Reply::Validated(Ok(())) => set("ok", json!(true)),
+        Reply::Validated(Err(problem)) => {
+            set("ok", json!(false));
+            set("problem", json!(problem));
+        }
+        Reply::Checked { passed, expected, actual } => {
+            set("ok", json!(passed));
+            set("expected", json!(expected));
+            set("actual", json!(actual));
+        }
+        Reply::ExplainMode(explain) => set("explain", json!(explain)),
+        Reply::Explained { walkthroughs, outcome } => {
+            let walkthroughs: Vec<Value> = walkthroughs
+                .into_iter()
+                .map(|walkthrough| {
+                    let steps: Vec<Value> = walkthrough
+                        .steps
+                        .into_iter()
+                        .map(|step| json!({ "narrative": step.narrative, "diagram": step.diagram }))
+                        .collect();
+                    json!({
+                        "operation": walkthrough.operation,
+                        "before": walkthrough.before,
+                        "steps": steps,
+                        "after": walkthrough.after,
+                    })
+                })
+                .collect();
+            set("explain", json!(walkthroughs));
+            add_reply(record, *outcome);
+        }
+        Reply::Help => set("help", json!(HELP)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_script(script: &str, kind: TreeKind) -> (Vec<Value>, BatchSummary) {
+        let mut out = Vec::new();
+        let summary = run::<u32>(script.as_bytes(), &mut out, kind).unwrap();
+        let records = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        (records, summary)
+    }
+
+    #[test]
+    fn test_passing_script() {
+        let script = "\
+# build a small tree
+insert 5 3 9 3
+find 9 4
+validate
+expect inorder 3 5 9
+expect height 2
+";
+        for kind in [TreeKind::Avl, TreeKind::RedBlack] {
+            let (records, summary) = run_script(script, kind);
+            assert!(summary.passed());
+            assert_eq!(summary.commands, 5);
+            assert_eq!(
+                records[0],
+                json!({"line": 2, "command": "insert", "inserted": [5, 3, 9], "duplicates": [3]})
+            );
+            assert_eq!(records[1], json!({"line": 3, "command": "find", "found": [9], "missing": [4]}));
+            assert_eq!(records[2], json!({"line": 4, "command": "validate", "ok": true}));
+            assert_eq!(records[4]["ok"], json!(true));
+        }
+    }
+
+    #[test]
+    fn test_failures_are_reported_and_counted() {
+        let script = "insert 1 2 3\nexpect height 5\nbogus\nexpect leaves 2\n";
+        let (records, summary) = run_script(script, TreeKind::Avl);
+        assert_eq!(summary, BatchSummary { commands: 3, failures: 1, errors: 1 });
+        assert!(!summary.passed());
+        assert_eq!(
+            records[1],
+            json!({"line": 2, "command": "expect", "ok": false, "expected": "height 5", "actual": "height 2"})
+        );
+        assert_eq!(records[2]["line"], json!(3));
+        assert!(records[2]["error"].as_str().unwrap().contains("bogus"));
+    }
+
+    #[test]
+    fn test_scenarios_pass_on_both_trees() {
+        let scenario = include_str!("../scenarios/rebalance.txt");
+        for kind in [TreeKind::Avl, TreeKind::RedBlack] {
+            let (_, summary) = run_script(scenario, kind);
+            assert!(summary.passed(), "{:?} on the {}", summary, kind);
+        }
+    }
+
+    #[test]
+    fn test_exit_stops_the_script() {
+        let (records, summary) = run_script("insert 1\nexit\nexpect empty\n", TreeKind::RedBlack);
+        assert_eq!(records.len(), 1);
+        assert!(summary.passed());
+    }
+}
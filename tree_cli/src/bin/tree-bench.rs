@@ -0,0 +1,174 @@
+//! Tail latency, heap footprint and rebalancing work of both trees on
+//! generated workloads.
+//!
+//! ```text
+//! cargo run --release -p tree_cli --bin tree-bench -- --ops 100000 --dist uniform,sorted --format csv
+//! ```
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::env;
+use std::hint::black_box;
+use std::io;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use tree_cli::report::{self, Format, Latencies, Row};
+use tree_cli::search_tree::TreeKind;
+use tree_cli::workload::{Distribution, Mix, Op, Workload};
+
+/// Passes every request to the system allocator, keeping a running total of
+/// the bytes currently allocated and the most allocated at once
+struct Counting;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+impl Counting {
+    fn grow(bytes: usize) {
+        let now = CURRENT.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        PEAK.fetch_max(now, Ordering::Relaxed);
+    }
+
+    fn shrink(bytes: usize) {
+        CURRENT.fetch_sub(bytes, Ordering::Relaxed);
+    }
+
+    /// Bytes allocated now, after restarting the peak from here
+    fn reset_peak() -> usize {
+        let now = CURRENT.load(Ordering::Relaxed);
+        PEAK.store(now, Ordering::Relaxed);
+        now
+    }
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            Counting::grow(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        Counting::shrink(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            Counting::shrink(layout.size());
+            Counting::grow(new_size);
+        }
+        new_ptr
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+const USAGE: &str = "usage: tree-bench [--ops N] [--dist D[,D...]] [--mix I/D/S] [--seed S] [--tree avl|rb|both] \
+[--format markdown|csv]";
+
+struct Options {
+    ops: usize,
+    distributions: Vec<Distribution>,
+    mix: Mix,
+    seed: u64,
+    kinds: Vec<TreeKind>,
+    format: Format,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            ops: 100_000,
+            distributions: vec![Distribution::Uniform, Distribution::Sorted, Distribution::Zipfian],
+            mix: Mix::default(),
+            seed: 421,
+            kinds: vec![TreeKind::Avl, TreeKind::RedBlack],
+            format: Format::default(),
+        }
+    }
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args;
+    while let Some(arg) = args.next() {
+        if arg == "--help" || arg == "-h" {
+            return Err(USAGE.to_string());
+        }
+        let value = args.next().ok_or_else(|| format!("{} needs a value\n{}", arg, USAGE))?;
+        let number = |value: &str| value.parse().map_err(|_| format!("'{}' is not a number", value));
+        match arg.as_str() {
+            "--ops" | "-n" => options.ops = number(&value)? as usize,
+            "--dist" => options.distributions = value.split(',').map(str::parse).collect::<Result<_, _>>()?,
+            "--mix" => options.mix = value.parse()?,
+            "--seed" => options.seed = number(&value)?,
+            "--tree" => {
+                options.kinds = match value.as_str() {
+                    "both" => vec![TreeKind::Avl, TreeKind::RedBlack],
+                    kind => vec![kind.parse()?],
+                }
+            }
+            "--format" => options.format = value.parse()?,
+            _ => return Err(format!("unexpected argument '{}'\n{}", arg, USAGE)),
+        }
+    }
+    Ok(options)
+}
+
+/// Time every operation of `ops` on a fresh tree, and weigh the tree
+fn measure(kind: TreeKind, label: String, ops: &[Op<i64>]) -> Row {
+    let mut latencies = Latencies::with_capacity(ops.len());
+    let baseline = Counting::reset_peak();
+    let mut tree = kind.new_tree::<i64>();
+    for &op in ops {
+        let start = Instant::now();
+        match op {
+            Op::Insert(key) => black_box(tree.insert(key)),
+            Op::Delete(key) => black_box(tree.delete(key)),
+            Op::Search(key) => black_box(tree.contains(&key)),
+        };
+        latencies.record(start.elapsed());
+    }
+    let held = CURRENT.load(Ordering::Relaxed).saturating_sub(baseline);
+    let peak = PEAK.load(Ordering::Relaxed).saturating_sub(baseline);
+
+    let mut row = Row::new(kind.name().to_string(), label, &mut latencies);
+    row.keys = tree.keys().len();
+    row.bytes_per_key = if row.keys == 0 { 0.0 } else { held as f64 / row.keys as f64 };
+    row.peak_bytes = peak;
+    let stats = tree.stats();
+    row.rotations_per_insert = stats.rotations_per_insert();
+    row.recolors = stats.recolors;
+    row.max_depth = stats.max_depth;
+    row
+}
+
+fn main() {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            process::exit(2);
+        }
+    };
+
+    let mut rows = Vec::new();
+    for &distribution in &options.distributions {
+        let workload = Workload::new(distribution).mix(options.mix).seed(options.seed);
+        let ops = workload.ops(options.ops);
+        let label = format!("{} {}", distribution, options.mix);
+        for &kind in &options.kinds {
+            rows.push(measure(kind, label.clone(), &ops));
+        }
+    }
+    if let Err(err) = report::write_rows(&mut io::stdout().lock(), &rows, options.format) {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,357 @@
+//! The command language read by the REPL, one command per line:
+//!
+//! ```text
+//! insert 5 3 9
+//! delete 3
+//! range 2 8
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::search_tree::TreeKind;
+use crate::workload::Workload;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command<T> {
+    Insert(Vec<T>),
+    Delete(Vec<T>),
+    Find(Vec<T>),
+    /// Keys from the first bound to the second, both inclusive
+    Range(T, T),
+    /// Run `count` generated operations
+    Fill { count: usize, workload: Workload },
+    Print,
+    Stats,
+    ResetStats,
+    Clear,
+    Leaves,
+    Height,
+    InOrder,
+    IsEmpty,
+    /// Rebuild the keys in the named tree type, or the other one if `None`
+    Switch(Option<TreeKind>),
+    Undo,
+    Redo,
+    History,
+    /// Rebuild the tree from a fresh one by reapplying the history
+    Replay,
+    /// Check the tree's invariants
+    Validate,
+    Expect(Expectation<T>),
+    /// Turn step-by-step explanations of inserts and deletes on or off, or
+    /// toggle them if `None`
+    Explain(Option<bool>),
+    Help,
+    Exit,
+}
+
+/// A claim about the tree checked by `expect`
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expectation<T> {
+    /// Exactly these keys, in ascending order
+    InOrder(Vec<T>),
+    Height(usize),
+    Leaves(usize),
+    Empty(bool),
+    Found(Vec<T>),
+    Missing(Vec<T>),
+}
+
+impl<T> Command<T> {
+    /// The command's name as typed, used to label batch output
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Insert(_) => "insert",
+            Command::Delete(_) => "delete",
+            Command::Find(_) => "find",
+            Command::Range(_, _) => "range",
+            Command::Fill { .. } => "fill",
+            Command::Print => "print",
+            Command::Stats => "stats",
+            Command::ResetStats => "stats reset",
+            Command::Clear => "clear",
+            Command::Leaves => "leaves",
+            Command::Height => "height",
+            Command::InOrder => "inorder",
+            Command::IsEmpty => "empty",
+            Command::Switch(_) => "switch",
+            Command::Undo => "undo",
+            Command::Redo => "redo",
+            Command::History => "history",
+            Command::Replay => "replay",
+            Command::Validate => "validate",
+            Command::Expect(_) => "expect",
+            Command::Explain(_) => "explain",
+            Command::Help => "help",
+            Command::Exit => "exit",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseError {
+    UnknownCommand(String),
+    MissingKeys(&'static str),
+    BadKey(String),
+    WrongArgumentCount { command: &'static str, expected: usize, found: usize },
+    BadArgument(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnknownCommand(name) => write!(f, "unknown command '{}', type 'help' for a list", name),
+            ParseError::MissingKeys(command) => write!(f, "'{}' needs at least one key", command),
+            ParseError::BadKey(key) => write!(f, "'{}' is not a valid key", key),
+            ParseError::WrongArgumentCount { command, expected, found } => {
+                write!(f, "'{}' takes {} argument(s) but was given {}", command, expected, found)
+            }
+            ParseError::BadArgument(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Lines shown by `help`
+pub const HELP: &str = "\
+insert <key>...     insert one or more keys
+delete <key>...     delete one or more keys
+find <key>...       report whether each key is in the tree
+range <low> <high>  list the keys between low and high, inclusive
+fill <count> [--dist uniform|sorted|reverse|zipfian|clustered]
+     [--mix I/D/S] [--seed S] [--keys K]
+                    run generated inserts, deletes and searches
+print               draw the tree
+stats [reset]       show or reset the operation counters
+clear               remove every key
+leaves              count the leaves
+height              show the height
+inorder             list every key in ascending order
+empty               report whether the tree is empty
+switch [avl|rb]     move the keys into the other tree type
+undo                take back the last insert, delete or clear
+redo                reapply the last undone change
+history             list the changes made so far
+replay              rebuild the tree by reapplying the history
+validate            check the tree's invariants
+expect <claim>      check a claim about the tree, one of:
+                      inorder <key>..., height <n>, leaves <n>,
+                      empty true|false, find <key>..., missing <key>...
+explain [on|off]    narrate each insert and delete step by step
+help                show this list
+exit                leave (end of input works too)";
+
+impl<T: FromStr> Command<T> {
+    /// Parse one line. Blank lines and lines starting with `#` give `Ok(None)`.
+    pub fn parse(line: &str) -> Result<Option<Command<T>>, ParseError> {
+        let mut words = line.split_whitespace();
+        let name = match words.next() {
+            None => return Ok(None),
+            Some(name) if name.starts_with('#') => return Ok(None),
+            Some(name) => name,
+        };
+        let args: Vec<&str> = words.collect();
+
+        let command = match name.to_ascii_lowercase().as_str() {
+            "insert" | "i" => Command::Insert(keys("insert", &args)?),
+            "delete" | "d" => Command::Delete(keys("delete", &args)?),
+            "find" | "f" => Command::Find(keys("find", &args)?),
+            "range" => {
+                let bounds = fixed("range", &args, 2)?;
+                Command::Range(key(bounds[0])?, key(bounds[1])?)
+            }
+            "fill" => fill(&args)?,
+            "print" | "p" => no_args(Command::Print, "print", &args)?,
+            "stats" => match args.as_slice() {
+                [] => Command::Stats,
+                ["reset"] => Command::ResetStats,
+                _ => return Err(ParseError::BadArgument("usage: stats [reset]".to_string())),
+            },
+            "clear" => no_args(Command::Clear, "clear", &args)?,
+            "leaves" => no_args(Command::Leaves, "leaves", &args)?,
+            "height" => no_args(Command::Height, "height", &args)?,
+            "inorder" => no_args(Command::InOrder, "inorder", &args)?,
+            "empty" => no_args(Command::IsEmpty, "empty", &args)?,
+            "switch" => match args.as_slice() {
+                [] => Command::Switch(None),
+                [kind] => Command::Switch(Some(kind.parse().map_err(ParseError::BadArgument)?)),
+                _ => return Err(ParseError::WrongArgumentCount { command: "switch", expected: 1, found: args.len() }),
+            },
+            "undo" | "u" => no_args(Command::Undo, "undo", &args)?,
+            "redo" | "r" => no_args(Command::Redo, "redo", &args)?,
+            "history" => no_args(Command::History, "history", &args)?,
+            "replay" => no_args(Command::Replay, "replay", &args)?,
+            "validate" => no_args(Command::Validate, "validate", &args)?,
+            "expect" => Command::Expect(expectation(&args)?),
+            "explain" => match args.as_slice() {
+                [] => Command::Explain(None),
+                ["on"] => Command::Explain(Some(true)),
+                ["off"] => Command::Explain(Some(false)),
+                _ => return Err(ParseError::BadArgument("usage: explain [on|off]".to_string())),
+            },
+            "help" | "?" => no_args(Command::Help, "help", &args)?,
+            "exit" | "quit" | "q" => no_args(Command::Exit, "exit", &args)?,
+            _ => return Err(ParseError::UnknownCommand(name.to_string())),
+        };
+        Ok(Some(command))
+    }
+}
+
+fn expectation<T: FromStr>(args: &[&str]) -> Result<Expectation<T>, ParseError> {
+    let (claim, rest) = match args.split_first() {
+        Some(split) => split,
+        None => return Err(ParseError::BadArgument("usage: expect <claim> [values]".to_string())),
+    };
+    let count = |command| -> Result<usize, ParseError> {
+        let value = fixed(command, rest, 1)?[0];
+        value.parse().map_err(|_| ParseError::BadArgument(format!("'{}' is not a count", value)))
+    };
+    match claim.to_ascii_lowercase().as_str() {
+        "inorder" => rest.iter().map(|word| key(word)).collect::<Result<_, _>>().map(Expectation::InOrder),
+        "height" => count("expect height").map(Expectation::Height),
+        "leaves" => count("expect leaves").map(Expectation::Leaves),
+        "empty" => match rest {
+            [] | ["true"] => Ok(Expectation::Empty(true)),
+            ["false"] => Ok(Expectation::Empty(false)),
+            _ => Err(ParseError::BadArgument("usage: expect empty [true|false]".to_string())),
+        },
+        "find" => keys("expect find", rest).map(Expectation::Found),
+        "missing" => keys("expect missing", rest).map(Expectation::Missing),
+        _ => Err(ParseError::BadArgument(format!("unknown claim '{}'", claim))),
+    }
+}
+
+/// Most operations one `fill` may run
+const MAX_FILL: usize = 10_000_000;
+
+fn fill<T>(args: &[&str]) -> Result<Command<T>, ParseError> {
+    let (count, options) = match args.split_first() {
+        Some(split) => split,
+        None => return Err(ParseError::BadArgument("usage: fill <count> [options]".to_string())),
+    };
+    let number = |word: &str| -> Result<u64, ParseError> {
+        word.parse().map_err(|_| ParseError::BadArgument(format!("'{}' is not a number", word)))
+    };
+    let count = match number(count)? {
+        count if count <= MAX_FILL as u64 => count as usize,
+        _ => return Err(ParseError::BadArgument(format!("fill runs at most {} operations", MAX_FILL))),
+    };
+    let mut workload = Workload::default();
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        let value = match options.next() {
+            Some(value) => *value,
+            None => return Err(ParseError::BadArgument(format!("'{}' needs a value", option))),
+        };
+        match *option {
+            "--dist" => workload.distribution = value.parse().map_err(ParseError::BadArgument)?,
+            "--mix" => workload.mix = value.parse().map_err(ParseError::BadArgument)?,
+            "--seed" => workload.seed = number(value)?,
+            "--keys" => workload = workload.key_space(number(value)?.max(1)),
+            _ => return Err(ParseError::BadArgument(format!("unknown fill option '{}'", option))),
+        }
+    }
+    Ok(Command::Fill { count, workload })
+}
+
+fn key<T: FromStr>(word: &str) -> Result<T, ParseError> {
+    word.parse().map_err(|_| ParseError::BadKey(word.to_string()))
+}
+
+fn keys<T: FromStr>(command: &'static str, args: &[&str]) -> Result<Vec<T>, ParseError> {
+    if args.is_empty() {
+        return Err(ParseError::MissingKeys(command));
+    }
+    args.iter().map(|word| key(word)).collect()
+}
+
+fn fixed<'a>(command: &'static str, args: &[&'a str], expected: usize) -> Result<Vec<&'a str>, ParseError> {
+    if args.len() != expected {
+        return Err(ParseError::WrongArgumentCount { command, expected, found: args.len() });
+    }
+    Ok(args.to_vec())
+}
+
+fn no_args<T>(command: Command<T>, name: &'static str, args: &[&str]) -> Result<Command<T>, ParseError> {
+    fixed(name, args, 0).map(|_| command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<Option<Command<u32>>, ParseError> {
+        Command::parse(line)
+    }
+
+    #[test]
+    fn test_parse_multi_key_commands() {
+        assert_eq!(parse("insert 5 3 9"), Ok(Some(Command::Insert(vec![5, 3, 9]))));
+        assert_eq!(parse("  DELETE   3 "), Ok(Some(Command::Delete(vec![3]))));
+        assert_eq!(parse("find 9"), Ok(Some(Command::Find(vec![9]))));
+        assert_eq!(parse("range 2 8"), Ok(Some(Command::Range(2, 8))));
+    }
+
+    #[test]
+    fn test_parse_commands_without_keys() {
+        assert_eq!(parse("print"), Ok(Some(Command::Print)));
+        assert_eq!(parse("stats"), Ok(Some(Command::Stats)));
+        assert_eq!(parse("stats reset"), Ok(Some(Command::ResetStats)));
+        assert_eq!(parse("switch"), Ok(Some(Command::Switch(None))));
+        assert_eq!(parse("switch avl"), Ok(Some(Command::Switch(Some(TreeKind::Avl)))));
+        assert_eq!(parse("explain"), Ok(Some(Command::Explain(None))));
+        assert_eq!(parse("explain off"), Ok(Some(Command::Explain(Some(false)))));
+        assert!(parse("explain maybe").is_err());
+        assert_eq!(parse(""), Ok(None));
+        assert_eq!(parse("# a comment"), Ok(None));
+    }
+
+    #[test]
+    fn test_parse_directives() {
+        assert_eq!(parse("validate"), Ok(Some(Command::Validate)));
+        assert_eq!(parse("expect inorder 1 2 3"), Ok(Some(Command::Expect(Expectation::InOrder(vec![1, 2, 3])))));
+        assert_eq!(parse("expect inorder"), Ok(Some(Command::Expect(Expectation::InOrder(vec![])))));
+        assert_eq!(parse("expect height 3"), Ok(Some(Command::Expect(Expectation::Height(3)))));
+        assert_eq!(parse("expect empty false"), Ok(Some(Command::Expect(Expectation::Empty(false)))));
+        assert_eq!(parse("expect missing 4 5"), Ok(Some(Command::Expect(Expectation::Missing(vec![4, 5])))));
+        assert!(parse("expect").is_err());
+        assert!(parse("expect height").is_err());
+        assert!(parse("expect tall 3").is_err());
+    }
+
+    #[test]
+    fn test_parse_fill() {
+        use crate::workload::{Distribution, Mix};
+
+        assert_eq!(parse("fill 100"), Ok(Some(Command::Fill { count: 100, workload: Workload::default() })));
+        let workload = Workload::new(Distribution::Zipfian).mix(Mix::new(70, 20, 10)).seed(7);
+        assert_eq!(
+            parse("fill 50 --seed 7 --dist zipfian --mix 70/20/10"),
+            Ok(Some(Command::Fill { count: 50, workload }))
+        );
+        assert!(parse("fill").is_err());
+        assert!(parse("fill 10 --seed").is_err());
+        assert!(parse("fill 10 --dist wavy").is_err());
+        assert!(parse("fill 10 --color red").is_err());
+        assert!(parse("fill 18446744073709551615").is_err());
+        assert!(parse("fill 10 --mix 4294967295/1/0").is_err());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse("insert"), Err(ParseError::MissingKeys("insert")));
+        assert_eq!(parse("insert 5 x"), Err(ParseError::BadKey("x".to_string())));
+        assert_eq!(parse("delete -1"), Err(ParseError::BadKey("-1".to_string())));
+        assert_eq!(
+            parse("range 2"),
+            Err(ParseError::WrongArgumentCount { command: "range", expected: 2, found: 1 })
+        );
+        assert_eq!(
+            parse("print 3"),
+            Err(ParseError::WrongArgumentCount { command: "print", expected: 0, found: 1 })
+        );
+        assert_eq!(parse("frobnicate"), Err(ParseError::UnknownCommand("frobnicate".to_string())));
+    }
+}
@@ -0,0 +1,217 @@
+//! Compare mode: every command runs against an AVL tree and a red-black tree
+//! holding the same keys, and the two are shown side by side with what each
+//! operation cost.
+
+use std::fmt::{self, Display};
+
+use crate::command::Command;
+use crate::search_tree::{Key, TreeKind};
+use crate::session::{Reply, Session};
+use tree_core::stats::TreeStats;
+
+/// Shape of one tree after a command, and the work the command did on it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Metrics {
+    pub height: usize,
+    pub leaves: usize,
+    /// Rotations made by this command, a double rotation counting as two
+    pub rotations: u64,
+    /// Key comparisons made by this command
+    pub comparisons: u64,
+}
+
+impl Metrics {
+    fn measure<T: Key>(session: &Session<T>, before: TreeStats) -> Self {
+        let tree = session.tree();
+        let after = tree.stats();
+        Metrics {
+            height: tree.height(),
+            leaves: tree.leaves(),
+            // Undo and replay rebuild the tree, restarting its counters
+            rotations: after.rotations().saturating_sub(before.rotations()),
+            comparisons: after.comparisons.saturating_sub(before.comparisons),
+        }
+    }
+}
+
+/// One command's outcome on both trees
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comparison<T> {
+    pub avl: Reply<T>,
+    pub red_black: Reply<T>,
+    /// Both trees drawn top-down, for commands that change or print the tree
+    pub diagrams: Option<(String, String)>,
+    pub avl_metrics: Metrics,
+    pub red_black_metrics: Metrics,
+}
+
+/// An AVL session and a red-black session fed the same commands
+pub struct Compare<T: Key> {
+    avl: Session<T>,
+    red_black: Session<T>,
+}
+
+impl<T: Key> Default for Compare<T> {
+    fn default() -> Self {
+        Compare::new()
+    }
+}
+
+impl<T: Key> Compare<T> {
+    pub fn new() -> Self {
+        Compare { avl: Session::new(TreeKind::Avl), red_black: Session::new(TreeKind::RedBlack) }
+    }
+
+    pub fn avl(&self) -> &Session<T> {
+        &self.avl
+    }
+
+    pub fn red_black(&self) -> &Session<T> {
+        &self.red_black
+    }
+
+    /// Run `command` on both trees. `switch` is refused, since each side
+    /// keeps its own tree type.
+    pub fn execute(&mut self, command: Command<T>) -> Result<Comparison<T>, String> {
+        if let Command::Switch(_) = command {
+            return Err("switch is not available in compare mode".to_string());
+        }
+        let drawn = matches!(
+            command,
+            Command::Insert(_)
+                | Command::Delete(_)
+                | Command::Fill { .. }
+                | Command::Clear
+                | Command::Undo
+                | Command::Redo
+                | Command::Replay
+                | Command::Print
+        );
+
+        let (avl_before, red_black_before) = (self.avl.tree().stats(), self.red_black.tree().stats());
+        let avl = self.avl.execute(command.clone());
+        let red_black = self.red_black.execute(command);
+        let diagrams = drawn.then(|| (self.avl.render_diagram(), self.red_black.render_diagram()));
+        Ok(Comparison {
+            avl,
+            red_black,
+            diagrams,
+            avl_metrics: Metrics::measure(&self.avl, avl_before),
+            red_black_metrics: Metrics::measure(&self.red_black, red_black_before),
+        })
+    }
+}
+
+/// `left` and `right` as two columns separated by ` | `
+pub fn side_by_side(left: &str, right: &str) -> String {
+    let width = left.lines().map(|line| line.chars().count()).max().unwrap_or(0);
+    let (left, right): (Vec<&str>, Vec<&str>) = (left.lines().collect(), right.lines().collect());
+    let mut out = String::new();
+    for row in 0..left.len().max(right.len()) {
+        let left = left.get(row).copied().unwrap_or("");
+        let right = right.get(row).copied().unwrap_or("");
+        let line = format!("{:width$} | {}", left, right, width = width);
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// The replies, the diagrams, then a table of the metrics. A reply both
+/// trees agree on is printed once, above the columns.
+impl<T: Display + PartialEq> Display for Comparison<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.avl {
+            Reply::Exit => return Ok(()),
+            Reply::Help => return write!(f, "{}", self.avl),
+            _ => (),
+        }
+        let mut left = format!("{}\n", TreeKind::Avl);
+        let mut right = format!("{}\n", TreeKind::RedBlack);
+        let mut columns = false;
+        if let Reply::Tree(_) = self.avl {
+            // `print` replies with the sideways drawing; the diagrams replace it
+        } else if self.avl == self.red_black {
+            write!(f, "{}", self.avl)?;
+        } else {
+            left.push_str(&self.avl.to_string());
+            right.push_str(&self.red_black.to_string());
+            columns = true;
+        }
+        if let Some((avl, red_black)) = &self.diagrams {
+            left.push_str(avl);
+            right.push_str(red_black);
+            columns = true;
+        }
+        if columns {
+            write!(f, "{}", side_by_side(&left, &right))?;
+        }
+
+        let (avl, red_black) = (&self.avl_metrics, &self.red_black_metrics);
+        writeln!(f, "{:<12} {:>9} {:>9}", "", "avl", "rb")?;
+        writeln!(f, "{:<12} {:>9} {:>9}", "height", avl.height, red_black.height)?;
+        writeln!(f, "{:<12} {:>9} {:>9}", "leaves", avl.leaves, red_black.leaves)?;
+        writeln!(f, "{:<12} {:>9} {:>9}", "rotations", avl.rotations, red_black.rotations)?;
+        writeln!(f, "{:<12} {:>9} {:>9}", "comparisons", avl.comparisons, red_black.comparisons)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(compare: &mut Compare<u32>, line: &str) -> Comparison<u32> {
+        compare.execute(Command::parse(line).unwrap().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_sorted_inserts_cost_each_tree_differently() {
+        let mut compare = Compare::new();
+        let comparison = run(&mut compare, "insert 1 2 3 4 5 6 7");
+        assert_eq!(comparison.avl, comparison.red_black);
+        assert_eq!(
+            comparison.avl_metrics,
+            Metrics { height: 3, leaves: 4, rotations: 4, comparisons: 14 }
+        );
+        assert_eq!(
+            comparison.red_black_metrics,
+            Metrics { height: 4, leaves: 4, rotations: 3, comparisons: 15 }
+        );
+        assert!(comparison.diagrams.is_some());
+
+        // Only the command's own work is counted
+        let comparison = run(&mut compare, "find 7");
+        assert_eq!(comparison.avl_metrics.comparisons, 3);
+        assert_eq!(comparison.avl_metrics.rotations, 0);
+        assert_eq!(comparison.diagrams, None);
+        assert_eq!(compare.avl().tree().keys(), compare.red_black().tree().keys());
+
+        assert!(run(&mut compare, "fill 20").diagrams.is_some());
+        assert_eq!(compare.avl().tree().keys(), compare.red_black().tree().keys());
+    }
+
+    #[test]
+    fn test_switch_is_refused() {
+        let mut compare: Compare<u32> = Compare::new();
+        assert!(compare.execute(Command::Switch(None)).is_err());
+        assert_eq!(compare.avl().tree().kind(), TreeKind::Avl);
+    }
+
+    #[test]
+    fn test_side_by_side() {
+        assert_eq!(side_by_side("a\nlonger\n", "x\n"), "a      | x\nlonger |\n");
+        assert_eq!(side_by_side("", "x\ny\n"), " | x\n | y\n");
+    }
+
+    #[test]
+    fn test_comparison_text() {
+        let mut compare = Compare::new();
+        run(&mut compare, "insert 2 1");
+        let text = run(&mut compare, "find 1").to_string();
+        assert!(text.starts_with("found 1\n            "), "{}", text);
+        assert!(text.contains("comparisons          2         2\n"), "{}", text);
+
+        let text = run(&mut compare, "insert 3").to_string();
+        assert!(text.starts_with("AVL tree | red-black tree\n"), "{}", text);
+    }
+}
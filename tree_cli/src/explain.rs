@@ -0,0 +1,208 @@
+//! Turns the trace events each tree records while it runs into a narrated
+//! walkthrough for the `explain` command. Every case and rotation named here
+//! was reported by the tree's own `rebalance`, `fix` or `fix_delete`.
+
+use std::fmt::Display;
+
+use avl_trees::trace::{self as avl, Direction, RebalanceCase};
+use red_black_trees::rbt::NodeColor;
+use red_black_trees::trace::{self as rb, DeleteCase, InsertCase};
+
+/// One stage of an operation: what happened, and the tree afterwards when
+/// the tree took a snapshot
+#[derive(Clone, Debug, PartialEq)]
+pub struct Step {
+    pub narrative: String,
+    pub diagram: Option<String>,
+}
+
+impl Step {
+    fn said(narrative: String) -> Self {
+        Step { narrative, diagram: None }
+    }
+}
+
+fn rotation(direction: Direction, pivot: impl Display) -> String {
+    match direction {
+        Direction::Left => format!("rotate_left({})", pivot),
+        Direction::Right => format!("rotate_right({})", pivot),
+    }
+}
+
+fn search_path<T: Display>(nodes: &[T]) -> Option<Step> {
+    if nodes.is_empty() {
+        return None;
+    }
+    let path: Vec<String> = nodes.iter().map(T::to_string).collect();
+    Some(Step::said(format!("search path: {}", path.join(" -> "))))
+}
+
+/// Steps for an AVL `insert_explained` or `delete_explained`. Each rotation
+/// is followed by a diagram of the subtree it rebuilt.
+pub fn avl_steps<T: Display>(events: Vec<avl::TraceEvent<T>>) -> Vec<Step> {
+    let mut compared = Vec::new();
+    let mut steps = Vec::new();
+    let mut rotations = Vec::new();
+    for event in &events {
+        if let avl::TraceEvent::Rotate { direction, pivot } = event {
+            rotations.push(rotation(*direction, pivot));
+        }
+    }
+    let mut rotations = rotations.into_iter();
+    let mut last_rotation = String::new();
+
+    for event in events {
+        match event {
+            avl::TraceEvent::Compare { node, .. } => compared.push(node),
+            avl::TraceEvent::Rebalance { case, node } => {
+                let (name, count) = match case {
+                    RebalanceCase::LeftLeft => ("left-left", 1),
+                    RebalanceCase::LeftRight => ("left-right", 2),
+                    RebalanceCase::RightRight => ("right-right", 1),
+                    RebalanceCase::RightLeft => ("right-left", 2),
+                };
+                let planned: Vec<String> = rotations.by_ref().take(count).collect();
+                steps.push(Step::said(format!("{} case at {}: {}", name, node, planned.join(", then "))));
+            }
+            avl::TraceEvent::Rotate { direction, pivot } => last_rotation = rotation(direction, pivot),
+            avl::TraceEvent::Snapshot { diagram } => steps.push(Step {
+                narrative: format!("tree after {}", last_rotation),
+                diagram: Some(diagram),
+            }),
+        }
+    }
+    search_path(&compared).into_iter().chain(steps).collect()
+}
+
+fn insert_case(case: InsertCase, node: impl Display) -> String {
+    match case {
+        InsertCase::RedUncle => format!("uncle of {} is red: recolor parent, uncle and grandparent", node),
+        InsertCase::Triangle => format!("{} is an inner grandchild (triangle case)", node),
+        InsertCase::Line => format!("{} is an outer grandchild (line case)", node),
+    }
+}
+
+fn delete_case(case: DeleteCase, parent: impl Display) -> String {
+    match case {
+        DeleteCase::RedSibling => format!("sibling below {} is red", parent),
+        DeleteCase::BlackSiblingBlackChildren => {
+            format!("sibling below {} is black with black children: move the extra black up", parent)
+        }
+        DeleteCase::BlackSiblingRedNearChild => format!("sibling below {} is black with a red near child", parent),
+        DeleteCase::BlackSiblingRedFarChild => format!("sibling below {} is black with a red far child", parent),
+    }
+}
+
+fn color_name(color: &NodeColor) -> &'static str {
+    match color {
+        NodeColor::Red => "red",
+        NodeColor::Black => "black",
+    }
+}
+
+/// Steps for a red-black `insert_explained` or `delete_explained` of `key`.
+/// Each fix-up case ends with a diagram of the whole tree, as does the
+/// unlinking done by a delete.
+pub fn red_black_steps<T: Display>(key: &T, events: Vec<rb::TraceEvent<T>>) -> Vec<Step> {
+    let mut compared = Vec::new();
+    let mut steps = Vec::new();
+    let mut heading: Option<String> = None;
+    let mut actions: Vec<String> = Vec::new();
+
+    let narrate = |heading: Option<String>, actions: &mut Vec<String>| {
+        let heading = heading.unwrap_or_else(|| format!("unlink {}", key));
+        if actions.is_empty() {
+            heading
+        } else {
+            format!("{}: {}", heading, std::mem::take(actions).join(", then "))
+        }
+    };
+
+    for event in events {
+        match event {
+            rb::TraceEvent::Compare { node, .. } => compared.push(node),
+            rb::TraceEvent::InsertFix { case, node } => heading = Some(insert_case(case, node)),
+            rb::TraceEvent::DeleteFix { case, parent } => heading = Some(delete_case(case, parent)),
+            rb::TraceEvent::Recolor { node, to, .. } => actions.push(format!("color {} {}", node, color_name(&to))),
+            rb::TraceEvent::Rotate { direction, pivot } => actions.push(rotation(direction, pivot)),
+            rb::TraceEvent::Snapshot { diagram } => steps.push(Step {
+                narrative: narrate(heading.take(), &mut actions),
+                diagram: Some(diagram),
+            }),
+        }
+    }
+    // Recolors after the last case, such as blackening the root
+    if !actions.is_empty() {
+        steps.push(Step::said(format!("finally {}", actions.join(", then "))));
+    }
+    search_path(&compared).into_iter().chain(steps).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use avl_trees::tree::Tree;
+    use red_black_trees::rbt::RedBlackTreeSet;
+
+    #[test]
+    fn test_avl_double_rotation_narrative() {
+        let mut tree = Tree::new();
+        for key in [30, 20] {
+            tree.insert(key);
+        }
+        let (_, events) = tree.insert_explained(25);
+        let steps = avl_steps(events);
+        let narratives: Vec<&str> = steps.iter().map(|step| step.narrative.as_str()).collect();
+        assert_eq!(
+            narratives,
+            [
+                "search path: 30 -> 20",
+                "left-right case at 30: rotate_left(20), then rotate_right(30)",
+                "tree after rotate_left(20)",
+                "tree after rotate_right(30)",
+            ]
+        );
+        assert_eq!(steps[3].diagram.as_deref(), Some(&*tree.diagram(&Default::default())));
+    }
+
+    #[test]
+    fn test_red_black_red_uncle_narrative() {
+        let mut tree = RedBlackTreeSet::new();
+        for key in [20, 10, 30] {
+            tree.insert(key);
+        }
+        let (_, events) = tree.insert_explained(5);
+        let steps = red_black_steps(&5, events);
+        let narratives: Vec<&str> = steps.iter().map(|step| step.narrative.as_str()).collect();
+        assert_eq!(
+            narratives,
+            [
+                "search path: 20 -> 10",
+                "uncle of 5 is red: recolor parent, uncle and grandparent: \
+                 color 30 black, then color 10 black, then color 20 red",
+                "finally color 20 black",
+            ]
+        );
+        assert!(steps[1].diagram.is_some());
+    }
+
+    #[test]
+    fn test_red_black_delete_narrative() {
+        let mut tree = RedBlackTreeSet::new();
+        for key in [20, 10, 30, 40] {
+            tree.insert(key);
+        }
+        tree.delete(40);
+        let (_, events) = tree.delete_explained(10);
+        let steps = red_black_steps(&10, events);
+        let narratives: Vec<&str> = steps.iter().map(|step| step.narrative.as_str()).collect();
+        assert_eq!(
+            narratives,
+            [
+                "search path: 20 -> 10",
+                "unlink 10",
+                "sibling below 20 is black with black children: move the extra black up: color 30 red",
+            ]
+        );
+    }
+}
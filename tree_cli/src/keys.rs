@@ -0,0 +1,139 @@
+//! Key types the CLI can be started with, selected by `--key-type`.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::num::ParseFloatError;
+use std::str::FromStr;
+
+use serde::{Serialize, Serializer};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyType {
+    #[default]
+    I64,
+    U64,
+    String,
+    F64,
+}
+
+impl FromStr for KeyType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "i64" | "int" => Ok(KeyType::I64),
+            "u64" | "uint" => Ok(KeyType::U64),
+            "string" | "str" => Ok(KeyType::String),
+            "f64" | "float" => Ok(KeyType::F64),
+            other => Err(format!("unknown key type '{}', expected i64, u64, string or f64", other)),
+        }
+    }
+}
+
+impl fmt::Display for KeyType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyType::I64 => write!(f, "i64"),
+            KeyType::U64 => write!(f, "u64"),
+            KeyType::String => write!(f, "string"),
+            KeyType::F64 => write!(f, "f64"),
+        }
+    }
+}
+
+/// An `f64` ordered by `f64::total_cmp`, so floats can be tree keys.
+/// `-0.0` sorts before `0.0`. Parsing turns every NaN, `-nan` included, into
+/// the one positive NaN, which sorts after every other value.
+#[derive(Clone, Copy)]
+pub struct TotalF64(pub f64);
+
+impl PartialEq for TotalF64 {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for TotalF64 {}
+
+impl PartialOrd for TotalF64 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TotalF64 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// Prints like a bare `f64`, since the tree printers show keys with `Debug`
+impl fmt::Debug for TotalF64 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
+impl fmt::Display for TotalF64 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for TotalF64 {
+    type Err = ParseFloatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value: f64 = s.parse()?;
+        Ok(TotalF64(if value.is_nan() { f64::NAN } else { value }))
+    }
+}
+
+/// Non-finite values go out as the strings `Display` prints, since JSON
+/// has no number for them
+impl Serialize for TotalF64 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.0.is_finite() {
+            serializer.serialize_f64(self.0)
+        } else {
+            serializer.collect_str(self)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_total_order() {
+        let mut keys: Vec<TotalF64> = ["2.5", "-1", "NaN", "0", "-0", "1e3"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        keys.sort();
+        let printed: Vec<String> = keys.iter().map(TotalF64::to_string).collect();
+        assert_eq!(printed, ["-1", "-0", "0", "2.5", "1000", "NaN"]);
+        assert_eq!(TotalF64(f64::NAN), TotalF64(f64::NAN));
+        assert_eq!(format!("{:?}", TotalF64(2.0)), "2.0");
+        assert_ne!(TotalF64(0.0), TotalF64(-0.0));
+    }
+
+    #[test]
+    fn test_nan_and_infinities() {
+        let mut keys: Vec<TotalF64> = ["-nan", "inf", "nan", "1", "-inf"].iter().map(|s| s.parse().unwrap()).collect();
+        assert_eq!(keys[0], keys[2]);
+        keys.sort();
+        keys.dedup();
+        let printed: Vec<String> = keys.iter().map(TotalF64::to_string).collect();
+        assert_eq!(printed, ["-inf", "1", "inf", "NaN"]);
+        assert_eq!(serde_json::to_string(&keys).unwrap(), r#"["-inf",1.0,"inf","NaN"]"#);
+    }
+
+    #[test]
+    fn test_parse_key_type() {
+        assert_eq!("String".parse(), Ok(KeyType::String));
+        assert_eq!("f64".parse(), Ok(KeyType::F64));
+        assert!("u8".parse::<KeyType>().is_err());
+    }
+}
@@ -0,0 +1,10 @@
+pub mod batch;
+pub mod command;
+pub mod compare;
+pub mod explain;
+pub mod keys;
+pub mod recording;
+pub mod report;
+pub mod search_tree;
+pub mod session;
+pub mod workload;
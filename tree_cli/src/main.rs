@@ -0,0 +1,256 @@
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process;
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use tree_cli::batch;
+use tree_cli::command::Command;
+use tree_cli::compare::Compare;
+use tree_cli::keys::{KeyType, TotalF64};
+use tree_cli::recording::{self, ReplayOptions};
+use tree_cli::search_tree::{Key, TreeKind};
+use tree_cli::session::{Reply, Session};
+
+/// Print `prompt` and read one line; `None` once stdin is closed
+fn prompt(input: &mut impl BufRead, prompt: &str) -> Option<String> {
+    print!("{}", prompt);
+    io::stdout().flush().unwrap();
+
+    let mut line = String::new();
+    match input.read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line),
+    }
+}
+
+/// Prompt until a line parses as a command; `None` once stdin is closed
+fn next_command<T: FromStr>(input: &mut impl BufRead) -> Option<Command<T>> {
+    loop {
+        let line = match prompt(input, "> ") {
+            Some(line) => line,
+            None => {
+                // End of input: finish the prompt line and leave
+                println!();
+                return None;
+            }
+        };
+        match Command::parse(&line) {
+            Ok(Some(command)) => return Some(command),
+            Ok(None) => (),
+            Err(err) => println!("error: {}", err),
+        }
+    }
+}
+
+const USAGE: &str = "usage: tree_cli [avl|rb|--compare] [--key-type i64|u64|string|f64] [--batch [FILE]]
+                [--replay FILE [--stop-at-first]]";
+
+#[derive(Debug, Default)]
+struct Options {
+    kind: Option<TreeKind>,
+    key_type: KeyType,
+    /// Run as a script instead of a REPL, reading FILE or, if `None`, stdin
+    batch: Option<Option<String>>,
+    /// Drive an AVL and a red-black tree side by side
+    compare: bool,
+    /// Replay a recorded operation trace
+    replay: Option<String>,
+    /// Stop a replay at the first divergence
+    stop_at_first: bool,
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.peekable();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--batch" | "-b" => {
+                let file = args.next_if(|next| !next.starts_with('-') || next == "-");
+                options.batch = Some(file.filter(|file| file != "-"));
+            }
+            "--compare" | "-c" => options.compare = true,
+            "--replay" => match args.next() {
+                Some(file) => options.replay = Some(file),
+                None => return Err(format!("--replay needs a trace file\n{}", USAGE)),
+            },
+            "--stop-at-first" => options.stop_at_first = true,
+            "--key-type" | "-k" => match args.next() {
+                Some(key_type) => options.key_type = key_type.parse()?,
+                None => return Err(format!("--key-type needs a value\n{}", USAGE)),
+            },
+            "--help" | "-h" => return Err(USAGE.to_string()),
+            _ if options.kind.is_none() => options.kind = Some(arg.parse()?),
+            _ => return Err(format!("unexpected argument '{}'\n{}", arg, USAGE)),
+        }
+    }
+    if options.compare && (options.kind.is_some() || options.batch.is_some()) {
+        return Err(format!("--compare runs both trees interactively\n{}", USAGE));
+    }
+    if options.replay.is_some() && (options.compare || options.batch.is_some()) {
+        return Err(format!("--replay cannot be combined with --compare or --batch\n{}", USAGE));
+    }
+    if options.stop_at_first && options.replay.is_none() {
+        return Err(format!("--stop-at-first only applies to --replay\n{}", USAGE));
+    }
+    Ok(options)
+}
+
+/// Run a script, exiting with 1 if any `validate` or `expect` failed or any
+/// line could not be parsed
+fn run_batch<T: Key + FromStr + Serialize>(kind: TreeKind, file: Option<String>) -> ! {
+    let input: Box<dyn BufRead> = match file {
+        Some(path) => match File::open(&path) {
+            Ok(file) => Box::new(BufReader::new(file)),
+            Err(err) => {
+                eprintln!("cannot open {}: {}", path, err);
+                process::exit(2);
+            }
+        },
+        None => Box::new(io::stdin().lock()),
+    };
+    let stdout = io::stdout();
+    match batch::run::<T>(input, &mut stdout.lock(), kind) {
+        Ok(summary) if summary.passed() => process::exit(0),
+        Ok(summary) => {
+            eprintln!("{} check(s) failed, {} line(s) could not be parsed", summary.failures, summary.errors);
+            process::exit(1);
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(2);
+        }
+    }
+}
+
+/// Replay a trace on the chosen tree, or on each tree if none was chosen,
+/// exiting with 1 if any replay diverged
+fn run_replay<T: Key>(kind: Option<TreeKind>, path: &str, options: &ReplayOptions) -> ! {
+    let kinds = match kind {
+        Some(kind) => vec![kind],
+        None => vec![TreeKind::Avl, TreeKind::RedBlack],
+    };
+    let mut passed = true;
+    for kind in kinds {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(err) => {
+                eprintln!("cannot open {}: {}", path, err);
+                process::exit(2);
+            }
+        };
+        let mut tree = kind.new_tree::<T>();
+        let report = match recording::replay(tree.as_mut(), BufReader::new(file), options) {
+            Ok(report) => report,
+            Err(err) => {
+                eprintln!("cannot read {}: {}", path, err);
+                process::exit(2);
+            }
+        };
+        for divergence in &report.divergences {
+            println!("{}: {}", kind.name(), divergence);
+        }
+        println!(
+            "{}: replayed {} operation(s), {} divergence(s)",
+            kind.name(),
+            report.operations,
+            report.divergences.len()
+        );
+        passed &= report.passed();
+    }
+    process::exit(if passed { 0 } else { 1 });
+}
+
+fn choose_kind(input: &mut impl BufRead) -> Option<TreeKind> {
+    loop {
+        match prompt(input, "Which tree would you like to use (avl/rb)? ")?.parse() {
+            Ok(kind) => return Some(kind),
+            Err(err) => println!("{}", err),
+        }
+    }
+}
+
+fn main() {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            process::exit(2);
+        }
+    };
+    match options.key_type {
+        KeyType::I64 => run::<i64>(options),
+        KeyType::U64 => run::<u64>(options),
+        KeyType::String => run::<String>(options),
+        KeyType::F64 => run::<TotalF64>(options),
+    }
+}
+
+fn run<T: Key + FromStr + Serialize>(options: Options) {
+    if let Some(path) = &options.replay {
+        let replay = ReplayOptions { stop_at_first: options.stop_at_first, ..ReplayOptions::default() };
+        run_replay::<T>(options.kind, path, &replay);
+    }
+    if let Some(file) = options.batch {
+        run_batch::<T>(options.kind.unwrap_or(TreeKind::Avl), file);
+    }
+
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    if options.compare {
+        return run_compare::<T>(&mut input, options.key_type);
+    }
+    let kind = match options.kind.or_else(|| choose_kind(&mut input)) {
+        Some(kind) => kind,
+        None => return,
+    };
+    let mut session: Session<T> = Session::new(kind);
+    println!(
+        "Using the {} with {} keys. Type 'help' for a list of commands.",
+        kind, options.key_type
+    );
+
+    while let Some(command) = next_command(&mut input) {
+        let changes_tree = matches!(
+            command,
+            Command::Insert(_)
+                | Command::Delete(_)
+                | Command::Fill { .. }
+                | Command::Clear
+                | Command::Switch(_)
+                | Command::Undo
+                | Command::Redo
+                | Command::Replay
+        );
+
+        let reply = session.execute(command);
+        print!("{}", reply);
+        if matches!(reply, Reply::Exit) {
+            break;
+        }
+        // An explanation already ends with the finished tree
+        if changes_tree && !matches!(reply, Reply::Explained { .. }) {
+            print!("{}", session.render_tree());
+        }
+    }
+}
+
+fn run_compare<T: Key + FromStr>(input: &mut impl BufRead, key_type: KeyType) {
+    let mut compare: Compare<T> = Compare::new();
+    println!(
+        "Comparing an AVL tree and a red-black tree with {} keys. Type 'help' for a list of commands.",
+        key_type
+    );
+    while let Some(command) = next_command(input) {
+        let exit = command == Command::Exit;
+        match compare.execute(command) {
+            Ok(comparison) => print!("{}", comparison),
+            Err(message) => println!("error: {}", message),
+        }
+        if exit {
+            break;
+        }
+    }
+}
@@ -0,0 +1,393 @@
+//! Operation traces: a plain-text log of every call made to a tree, which can
+//! be replayed offline against a fresh tree to reproduce a bug.
+//!
+//! One operation per line, optionally prefixed with `@` and the microseconds
+//! since recording started, and followed by what the call returned:
+//!
+//! ```text
+//! # lines starting with '#' are comments
+//! @0 insert 5 true
+//! @12 insert 5 false
+//! @31 find 9 false
+//! delete 5 true
+//! ```
+//!
+//! Replay checks each result the trace gives, and the tree's invariants.
+
+use std::cell::RefCell;
+use std::fmt::{self, Display};
+use std::io::{self, BufRead, Write};
+use std::time::Instant;
+
+use crate::explain::Step;
+use crate::search_tree::{Key, SearchTree, TreeKind};
+use crate::workload::Op;
+use tree_core::stats::TreeStats;
+
+/// One line of a trace
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry<T> {
+    /// Microseconds since recording started
+    pub at: Option<u64>,
+    pub op: Op<T>,
+    /// What the call returned: whether the insert or delete changed the
+    /// tree, or whether the search found its key
+    pub result: Option<bool>,
+}
+
+impl<T: Display> Display for Entry<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(at) = self.at {
+            write!(f, "@{} ", at)?;
+        }
+        match &self.op {
+            Op::Insert(key) => write!(f, "insert {}", key)?,
+            Op::Delete(key) => write!(f, "delete {}", key)?,
+            Op::Search(key) => write!(f, "find {}", key)?,
+        }
+        if let Some(result) = self.result {
+            write!(f, " {}", result)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Key> Entry<T> {
+    /// Parse one line. Blank lines and comments give `Ok(None)`.
+    pub fn parse(line: &str) -> Result<Option<Entry<T>>, String> {
+        let mut words = line.split_whitespace().peekable();
+        match words.peek() {
+            None => return Ok(None),
+            Some(word) if word.starts_with('#') => return Ok(None),
+            Some(_) => (),
+        }
+        let at = match words.next_if(|word| word.starts_with('@')) {
+            Some(word) => Some(word[1..].parse().map_err(|_| format!("'{}' is not a timestamp", word))?),
+            None => None,
+        };
+        let (name, key) = match (words.next(), words.next()) {
+            (Some(name), Some(key)) => (name, key),
+            _ => return Err("expected an operation and a key".to_string()),
+        };
+        let key: T = key.parse().map_err(|_| format!("'{}' is not a valid key", key))?;
+        let op = match name {
+            "insert" => Op::Insert(key),
+            "delete" => Op::Delete(key),
+            "find" => Op::Search(key),
+            other => return Err(format!("unknown operation '{}'", other)),
+        };
+        let result = match words.next() {
+            None => None,
+            Some("true") => Some(true),
+            Some("false") => Some(false),
+            Some(other) => return Err(format!("'{}' is not a result, expected true or false", other)),
+        };
+        match words.next() {
+            None => Ok(Some(Entry { at, op, result })),
+            Some(extra) => Err(format!("unexpected '{}' after the result", extra)),
+        }
+    }
+}
+
+/// Wraps a tree and writes every insert, delete and search made through it
+/// to `out`, as a trace. Everything else passes straight through.
+pub struct Recording<S, W: Write> {
+    tree: S,
+    /// Behind a `RefCell` so searches, which take `&self`, can be logged too
+    out: RefCell<W>,
+    /// Set when timestamps are written
+    start: Option<Instant>,
+    /// The first write that failed; later calls are not written
+    error: RefCell<Option<io::Error>>,
+}
+
+impl<S, W: Write> Recording<S, W> {
+    pub fn new(tree: S, out: W) -> Self {
+        Recording { tree, out: RefCell::new(out), start: None, error: RefCell::new(None) }
+    }
+
+    /// Prefix each line with the microseconds since this call
+    pub fn with_timestamps(mut self) -> Self {
+        self.start = Some(Instant::now());
+        self
+    }
+
+    pub fn tree(&self) -> &S {
+        &self.tree
+    }
+
+    /// Flush the trace and hand back the tree and the writer, or the first
+    /// error hit while writing
+    pub fn finish(self) -> io::Result<(S, W)> {
+        if let Some(err) = self.error.into_inner() {
+            return Err(err);
+        }
+        let mut out = self.out.into_inner();
+        out.flush()?;
+        Ok((self.tree, out))
+    }
+
+    fn log<T: Display>(&self, op: Op<T>, result: bool) -> bool {
+        let mut error = self.error.borrow_mut();
+        if error.is_none() {
+            let at = self.start.map(|start| start.elapsed().as_micros() as u64);
+            let entry = Entry { at, op, result: Some(result) };
+            if let Err(err) = writeln!(self.out.borrow_mut(), "{}", entry) {
+                *error = Some(err);
+            }
+        }
+        result
+    }
+}
+
+impl<T: Key, S: SearchTree<T>, W: Write> SearchTree<T> for Recording<S, W> {
+    fn kind(&self) -> TreeKind {
+        self.tree.kind()
+    }
+
+    fn insert(&mut self, key: T) -> bool {
+        let inserted = self.tree.insert(key.clone());
+        self.log(Op::Insert(key), inserted)
+    }
+
+    fn delete(&mut self, key: T) -> bool {
+        let deleted = self.tree.delete(key.clone());
+        self.log(Op::Delete(key), deleted)
+    }
+
+    fn contains(&self, key: &T) -> bool {
+        let found = self.tree.contains(key);
+        self.log(Op::Search(key.clone()), found)
+    }
+
+    fn leaves(&self) -> usize {
+        self.tree.leaves()
+    }
+
+    fn height(&self) -> usize {
+        self.tree.height()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    fn keys(&self) -> Vec<T> {
+        self.tree.keys()
+    }
+
+    fn range(&self, low: T, high: T) -> Vec<T> {
+        self.tree.range(low, high)
+    }
+
+    fn write_tree(&self, out: &mut dyn io::Write) -> io::Result<()> {
+        self.tree.write_tree(out)
+    }
+
+    fn diagram(&self) -> String {
+        self.tree.diagram()
+    }
+
+    fn insert_explained(&mut self, key: T) -> (bool, Vec<Step>) {
+        let (inserted, steps) = self.tree.insert_explained(key.clone());
+        (self.log(Op::Insert(key), inserted), steps)
+    }
+
+    fn delete_explained(&mut self, key: T) -> (bool, Vec<Step>) {
+        let (deleted, steps) = self.tree.delete_explained(key.clone());
+        (self.log(Op::Delete(key), deleted), steps)
+    }
+
+    fn stats(&self) -> TreeStats {
+        self.tree.stats()
+    }
+
+    fn reset_stats(&mut self) {
+        self.tree.reset_stats();
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        self.tree.validate()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReplayOptions {
+    /// Stop at the first line that diverges instead of reporting them all
+    pub stop_at_first: bool,
+    /// Check the tree's invariants after every this many operations, and
+    /// always at the end; 0 checks only at the end
+    pub validate_every: usize,
+}
+
+impl Default for ReplayOptions {
+    fn default() -> Self {
+        ReplayOptions { stop_at_first: false, validate_every: 1 }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+    Unparsable(String),
+    /// The call returned `actual` where the trace recorded `expected`
+    Result { expected: bool, actual: bool },
+    Invalid(String),
+}
+
+/// Where a replay stopped matching the trace
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    pub line: usize,
+    /// The trace line, trimmed
+    pub entry: String,
+    pub problem: Problem,
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.problem {
+            Problem::Unparsable(message) => write!(f, "cannot parse '{}': {}", self.entry, message),
+            Problem::Result { expected, actual } => {
+                write!(f, "'{}' returned {} but the trace says {}", self.entry, actual, expected)
+            }
+            Problem::Invalid(problem) => write!(f, "after '{}' the tree is invalid: {}", self.entry, problem),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReplayReport {
+    /// Operations applied to the tree
+    pub operations: usize,
+    pub divergences: Vec<Divergence>,
+}
+
+impl ReplayReport {
+    pub fn passed(&self) -> bool {
+        self.divergences.is_empty()
+    }
+}
+
+/// Feed every operation in the trace `input` to `tree`, comparing results
+/// with the ones recorded and checking invariants as `options` asks
+pub fn replay<T: Key>(
+    tree: &mut dyn SearchTree<T>,
+    input: impl BufRead,
+    options: &ReplayOptions,
+) -> io::Result<ReplayReport> {
+    let mut report = ReplayReport::default();
+    let mut last = None;
+    for (index, line) in input.lines().enumerate() {
+        let line = line?;
+        let diverge = |problem| Divergence { line: index + 1, entry: line.trim().to_string(), problem };
+        let entry = match Entry::<T>::parse(&line) {
+            Ok(Some(entry)) => entry,
+            Ok(None) => continue,
+            Err(message) => {
+                report.divergences.push(diverge(Problem::Unparsable(message)));
+                if options.stop_at_first {
+                    return Ok(report);
+                }
+                continue;
+            }
+        };
+
+        let actual = match entry.op {
+            Op::Insert(key) => tree.insert(key),
+            Op::Delete(key) => tree.delete(key),
+            Op::Search(key) => tree.contains(&key),
+        };
+        report.operations += 1;
+        let mut problems = Vec::new();
+        if let Some(expected) = entry.result.filter(|&expected| expected != actual) {
+            problems.push(Problem::Result { expected, actual });
+        }
+        if options.validate_every > 0 && report.operations % options.validate_every == 0 {
+            if let Err(problem) = tree.validate() {
+                problems.push(Problem::Invalid(problem));
+            }
+        }
+        let diverged = !problems.is_empty();
+        report.divergences.extend(problems.into_iter().map(diverge));
+        if diverged && options.stop_at_first {
+            return Ok(report);
+        }
+        last = Some((index + 1, line));
+    }
+
+    let checked_last = options.validate_every > 0 && report.operations % options.validate_every == 0;
+    if let (false, Some((line, entry))) = (checked_last, last) {
+        if let Err(problem) = tree.validate() {
+            report.divergences.push(Divergence { line, entry: entry.trim().to_string(), problem: Problem::Invalid(problem) });
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use avl_trees::tree::Tree;
+    use red_black_trees::rbt::RedBlackTreeSet;
+
+    #[test]
+    fn test_entry_round_trip() {
+        for line in ["@12 insert 5 true", "delete 3 false", "find 9", "@0 find -1"] {
+            let entry: Entry<i64> = Entry::parse(line).unwrap().unwrap();
+            assert_eq!(entry.to_string(), line);
+        }
+        assert_eq!(Entry::<i64>::parse("  # note"), Ok(None));
+        assert!(Entry::<i64>::parse("insert").is_err());
+        assert!(Entry::<i64>::parse("insert x").is_err());
+        assert!(Entry::<i64>::parse("upsert 5").is_err());
+        assert!(Entry::<i64>::parse("insert 5 maybe").is_err());
+        assert!(Entry::<i64>::parse("@soon insert 5").is_err());
+    }
+
+    #[test]
+    fn test_recorded_trace_replays_cleanly() {
+        let mut recording = Recording::new(RedBlackTreeSet::new(), Vec::new()).with_timestamps();
+        for key in [5, 3, 9, 3] {
+            recording.insert(key);
+        }
+        recording.contains(&4);
+        recording.delete(5);
+        let (tree, trace) = recording.finish().unwrap();
+        assert_eq!(SearchTree::keys(&tree), vec![3, 9]);
+
+        let trace = String::from_utf8(trace).unwrap();
+        let lines: Vec<&str> = trace.lines().map(|line| line.split_once(' ').unwrap().1).collect();
+        assert_eq!(
+            lines,
+            ["insert 5 true", "insert 3 true", "insert 9 true", "insert 3 false", "find 4 false", "delete 5 true"]
+        );
+
+        let mut avl: Tree<i64> = Tree::new();
+        let report = replay(&mut avl, trace.as_bytes(), &ReplayOptions::default()).unwrap();
+        assert_eq!(report, ReplayReport { operations: 6, divergences: vec![] });
+        assert_eq!(SearchTree::keys(&avl), vec![3, 9]);
+    }
+
+    #[test]
+    fn test_replay_reports_divergences() {
+        let trace = "insert 1 true\ninsert 1 true\nbogus\nfind 1 false\n";
+        let mut tree: Tree<i64> = Tree::new();
+        let report = replay(&mut tree, trace.as_bytes(), &ReplayOptions::default()).unwrap();
+        assert_eq!(report.operations, 3);
+        let lines: Vec<String> = report.divergences.iter().map(Divergence::to_string).collect();
+        assert_eq!(
+            lines,
+            [
+                "line 2: 'insert 1 true' returned false but the trace says true",
+                "line 3: cannot parse 'bogus': expected an operation and a key",
+                "line 4: 'find 1 false' returned true but the trace says false",
+            ]
+        );
+
+        let options = ReplayOptions { stop_at_first: true, validate_every: 0 };
+        let mut tree: Tree<i64> = Tree::new();
+        let report = replay(&mut tree, trace.as_bytes(), &options).unwrap();
+        assert_eq!((report.operations, report.divergences.len()), (2, 1));
+    }
+}
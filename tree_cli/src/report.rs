@@ -0,0 +1,224 @@
+//! Latency percentiles and the tables `tree-bench` prints them in.
+
+use std::fmt::{self, Display};
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Every latency measured during a run, kept whole so tail percentiles are
+/// exact rather than bucketed
+#[derive(Clone, Debug, Default)]
+pub struct Latencies {
+    nanos: Vec<u64>,
+    sorted: bool,
+}
+
+impl Latencies {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Latencies { nanos: Vec::with_capacity(capacity), sorted: true }
+    }
+
+    pub fn record(&mut self, latency: Duration) {
+        self.nanos.push(latency.as_nanos().min(u64::MAX as u128) as u64);
+        self.sorted = false;
+    }
+
+    pub fn len(&self) -> usize {
+        self.nanos.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nanos.is_empty()
+    }
+
+    /// The latency at or below which `percent` of the samples fall, in
+    /// nanoseconds, using the nearest-rank method; 0 with no samples
+    pub fn percentile(&mut self, percent: f64) -> u64 {
+        if self.nanos.is_empty() {
+            return 0;
+        }
+        if !self.sorted {
+            self.nanos.sort_unstable();
+            self.sorted = true;
+        }
+        // Shave off rounding error so 99.9% of 1000 samples is rank 999, not 1000
+        let rank = (percent / 100.0 * self.nanos.len() as f64 - 1e-9).ceil() as usize;
+        self.nanos[rank.clamp(1, self.nanos.len()) - 1]
+    }
+
+    pub fn max(&self) -> u64 {
+        self.nanos.iter().copied().max().unwrap_or(0)
+    }
+}
+
+/// One tree's results on one workload
+#[derive(Clone, Debug, PartialEq)]
+pub struct Row {
+    pub tree: String,
+    pub workload: String,
+    pub ops: usize,
+    pub p50: u64,
+    pub p99: u64,
+    pub p999: u64,
+    pub max: u64,
+    /// Keys left in the tree at the end
+    pub keys: usize,
+    /// Heap bytes the tree held at the end, per key
+    pub bytes_per_key: f64,
+    /// Most heap bytes the tree held at once during the run
+    pub peak_bytes: usize,
+    /// Rotations per key actually inserted, counting the ones deletes made
+    pub rotations_per_insert: f64,
+    /// Color changes; always 0 for AVL trees
+    pub recolors: u64,
+    /// Deepest level any operation reached
+    pub max_depth: usize,
+}
+
+impl Row {
+    pub fn new(tree: String, workload: String, latencies: &mut Latencies) -> Self {
+        Row {
+            tree,
+            workload,
+            ops: latencies.len(),
+            p50: latencies.percentile(50.0),
+            p99: latencies.percentile(99.0),
+            p999: latencies.percentile(99.9),
+            max: latencies.max(),
+            keys: 0,
+            bytes_per_key: 0.0,
+            peak_bytes: 0,
+            rotations_per_insert: 0.0,
+            recolors: 0,
+            max_depth: 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Markdown,
+    Csv,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "markdown" | "md" => Ok(Format::Markdown),
+            "csv" => Ok(Format::Csv),
+            other => Err(format!("unknown format '{}', expected markdown or csv", other)),
+        }
+    }
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Format::Markdown => write!(f, "markdown"),
+            Format::Csv => write!(f, "csv"),
+        }
+    }
+}
+
+const COLUMNS: [&str; 13] = [
+    "tree",
+    "workload",
+    "ops",
+    "p50 ns",
+    "p99 ns",
+    "p99.9 ns",
+    "max ns",
+    "keys",
+    "bytes/key",
+    "peak bytes",
+    "rotations/insert",
+    "recolors",
+    "max depth",
+];
+
+pub fn write_rows(out: &mut dyn Write, rows: &[Row], format: Format) -> io::Result<()> {
+    let cells = |row: &Row| {
+        [
+            row.tree.clone(),
+            row.workload.clone(),
+            row.ops.to_string(),
+            row.p50.to_string(),
+            row.p99.to_string(),
+            row.p999.to_string(),
+            row.max.to_string(),
+            row.keys.to_string(),
+            format!("{:.1}", row.bytes_per_key),
+            row.peak_bytes.to_string(),
+            format!("{:.3}", row.rotations_per_insert),
+            row.recolors.to_string(),
+            row.max_depth.to_string(),
+        ]
+    };
+    match format {
+        Format::Csv => {
+            let header: Vec<String> = COLUMNS.iter().map(|column| column.replace(' ', "_").replace('/', "_per_")).collect();
+            writeln!(out, "{}", header.join(","))?;
+            for row in rows {
+                writeln!(out, "{}", cells(row).join(","))?;
+            }
+        }
+        Format::Markdown => {
+            writeln!(out, "| {} |", COLUMNS.join(" | "))?;
+            let rule: Vec<&str> = COLUMNS.iter().enumerate().map(|(i, _)| if i < 2 { "---" } else { "---:" }).collect();
+            writeln!(out, "| {} |", rule.join(" | "))?;
+            for row in rows {
+                writeln!(out, "| {} |", cells(row).join(" | "))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentiles() {
+        let mut latencies = Latencies::with_capacity(1000);
+        for nanos in (1..=1000).rev() {
+            latencies.record(Duration::from_nanos(nanos));
+        }
+        assert_eq!(latencies.percentile(50.0), 500);
+        assert_eq!(latencies.percentile(99.0), 990);
+        assert_eq!(latencies.percentile(99.9), 999);
+        assert_eq!(latencies.percentile(100.0), 1000);
+        assert_eq!(latencies.percentile(0.0), 1);
+        assert_eq!(latencies.max(), 1000);
+        assert_eq!(Latencies::default().percentile(50.0), 0);
+    }
+
+    #[test]
+    fn test_write_rows() {
+        let mut latencies = Latencies::default();
+        latencies.record(Duration::from_nanos(40));
+        let mut row = Row::new("avl".to_string(), "uniform".to_string(), &mut latencies);
+        row.keys = 1;
+        row.bytes_per_key = 72.0;
+        row.peak_bytes = 72;
+        row.max_depth = 1;
+
+        let mut out = Vec::new();
+        write_rows(&mut out, &[row.clone()], Format::Csv).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "tree,workload,ops,p50_ns,p99_ns,p99.9_ns,max_ns,keys,bytes_per_key,peak_bytes,\
+             rotations_per_insert,recolors,max_depth\n\
+             avl,uniform,1,40,40,40,40,1,72.0,72,0.000,0,1\n"
+        );
+
+        let mut out = Vec::new();
+        write_rows(&mut out, &[row], Format::Markdown).unwrap();
+        let table = String::from_utf8(out).unwrap();
+        assert!(table.starts_with("| tree | workload | ops |"));
+        assert!(table.ends_with("| avl | uniform | 1 | 40 | 40 | 40 | 40 | 1 | 72.0 | 72 | 0.000 | 0 | 1 |\n"));
+    }
+}
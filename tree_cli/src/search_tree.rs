@@ -0,0 +1,308 @@
+//! One interface over both tree crates, so the CLI can hold either behind a
+//! `Box<dyn SearchTree<T>>` and swap it at runtime.
+
+use std::fmt::{self, Debug, Display};
+use std::io;
+use std::str::FromStr;
+
+use crate::explain::{self, Step};
+use avl_trees::diagram::DiagramOptions as AvlDiagramOptions;
+use avl_trees::tree::Tree;
+use red_black_trees::diagram::DiagramOptions as RedBlackDiagramOptions;
+use red_black_trees::rbt::RedBlackTreeSet;
+use tree_core::stats::TreeStats;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TreeKind {
+    Avl,
+    RedBlack,
+}
+
+impl TreeKind {
+    pub fn other(self) -> TreeKind {
+        match self {
+            TreeKind::Avl => TreeKind::RedBlack,
+            TreeKind::RedBlack => TreeKind::Avl,
+        }
+    }
+
+    /// The name used on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            TreeKind::Avl => "avl",
+            TreeKind::RedBlack => "rb",
+        }
+    }
+
+    /// An empty tree of this kind
+    pub fn new_tree<T: Key>(self) -> Box<dyn SearchTree<T>> {
+        match self {
+            TreeKind::Avl => Box::new(Tree::new()),
+            TreeKind::RedBlack => Box::new(RedBlackTreeSet::new()),
+        }
+    }
+}
+
+impl Display for TreeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TreeKind::Avl => write!(f, "AVL tree"),
+            TreeKind::RedBlack => write!(f, "red-black tree"),
+        }
+    }
+}
+
+impl FromStr for TreeKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "avl" => Ok(TreeKind::Avl),
+            "rb" | "red-black" | "redblack" => Ok(TreeKind::RedBlack),
+            other => Err(format!("unknown tree type '{}', expected 'avl' or 'rb'", other)),
+        }
+    }
+}
+
+/// Everything a key needs to be stored in either tree. `FromStr` lets
+/// generated workloads, which are integers, be read as any key type.
+pub trait Key: Ord + Clone + Debug + Display + FromStr + 'static {}
+
+impl<T: Ord + Clone + Debug + Display + FromStr + 'static> Key for T {}
+
+pub trait SearchTree<T: Key> {
+    fn kind(&self) -> TreeKind;
+    /// Returns false if `key` was already present
+    fn insert(&mut self, key: T) -> bool;
+    /// Returns false if `key` was not present
+    fn delete(&mut self, key: T) -> bool;
+    fn contains(&self, key: &T) -> bool;
+    fn leaves(&self) -> usize;
+    /// Number of levels; 0 for an empty tree
+    fn height(&self) -> usize;
+    fn is_empty(&self) -> bool;
+    /// Every key, in ascending order
+    fn keys(&self) -> Vec<T>;
+    fn write_tree(&self, out: &mut dyn io::Write) -> io::Result<()>;
+    /// The tree drawn top-down, in the same form as the diagrams in an
+    /// explained operation's steps
+    fn diagram(&self) -> String;
+    /// `insert`, narrating the search and each rebalancing case it ran into
+    fn insert_explained(&mut self, key: T) -> (bool, Vec<Step>);
+    /// `delete`, narrating the search and each rebalancing case it ran into
+    fn delete_explained(&mut self, key: T) -> (bool, Vec<Step>);
+    fn stats(&self) -> TreeStats;
+    fn reset_stats(&mut self);
+    /// Check the tree's ordering and balance invariants
+    fn validate(&self) -> Result<(), String>;
+
+    /// Keys from `low` to `high`, both inclusive, in ascending order
+    fn range(&self, low: T, high: T) -> Vec<T>;
+}
+
+impl<T: Key> SearchTree<T> for Tree<T> {
+    fn kind(&self) -> TreeKind {
+        TreeKind::Avl
+    }
+
+    fn insert(&mut self, key: T) -> bool {
+        Tree::insert(self, key)
+    }
+
+    fn delete(&mut self, key: T) -> bool {
+        Tree::delete(self, key)
+    }
+
+    fn contains(&self, key: &T) -> bool {
+        self.search(key)
+    }
+
+    fn leaves(&self) -> usize {
+        Tree::leaves(self)
+    }
+
+    fn height(&self) -> usize {
+        self.get_height(0)
+    }
+
+    fn is_empty(&self) -> bool {
+        Tree::is_empty(self)
+    }
+
+    fn keys(&self) -> Vec<T> {
+        self.iter().collect()
+    }
+
+    fn range(&self, low: T, high: T) -> Vec<T> {
+        let mut keys = Vec::new();
+        let mut cursor = self.cursor();
+        cursor.seek(&low);
+        while let Some(key) = cursor.key().filter(|key| *key <= high) {
+            keys.push(key);
+            cursor.move_next();
+        }
+        keys
+    }
+
+    fn write_tree(&self, out: &mut dyn io::Write) -> io::Result<()> {
+        Tree::write_tree(self, out)
+    }
+
+    fn diagram(&self) -> String {
+        Tree::diagram(self, &AvlDiagramOptions::new())
+    }
+
+    fn insert_explained(&mut self, key: T) -> (bool, Vec<Step>) {
+        let (inserted, events) = Tree::insert_explained(self, key);
+        (inserted, explain::avl_steps(events))
+    }
+
+    fn delete_explained(&mut self, key: T) -> (bool, Vec<Step>) {
+        let (deleted, events) = Tree::delete_explained(self, key);
+        (deleted, explain::avl_steps(events))
+    }
+
+    fn stats(&self) -> TreeStats {
+        Tree::stats(self)
+    }
+
+    fn reset_stats(&mut self) {
+        Tree::reset_stats(self);
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        Tree::validate(self)
+    }
+}
+
+impl<T: Key> SearchTree<T> for RedBlackTreeSet<T> {
+    fn kind(&self) -> TreeKind {
+        TreeKind::RedBlack
+    }
+
+    fn insert(&mut self, key: T) -> bool {
+        RedBlackTreeSet::insert(self, key)
+    }
+
+    fn delete(&mut self, key: T) -> bool {
+        RedBlackTreeSet::delete(self, key)
+    }
+
+    fn contains(&self, key: &T) -> bool {
+        self.find(key).is_some()
+    }
+
+    fn leaves(&self) -> usize {
+        RedBlackTreeSet::leaves(self) as usize
+    }
+
+    fn height(&self) -> usize {
+        self.get_tree_height() as usize
+    }
+
+    fn is_empty(&self) -> bool {
+        RedBlackTreeSet::is_empty(self)
+    }
+
+    fn keys(&self) -> Vec<T> {
+        self.iter().collect()
+    }
+
+    fn range(&self, low: T, high: T) -> Vec<T> {
+        let mut keys = Vec::new();
+        let mut cursor = self.cursor();
+        cursor.seek(&low);
+        while let Some(key) = cursor.key().filter(|key| *key <= high) {
+            keys.push(key);
+            cursor.move_next();
+        }
+        keys
+    }
+
+    fn write_tree(&self, out: &mut dyn io::Write) -> io::Result<()> {
+        RedBlackTreeSet::write_tree(self, out)
+    }
+
+    fn diagram(&self) -> String {
+        RedBlackTreeSet::diagram(self, &RedBlackDiagramOptions::new())
+    }
+
+    fn insert_explained(&mut self, key: T) -> (bool, Vec<Step>) {
+        let (inserted, events) = RedBlackTreeSet::insert_explained(self, key.clone());
+        (inserted, explain::red_black_steps(&key, events))
+    }
+
+    fn delete_explained(&mut self, key: T) -> (bool, Vec<Step>) {
+        let (deleted, events) = RedBlackTreeSet::delete_explained(self, key.clone());
+        (deleted, explain::red_black_steps(&key, events))
+    }
+
+    fn stats(&self) -> TreeStats {
+        RedBlackTreeSet::stats(self)
+    }
+
+    fn reset_stats(&mut self) {
+        RedBlackTreeSet::reset_stats(self);
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        RedBlackTreeSet::validate(self)
+    }
+}
+
+/// Rebuild `tree` as the other kind, keeping its keys
+pub fn switch<T: Key>(tree: Box<dyn SearchTree<T>>) -> Box<dyn SearchTree<T>> {
+    let mut switched = tree.kind().other().new_tree();
+    for key in tree.keys() {
+        switched.insert(key);
+    }
+    switched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_both_kinds_agree() {
+        for kind in [TreeKind::Avl, TreeKind::RedBlack] {
+            let mut tree: Box<dyn SearchTree<u32>> = kind.new_tree();
+            assert!(tree.is_empty());
+            assert_eq!(tree.height(), 0);
+            for key in [50, 30, 70, 20, 40, 60, 80] {
+                tree.insert(key);
+            }
+            assert_eq!(tree.kind(), kind);
+            assert_eq!(tree.keys(), vec![20, 30, 40, 50, 60, 70, 80]);
+            assert_eq!(tree.leaves(), 4);
+            assert_eq!(tree.height(), 3);
+            assert!(tree.contains(&40));
+            assert!(tree.delete(40));
+            assert!(!tree.contains(&40));
+            assert_eq!(tree.range(25, 60), vec![30, 50, 60]);
+            assert_eq!(tree.range(80, 99), vec![80]);
+            assert!(tree.range(81, 99).is_empty());
+            assert_eq!(tree.stats().inserts, 7);
+            assert_eq!(tree.validate(), Ok(()));
+            assert!(!tree.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_switch_keeps_keys() {
+        let mut tree: Box<dyn SearchTree<u32>> = TreeKind::Avl.new_tree();
+        for key in 1..=10 {
+            tree.insert(key);
+        }
+        let tree = switch(tree);
+        assert_eq!(tree.kind(), TreeKind::RedBlack);
+        assert_eq!(tree.keys(), (1..=10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_parse_tree_kind() {
+        assert_eq!("avl".parse(), Ok(TreeKind::Avl));
+        assert_eq!("RB".parse(), Ok(TreeKind::RedBlack));
+        assert!("splay".parse::<TreeKind>().is_err());
+    }
+}
@@ -0,0 +1,673 @@
+//! Applies parsed commands to the current tree and describes the result.
+
+use std::fmt::{self, Display};
+
+use crate::command::{Command, Expectation, HELP};
+use crate::explain::Step;
+use crate::search_tree::{self, Key, SearchTree, TreeKind};
+use crate::workload::{Op, Workload};
+use tree_core::stats::TreeStats;
+
+/// What a command did, in a form the front end can print however it likes
+#[derive(Clone, Debug, PartialEq)]
+pub enum Reply<T> {
+    Inserted { inserted: Vec<T>, duplicates: Vec<T> },
+    Deleted { deleted: Vec<T>, missing: Vec<T> },
+    Found { found: Vec<T>, missing: Vec<T> },
+    Keys(Vec<T>),
+    /// Outcome of `fill`: the inserts and deletes that changed the tree, and
+    /// how many of the searches found their key
+    Filled { inserted: usize, deleted: usize, searched: usize, found: usize },
+    Tree(String),
+    Stats(TreeStats),
+    StatsReset,
+    Cleared,
+    Leaves(usize),
+    Height(usize),
+    IsEmpty(bool),
+    Switched(TreeKind),
+    /// The edit that was undone, or `None` if there was nothing to undo
+    Undone(Option<Edit<T>>),
+    /// The edit that was redone, or `None` if there was nothing to redo
+    Redone(Option<Edit<T>>),
+    /// Edits in the order they were applied, then the undone edits in the
+    /// order `redo` would bring them back
+    History { applied: Vec<Edit<T>>, undone: Vec<Edit<T>> },
+    /// Number of edits reapplied
+    Replayed(usize),
+    Validated(Result<(), String>),
+    /// Outcome of `expect`; `expected` and `actual` are in the claim's own
+    /// syntax, e.g. `height 3`
+    Checked { passed: bool, expected: String, actual: String },
+    ExplainMode(bool),
+    /// An insert or delete run with explanations on: one walkthrough per key,
+    /// then the reply the command would have given anyway
+    Explained { walkthroughs: Vec<Walkthrough>, outcome: Box<Reply<T>> },
+    Help,
+    Exit,
+}
+
+impl<T> Reply<T> {
+    /// True for a failed `validate` or `expect`
+    pub fn is_failure(&self) -> bool {
+        match self {
+            Reply::Explained { outcome, .. } => outcome.is_failure(),
+            _ => matches!(self, Reply::Validated(Err(_)) | Reply::Checked { passed: false, .. }),
+        }
+    }
+}
+
+/// How one key's insert or delete unfolded
+#[derive(Clone, Debug, PartialEq)]
+pub struct Walkthrough {
+    /// The operation as typed for a single key, e.g. `insert 25`
+    pub operation: String,
+    pub before: String,
+    pub steps: Vec<Step>,
+    pub after: String,
+}
+
+/// A command that changed the tree, keeping only the keys that had an effect
+#[derive(Clone, Debug, PartialEq)]
+pub enum Edit<T> {
+    Insert(Vec<T>),
+    Delete(Vec<T>),
+    /// Seeded, so applying it again makes the same changes
+    Fill { count: usize, workload: Workload },
+    Clear,
+}
+
+impl<T: Key> Edit<T> {
+    fn apply(&self, tree: &mut Box<dyn SearchTree<T>>) {
+        match self {
+            Edit::Fill { count, workload } => {
+                for op in generate(*count, workload) {
+                    match op {
+                        Op::Insert(key) => {
+                            tree.insert(key);
+                        }
+                        Op::Delete(key) => {
+                            tree.delete(key);
+                        }
+                        Op::Search(_) => (),
+                    }
+                }
+            }
+            Edit::Insert(keys) => {
+                for key in keys {
+                    tree.insert(key.clone());
+                }
+            }
+            Edit::Delete(keys) => {
+                for key in keys {
+                    tree.delete(key.clone());
+                }
+            }
+            Edit::Clear => *tree = tree.kind().new_tree(),
+        }
+    }
+}
+
+impl<T: Display> Display for Edit<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Edit::Insert(keys) => write!(f, "insert {}", join(keys)),
+            Edit::Delete(keys) => write!(f, "delete {}", join(keys)),
+            Edit::Fill { count, workload } => write!(f, "fill {} {}", count, workload),
+            Edit::Clear => write!(f, "clear"),
+        }
+    }
+}
+
+/// The current tree plus the edits that built it. Undo rebuilds the tree by
+/// replaying the remaining edits on a fresh tree, so every earlier shape is
+/// restored exactly; the rebuilt tree's stats count only the replay.
+pub struct Session<T: Key> {
+    tree: Box<dyn SearchTree<T>>,
+    history: Vec<Edit<T>>,
+    /// Undone edits, most recently undone last
+    redo: Vec<Edit<T>>,
+    /// Whether inserts and deletes are narrated
+    explain: bool,
+}
+
+impl<T: Key> Session<T> {
+    pub fn new(kind: TreeKind) -> Self {
+        Session { tree: kind.new_tree(), history: Vec::new(), redo: Vec::new(), explain: false }
+    }
+
+    pub fn tree(&self) -> &dyn SearchTree<T> {
+        self.tree.as_ref()
+    }
+
+    pub fn execute(&mut self, command: Command<T>) -> Reply<T> {
+        match command {
+            Command::Insert(keys) if self.explain => {
+                let (walkthroughs, inserted, duplicates) = self.walk_through("insert", keys, |tree, key| {
+                    tree.insert_explained(key)
+                });
+                if !inserted.is_empty() {
+                    self.record(Edit::Insert(inserted.clone()));
+                }
+                Reply::Explained { walkthroughs, outcome: Box::new(Reply::Inserted { inserted, duplicates }) }
+            }
+            Command::Delete(keys) if self.explain => {
+                let (walkthroughs, deleted, missing) = self.walk_through("delete", keys, |tree, key| {
+                    tree.delete_explained(key)
+                });
+                if !deleted.is_empty() {
+                    self.record(Edit::Delete(deleted.clone()));
+                }
+                Reply::Explained { walkthroughs, outcome: Box::new(Reply::Deleted { deleted, missing }) }
+            }
+            Command::Insert(keys) => {
+                let (inserted, duplicates): (Vec<T>, Vec<T>) = keys.into_iter().partition(|key| self.tree.insert(key.clone()));
+                if !inserted.is_empty() {
+                    self.record(Edit::Insert(inserted.clone()));
+                }
+                Reply::Inserted { inserted, duplicates }
+            }
+            Command::Delete(keys) => {
+                let (deleted, missing): (Vec<T>, Vec<T>) = keys.into_iter().partition(|key| self.tree.delete(key.clone()));
+                if !deleted.is_empty() {
+                    self.record(Edit::Delete(deleted.clone()));
+                }
+                Reply::Deleted { deleted, missing }
+            }
+            Command::Find(keys) => {
+                let (found, missing) = keys.into_iter().partition(|key| self.tree.contains(key));
+                Reply::Found { found, missing }
+            }
+            Command::Range(low, high) => Reply::Keys(self.tree.range(low, high)),
+            Command::Fill { count, workload } => {
+                let (mut inserted, mut deleted, mut searched, mut found) = (0, 0, 0, 0);
+                for op in generate(count, &workload) {
+                    match op {
+                        Op::Insert(key) => inserted += self.tree.insert(key) as usize,
+                        Op::Delete(key) => deleted += self.tree.delete(key) as usize,
+                        Op::Search(key) => {
+                            searched += 1;
+                            found += self.tree.contains(&key) as usize;
+                        }
+                    }
+                }
+                if inserted + deleted > 0 {
+                    self.record(Edit::Fill { count, workload });
+                }
+                Reply::Filled { inserted, deleted, searched, found }
+            }
+            Command::Print => Reply::Tree(self.render_tree()),
+            Command::Stats => Reply::Stats(self.tree.stats()),
+            Command::ResetStats => {
+                self.tree.reset_stats();
+                Reply::StatsReset
+            }
+            Command::Clear => {
+                if !self.tree.is_empty() {
+                    self.record(Edit::Clear);
+                }
+                self.tree = self.tree.kind().new_tree();
+                Reply::Cleared
+            }
+            Command::Leaves => Reply::Leaves(self.tree.leaves()),
+            Command::Height => Reply::Height(self.tree.height()),
+            Command::InOrder => Reply::Keys(self.tree.keys()),
+            Command::IsEmpty => Reply::IsEmpty(self.tree.is_empty()),
+            Command::Switch(kind) => {
+                let target = kind.unwrap_or_else(|| self.tree.kind().other());
+                if target != self.tree.kind() {
+                    let tree = std::mem::replace(&mut self.tree, target.new_tree());
+                    self.tree = search_tree::switch(tree);
+                }
+                Reply::Switched(target)
+            }
+            Command::Undo => {
+                let edit = self.history.pop();
+                if let Some(edit) = &edit {
+                    self.redo.push(edit.clone());
+                    self.rebuild();
+                }
+                Reply::Undone(edit)
+            }
+            Command::Redo => {
+                let edit = self.redo.pop();
+                if let Some(edit) = &edit {
+                    edit.apply(&mut self.tree);
+                    self.history.push(edit.clone());
+                }
+                Reply::Redone(edit)
+            }
+            Command::History => Reply::History {
+                applied: self.history.clone(),
+                undone: self.redo.iter().rev().cloned().collect(),
+            },
+            Command::Replay => {
+                self.rebuild();
+                Reply::Replayed(self.history.len())
+            }
+            Command::Validate => Reply::Validated(self.tree.validate()),
+            Command::Expect(expectation) => self.check(expectation),
+            Command::Explain(explain) => {
+                self.explain = explain.unwrap_or(!self.explain);
+                Reply::ExplainMode(self.explain)
+            }
+            Command::Help => Reply::Help,
+            Command::Exit => Reply::Exit,
+        }
+    }
+
+    /// Run `operation` on each key, drawing the tree before and after it.
+    /// Returns the walkthroughs, then the keys that changed the tree and the
+    /// keys that did not.
+    #[allow(clippy::type_complexity)]
+    fn walk_through(
+        &mut self,
+        name: &str,
+        keys: Vec<T>,
+        mut operation: impl FnMut(&mut dyn SearchTree<T>, T) -> (bool, Vec<Step>),
+    ) -> (Vec<Walkthrough>, Vec<T>, Vec<T>) {
+        let mut walkthroughs = Vec::new();
+        let (mut changed, mut unchanged) = (Vec::new(), Vec::new());
+        for key in keys {
+            let before = self.render_diagram();
+            let (applied, steps) = operation(self.tree.as_mut(), key.clone());
+            let operation = format!("{} {}", name, key);
+            walkthroughs.push(Walkthrough { operation, before, steps, after: self.render_diagram() });
+            if applied {
+                changed.push(key);
+            } else {
+                unchanged.push(key);
+            }
+        }
+        (walkthroughs, changed, unchanged)
+    }
+
+    /// Remember a new edit; anything undone can no longer be redone
+    fn record(&mut self, edit: Edit<T>) {
+        self.history.push(edit);
+        self.redo.clear();
+    }
+
+    /// Replace the tree with a fresh one of the same kind built from `history`
+    fn rebuild(&mut self) {
+        let mut tree = self.tree.kind().new_tree();
+        for edit in &self.history {
+            edit.apply(&mut tree);
+        }
+        self.tree = tree;
+    }
+
+    fn check(&mut self, expectation: Expectation<T>) -> Reply<T> {
+        let (expected, actual) = match expectation {
+            Expectation::InOrder(keys) => (
+                format!("inorder {}", join(&keys)),
+                format!("inorder {}", join(&self.tree.keys())),
+            ),
+            Expectation::Height(height) => (format!("height {}", height), format!("height {}", self.tree.height())),
+            Expectation::Leaves(leaves) => (format!("leaves {}", leaves), format!("leaves {}", self.tree.leaves())),
+            Expectation::Empty(empty) => (format!("empty {}", empty), format!("empty {}", self.tree.is_empty())),
+            Expectation::Found(keys) => {
+                let missing: Vec<T> = keys.iter().filter(|&key| !self.tree.contains(key)).cloned().collect();
+                let actual = if missing.is_empty() {
+                    format!("find {}", join(&keys))
+                } else {
+                    format!("missing {}", join(&missing))
+                };
+                (format!("find {}", join(&keys)), actual)
+            }
+            Expectation::Missing(keys) => {
+                let found: Vec<T> = keys.iter().filter(|&key| self.tree.contains(key)).cloned().collect();
+                let actual = if found.is_empty() {
+                    format!("missing {}", join(&keys))
+                } else {
+                    format!("find {}", join(&found))
+                };
+                (format!("missing {}", join(&keys)), actual)
+            }
+        };
+        Reply::Checked { passed: expected == actual, expected, actual }
+    }
+
+    pub fn render_tree(&self) -> String {
+        if self.tree.is_empty() {
+            return "(empty tree)\n".to_string();
+        }
+        let mut out = Vec::new();
+        self.tree.write_tree(&mut out).expect("writing to a Vec cannot fail");
+        String::from_utf8(out).expect("trees render as UTF-8")
+    }
+
+    /// The tree drawn top-down, as in an explanation's steps
+    pub fn render_diagram(&self) -> String {
+        if self.tree.is_empty() {
+            return "(empty tree)\n".to_string();
+        }
+        self.tree.diagram()
+    }
+}
+
+/// The workload's operations, with each integer key read as a `T`. Keys a
+/// `T` cannot hold, such as negative keys for `u64`, are skipped.
+fn generate<T: Key>(count: usize, workload: &Workload) -> impl Iterator<Item = Op<T>> {
+    workload.ops(count).into_iter().filter_map(|op| {
+        let key = op.key().to_string().parse().ok()?;
+        Some(op.map(|_| key))
+    })
+}
+
+fn join<T: Display>(keys: &[T]) -> String {
+    keys.iter().map(T::to_string).collect::<Vec<_>>().join(" ")
+}
+
+/// Human-readable form used by the REPL
+impl<T: Display> Display for Reply<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reply::Inserted { duplicates, .. } => {
+                for key in duplicates {
+                    writeln!(f, "{} is already in the tree", key)?;
+                }
+                Ok(())
+            }
+            Reply::Deleted { missing, .. } => {
+                for key in missing {
+                    writeln!(f, "{} is not in the tree", key)?;
+                }
+                Ok(())
+            }
+            Reply::Found { found, missing } => {
+                for key in found {
+                    writeln!(f, "found {}", key)?;
+                }
+                for key in missing {
+                    writeln!(f, "{} not found", key)?;
+                }
+                Ok(())
+            }
+            Reply::Keys(keys) if keys.is_empty() => writeln!(f, "(no keys)"),
+            Reply::Keys(keys) => writeln!(f, "{}", join(keys)),
+            Reply::Filled { inserted, deleted, searched, found } => writeln!(
+                f,
+                "{} inserted, {} deleted, {} of {} searches found",
+                inserted, deleted, found, searched
+            ),
+            Reply::Tree(tree) => write!(f, "{}", tree),
+            Reply::Stats(stats) => {
+                writeln!(f, "inserts:          {}", stats.inserts)?;
+                writeln!(f, "deletes:          {}", stats.deletes)?;
+                writeln!(f, "searches:         {}", stats.searches)?;
+                writeln!(f, "comparisons:      {}", stats.comparisons)?;
+                writeln!(f, "single rotations: {}", stats.single_rotations)?;
+                writeln!(f, "double rotations: {}", stats.double_rotations)?;
+                writeln!(f, "recolors:         {}", stats.recolors)?;
+                writeln!(f, "allocations:      {}", stats.allocations)?;
+                writeln!(f, "max depth:        {}", stats.max_depth)
+            }
+            Reply::StatsReset => writeln!(f, "stats reset"),
+            Reply::Cleared => writeln!(f, "cleared"),
+            Reply::Leaves(leaves) => writeln!(f, "leaves: {}", leaves),
+            Reply::Height(height) => writeln!(f, "height: {}", height),
+            Reply::IsEmpty(true) => writeln!(f, "the tree is empty"),
+            Reply::IsEmpty(false) => writeln!(f, "the tree is not empty"),
+            Reply::Switched(kind) => writeln!(f, "switched to the {}", kind),
+            Reply::Undone(Some(edit)) => writeln!(f, "undid {}", edit),
+            Reply::Undone(None) => writeln!(f, "nothing to undo"),
+            Reply::Redone(Some(edit)) => writeln!(f, "redid {}", edit),
+            Reply::Redone(None) => writeln!(f, "nothing to redo"),
+            Reply::History { applied, undone } if applied.is_empty() && undone.is_empty() => writeln!(f, "(no history)"),
+            Reply::History { applied, undone } => {
+                for (number, edit) in applied.iter().enumerate() {
+                    writeln!(f, "{:>3}. {}", number + 1, edit)?;
+                }
+                for edit in undone {
+                    writeln!(f, "     {} (undone)", edit)?;
+                }
+                Ok(())
+            }
+            Reply::Replayed(count) => writeln!(f, "replayed {} edit(s) on a fresh tree", count),
+            Reply::Validated(Ok(())) => writeln!(f, "valid"),
+            Reply::Validated(Err(problem)) => writeln!(f, "invalid: {}", problem),
+            Reply::Checked { passed: true, .. } => writeln!(f, "ok"),
+            Reply::Checked { passed: false, expected, actual } => {
+                writeln!(f, "failed: expected {}, got {}", expected, actual)
+            }
+            Reply::ExplainMode(true) => writeln!(f, "explanations on"),
+            Reply::ExplainMode(false) => writeln!(f, "explanations off"),
+            Reply::Explained { walkthroughs, outcome } => {
+                for walkthrough in walkthroughs {
+                    write!(f, "{}", walkthrough)?;
+                }
+                write!(f, "{}", outcome)
+            }
+            Reply::Help => writeln!(f, "{}", HELP),
+            Reply::Exit => Ok(()),
+        }
+    }
+}
+
+impl Display for Walkthrough {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "== {} ==", self.operation)?;
+        writeln!(f, "before:")?;
+        write!(f, "{}", indent(&self.before))?;
+        for (number, step) in self.steps.iter().enumerate() {
+            writeln!(f, "{}. {}", number + 1, step.narrative)?;
+            if let Some(diagram) = &step.diagram {
+                write!(f, "{}", indent(diagram))?;
+            }
+        }
+        writeln!(f, "after:")?;
+        write!(f, "{}", indent(&self.after))
+    }
+}
+
+fn indent(text: &str) -> String {
+    text.lines().map(|line| format!("    {}\n", line)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::TotalF64;
+
+    fn run(session: &mut Session<u32>, line: &str) -> Reply<u32> {
+        session.execute(Command::parse(line).unwrap().unwrap())
+    }
+
+    #[test]
+    fn test_commands_on_both_trees() {
+        for kind in [TreeKind::Avl, TreeKind::RedBlack] {
+            let mut session = Session::new(kind);
+            assert_eq!(
+                run(&mut session, "insert 5 3 9 3"),
+                Reply::Inserted { inserted: vec![5, 3, 9], duplicates: vec![3] }
+            );
+            assert_eq!(run(&mut session, "find 9 4"), Reply::Found { found: vec![9], missing: vec![4] });
+            assert_eq!(run(&mut session, "range 2 8"), Reply::Keys(vec![3, 5]));
+            assert_eq!(run(&mut session, "delete 3 7"), Reply::Deleted { deleted: vec![3], missing: vec![7] });
+            assert_eq!(run(&mut session, "inorder"), Reply::Keys(vec![5, 9]));
+            assert_eq!(run(&mut session, "height"), Reply::Height(2));
+            match run(&mut session, "stats") {
+                Reply::Stats(stats) => assert_eq!((stats.inserts, stats.deletes, stats.searches), (3, 1, 2)),
+                reply => panic!("unexpected reply {:?}", reply),
+            }
+            assert_eq!(run(&mut session, "clear"), Reply::Cleared);
+            assert_eq!(run(&mut session, "empty"), Reply::IsEmpty(true));
+            assert_eq!(session.tree().kind(), kind);
+        }
+    }
+
+    #[test]
+    fn test_switch_keeps_keys() {
+        let mut session = Session::new(TreeKind::Avl);
+        run(&mut session, "insert 1 2 3 4");
+        assert_eq!(run(&mut session, "switch"), Reply::Switched(TreeKind::RedBlack));
+        assert_eq!(session.tree().kind(), TreeKind::RedBlack);
+        assert_eq!(run(&mut session, "switch rb"), Reply::Switched(TreeKind::RedBlack));
+        assert_eq!(run(&mut session, "inorder"), Reply::Keys(vec![1, 2, 3, 4]));
+    }
+
+    #[test]
+    fn test_undo_restores_earlier_shapes() {
+        let mut session = Session::new(TreeKind::Avl);
+        run(&mut session, "insert 30 20");
+        let before = session.render_tree();
+        // 10 triggers a right rotation at 30
+        run(&mut session, "insert 10 20");
+        assert_ne!(session.render_tree(), before);
+
+        assert_eq!(run(&mut session, "undo"), Reply::Undone(Some(Edit::Insert(vec![10]))));
+        assert_eq!(session.render_tree(), before);
+        assert_eq!(run(&mut session, "redo"), Reply::Redone(Some(Edit::Insert(vec![10]))));
+        assert_eq!(run(&mut session, "inorder"), Reply::Keys(vec![10, 20, 30]));
+        assert_eq!(run(&mut session, "redo"), Reply::Redone(None));
+
+        run(&mut session, "clear");
+        assert_eq!(run(&mut session, "undo"), Reply::Undone(Some(Edit::Clear)));
+        assert_eq!(run(&mut session, "inorder"), Reply::Keys(vec![10, 20, 30]));
+
+        // A new edit discards what could have been redone
+        run(&mut session, "delete 20 99");
+        assert_eq!(
+            run(&mut session, "history"),
+            Reply::History {
+                applied: vec![Edit::Insert(vec![30, 20]), Edit::Insert(vec![10]), Edit::Delete(vec![20])],
+                undone: vec![],
+            }
+        );
+        run(&mut session, "undo");
+        run(&mut session, "undo");
+        run(&mut session, "undo");
+        assert_eq!(run(&mut session, "undo"), Reply::Undone(None));
+        assert_eq!(run(&mut session, "empty"), Reply::IsEmpty(true));
+    }
+
+    #[test]
+    fn test_replay_rebuilds_in_another_tree_type() {
+        let mut session = Session::new(TreeKind::Avl);
+        run(&mut session, "insert 1 2 3 4 5");
+        run(&mut session, "switch rb");
+        assert_eq!(run(&mut session, "replay"), Reply::Replayed(1));
+        assert_eq!(session.tree().kind(), TreeKind::RedBlack);
+        match run(&mut session, "stats") {
+            Reply::Stats(stats) => assert_eq!(stats.inserts, 5),
+            reply => panic!("unexpected reply {:?}", reply),
+        }
+        let reply: Reply<u32> = run(&mut session, "history");
+        assert_eq!(reply.to_string(), "  1. insert 1 2 3 4 5\n");
+    }
+
+    #[test]
+    fn test_validate_and_expect() {
+        let mut session = Session::new(TreeKind::RedBlack);
+        run(&mut session, "insert 1 2 3");
+        assert_eq!(run(&mut session, "validate"), Reply::Validated(Ok(())));
+
+        let reply = run(&mut session, "expect inorder 1 2 3");
+        assert!(!reply.is_failure());
+        let reply = run(&mut session, "expect height 3");
+        assert_eq!(
+            reply,
+            Reply::Checked { passed: false, expected: "height 3".to_string(), actual: "height 2".to_string() }
+        );
+        assert!(reply.is_failure());
+        assert_eq!(
+            run(&mut session, "expect find 2 7"),
+            Reply::Checked { passed: false, expected: "find 2 7".to_string(), actual: "missing 7".to_string() }
+        );
+        assert!(!run(&mut session, "expect missing 7").is_failure());
+        assert!(run(&mut session, "expect empty").is_failure());
+    }
+
+    #[test]
+    fn test_other_key_types() {
+        let mut session: Session<String> = Session::new(TreeKind::RedBlack);
+        session.execute(Command::parse("insert pear apple fig").unwrap().unwrap());
+        let range = session.execute(Command::parse("range b g").unwrap().unwrap());
+        assert_eq!(range, Reply::Keys(vec!["fig".to_string()]));
+
+        let mut session: Session<TotalF64> = Session::new(TreeKind::Avl);
+        session.execute(Command::parse("insert 2.5 -1 0 -0").unwrap().unwrap());
+        let keys = session.execute(Command::parse("inorder").unwrap().unwrap());
+        assert_eq!(keys.to_string(), "-1 -0 0 2.5\n");
+        assert_eq!(session.execute(Command::Validate), Reply::Validated(Ok(())));
+
+        let mut session: Session<i64> = Session::new(TreeKind::Avl);
+        session.execute(Command::parse("insert -5 3").unwrap().unwrap());
+        assert_eq!(session.execute(Command::InOrder), Reply::Keys(vec![-5, 3]));
+    }
+
+    #[test]
+    fn test_explain_walks_through_each_key() {
+        let mut session = Session::new(TreeKind::Avl);
+        run(&mut session, "insert 30 20");
+        assert_eq!(run(&mut session, "explain"), Reply::ExplainMode(true));
+        let reply = run(&mut session, "insert 25 20");
+        let walkthroughs = match &reply {
+            Reply::Explained { walkthroughs, outcome } => {
+                assert_eq!(**outcome, Reply::Inserted { inserted: vec![25], duplicates: vec![20] });
+                walkthroughs
+            }
+            reply => panic!("unexpected reply {:?}", reply),
+        };
+        assert_eq!(walkthroughs.len(), 2);
+        assert_eq!(walkthroughs[0].operation, "insert 25");
+        assert_eq!(walkthroughs[0].after, session.render_diagram());
+        assert_eq!(
+            walkthroughs[0].steps[1].narrative,
+            "left-right case at 30: rotate_left(20), then rotate_right(30)"
+        );
+        assert_eq!(walkthroughs[1].before, walkthroughs[1].after);
+        assert!(reply.to_string().contains("== insert 20 ==\nbefore:\n"));
+        assert!(reply.to_string().ends_with("20 is already in the tree\n"));
+
+        // Explained edits are undone like any other
+        assert_eq!(run(&mut session, "undo"), Reply::Undone(Some(Edit::Insert(vec![25]))));
+        assert_eq!(run(&mut session, "explain off"), Reply::ExplainMode(false));
+        assert_eq!(run(&mut session, "delete 20"), Reply::Deleted { deleted: vec![20], missing: vec![] });
+    }
+
+    #[test]
+    fn test_fill_is_reproducible_and_undoable() {
+        let mut first = Session::new(TreeKind::Avl);
+        let mut second = Session::new(TreeKind::RedBlack);
+        let line = "fill 200 --seed 3 --dist clustered --mix 60/30/10";
+        let reply = run(&mut first, line);
+        assert_eq!(run(&mut second, line), reply);
+        match reply {
+            Reply::Filled { inserted, deleted, searched, found } => {
+                assert!(0 < found && found <= searched);
+                assert_eq!(first.tree().keys().len(), inserted - deleted);
+            }
+            reply => panic!("unexpected reply {:?}", reply),
+        }
+        assert_eq!(first.tree().keys(), second.tree().keys());
+        assert_eq!(first.execute(Command::Validate), Reply::Validated(Ok(())));
+
+        let before = first.tree().keys();
+        run(&mut first, "fill 50 --seed 9");
+        run(&mut first, "undo");
+        assert_eq!(first.tree().keys(), before);
+        run(&mut first, "redo");
+        assert_eq!(run(&mut first, "replay"), Reply::Replayed(2));
+        assert_eq!(
+            run(&mut first, "history").to_string(),
+            "  1. fill 200 --dist clustered --mix 60/30/10 --seed 3\n  \
+             2. fill 50 --dist uniform --mix 100/0/0 --seed 9\n"
+        );
+
+        // Negative keys cannot be u64s and are skipped
+        let mut session: Session<u64> = Session::new(TreeKind::Avl);
+        session.execute(Command::parse("fill 10 --dist reverse --mix 50/0/50").unwrap().unwrap());
+        assert!(session.tree().keys().len() <= 10);
+    }
+
+    #[test]
+    fn test_reply_text() {
+        let reply: Reply<u32> = Reply::Found { found: vec![9], missing: vec![4] };
+        assert_eq!(reply.to_string(), "found 9\n4 not found\n");
+        let reply: Reply<u32> = Reply::Inserted { inserted: vec![1], duplicates: vec![] };
+        assert_eq!(reply.to_string(), "");
+        assert_eq!(Reply::<u32>::Keys(vec![]).to_string(), "(no keys)\n");
+    }
+}
@@ -0,0 +1,358 @@
+//! Reproducible workloads: a seeded stream of inserts, deletes and searches
+//! whose keys follow one of a few distributions. Used by the CLI's `fill`
+//! command and by the benchmarks, so both see exactly the same operations
+//! for the same seed.
+//!
+//! ```
+//! use tree_cli::workload::{Distribution, Mix, Workload};
+//!
+//! let workload = Workload::new(Distribution::Zipfian).seed(7).mix(Mix::new(70, 20, 10));
+//! assert_eq!(workload.ops(1000), workload.ops(1000));
+//! ```
+
+use std::collections::HashSet;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// How inserted keys are drawn
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Distribution {
+    /// Uniformly from the key space
+    #[default]
+    Uniform,
+    /// 0, 1, 2, ... in ascending order
+    Sorted,
+    /// The sorted keys backwards, ending at 0
+    Reverse,
+    /// Skewed towards small keys: key `k` is drawn with probability roughly
+    /// proportional to `1 / (k + 1)`
+    Zipfian,
+    /// Tight runs of nearby keys around a few random centers
+    Clustered,
+}
+
+/// Keys around each center of a clustered workload
+const CLUSTER_WIDTH: u64 = 64;
+
+impl Distribution {
+    pub fn name(self) -> &'static str {
+        match self {
+            Distribution::Uniform => "uniform",
+            Distribution::Sorted => "sorted",
+            Distribution::Reverse => "reverse",
+            Distribution::Zipfian => "zipfian",
+            Distribution::Clustered => "clustered",
+        }
+    }
+}
+
+impl Display for Distribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Distribution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "uniform" => Ok(Distribution::Uniform),
+            "sorted" => Ok(Distribution::Sorted),
+            "reverse" | "reverse-sorted" => Ok(Distribution::Reverse),
+            "zipfian" | "zipf" => Ok(Distribution::Zipfian),
+            "clustered" => Ok(Distribution::Clustered),
+            other => Err(format!(
+                "unknown distribution '{}', expected uniform, sorted, reverse, zipfian or clustered",
+                other
+            )),
+        }
+    }
+}
+
+/// Relative weights of inserts, deletes and searches, written `70/20/10`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Mix {
+    pub insert: u32,
+    pub delete: u32,
+    pub search: u32,
+}
+
+impl Mix {
+    /// Panics if every weight is zero or the weights add up past `u32::MAX`
+    pub fn new(insert: u32, delete: u32, search: u32) -> Self {
+        assert!(
+            total(insert, delete, search).is_some_and(|total| total > 0),
+            "a mix needs at least one nonzero weight and a total that fits in a u32"
+        );
+        Mix { insert, delete, search }
+    }
+
+    fn total(&self) -> u32 {
+        total(self.insert, self.delete, self.search).unwrap()
+    }
+}
+
+fn total(insert: u32, delete: u32, search: u32) -> Option<u32> {
+    insert.checked_add(delete)?.checked_add(search)
+}
+
+/// Inserts only
+impl Default for Mix {
+    fn default() -> Self {
+        Mix::new(100, 0, 0)
+    }
+}
+
+impl Display for Mix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}/{}", self.insert, self.delete, self.search)
+    }
+}
+
+impl FromStr for Mix {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let usage = || format!("'{}' is not a mix, expected insert/delete/search weights such as 70/20/10", s);
+        let weights: Vec<u32> = s
+            .split('/')
+            .map(|weight| weight.trim().parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| usage())?;
+        match weights.as_slice() {
+            &[insert, delete, search] if total(insert, delete, search).is_some_and(|total| total > 0) => {
+                Ok(Mix::new(insert, delete, search))
+            }
+            _ => Err(usage()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Op<T> {
+    Insert(T),
+    Delete(T),
+    Search(T),
+}
+
+impl<T> Op<T> {
+    pub fn key(&self) -> &T {
+        match self {
+            Op::Insert(key) | Op::Delete(key) | Op::Search(key) => key,
+        }
+    }
+
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Op<U> {
+        match self {
+            Op::Insert(key) => Op::Insert(f(key)),
+            Op::Delete(key) => Op::Delete(f(key)),
+            Op::Search(key) => Op::Search(f(key)),
+        }
+    }
+}
+
+/// A recipe for operations. The same recipe always yields the same
+/// operations for a given version of `rand`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Workload {
+    pub distribution: Distribution,
+    pub mix: Mix,
+    pub seed: u64,
+    /// Keys are drawn from `0..key_space`; `None` uses ten times the number
+    /// of keys asked for. Sorted and reverse workloads ignore it.
+    pub key_space: Option<u64>,
+}
+
+impl Default for Workload {
+    fn default() -> Self {
+        Workload::new(Distribution::default())
+    }
+}
+
+impl Workload {
+    pub fn new(distribution: Distribution) -> Self {
+        Workload { distribution, mix: Mix::default(), seed: 0, key_space: None }
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn mix(mut self, mix: Mix) -> Self {
+        self.mix = mix;
+        self
+    }
+
+    pub fn key_space(mut self, keys: u64) -> Self {
+        self.key_space = Some(keys);
+        self
+    }
+
+    /// `count` keys to insert, drawn from the distribution. Only sorted and
+    /// reverse workloads are guaranteed to have no duplicates.
+    pub fn keys(&self, count: usize) -> Vec<i64> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut keys = Keys::new(self, count, &mut rng);
+        let mut drawn = Vec::new();
+        for _ in 0..count {
+            drawn.push(keys.next(&mut rng));
+        }
+        drawn
+    }
+
+    /// `count` operations in the proportions of the mix. Inserts draw fresh
+    /// keys from the distribution; deletes and searches pick uniformly among
+    /// the keys inserted and not yet deleted, falling back to a fresh key
+    /// while there are none.
+    pub fn ops(&self, count: usize) -> Vec<Op<i64>> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut keys = Keys::new(self, count, &mut rng);
+        // Live keys, as a list to pick from and a set to skip duplicates
+        let mut live: Vec<i64> = Vec::new();
+        let mut present = HashSet::new();
+        let mut ops = Vec::new();
+        for _ in 0..count {
+            let roll = rng.gen_range(0..self.mix.total());
+            let op = if roll < self.mix.insert || (live.is_empty() && roll < self.mix.insert + self.mix.delete) {
+                Op::Insert(keys.next(&mut rng))
+            } else if roll < self.mix.insert + self.mix.delete {
+                Op::Delete(live.swap_remove(rng.gen_range(0..live.len())))
+            } else if live.is_empty() {
+                Op::Search(keys.next(&mut rng))
+            } else {
+                Op::Search(live[rng.gen_range(0..live.len())])
+            };
+            match op {
+                Op::Insert(key) if present.insert(key) => live.push(key),
+                Op::Delete(key) => {
+                    present.remove(&key);
+                }
+                _ => (),
+            }
+            ops.push(op);
+        }
+        ops
+    }
+}
+
+/// Draws keys one at a time for a workload of `count` operations
+struct Keys {
+    distribution: Distribution,
+    count: u64,
+    space: u64,
+    drawn: u64,
+    centers: Vec<u64>,
+}
+
+impl Keys {
+    fn new(workload: &Workload, count: usize, rng: &mut StdRng) -> Self {
+        let count = count as u64;
+        let space = workload.key_space.unwrap_or(count.saturating_mul(10)).max(1);
+        let centers = match workload.distribution {
+            Distribution::Clustered => {
+                let clusters = (count / CLUSTER_WIDTH).max(1);
+                (0..clusters).map(|_| rng.gen_range(0..space)).collect()
+            }
+            _ => Vec::new(),
+        };
+        Keys { distribution: workload.distribution, count, space, drawn: 0, centers }
+    }
+
+    fn next(&mut self, rng: &mut StdRng) -> i64 {
+        let index = self.drawn;
+        self.drawn += 1;
+        let key = match self.distribution {
+            Distribution::Uniform => rng.gen_range(0..self.space),
+            Distribution::Sorted => index,
+            // Past `count` draws, keep counting down below zero
+            Distribution::Reverse => return self.count as i64 - 1 - index as i64,
+            Distribution::Zipfian => {
+                // Inverse of the continuous approximation to Zipf's CDF with
+                // exponent 1: P(key < k) = ln(k + 1) / ln(space + 1)
+                let rank = ((self.space as f64 + 1.0).ln() * rng.gen::<f64>()).exp() - 1.0;
+                (rank as u64).min(self.space - 1)
+            }
+            Distribution::Clustered => {
+                let center = self.centers[rng.gen_range(0..self.centers.len())];
+                center.saturating_add(rng.gen_range(0..CLUSTER_WIDTH))
+            }
+        };
+        key as i64
+    }
+}
+
+/// The options `fill` takes, e.g. `--dist zipfian --mix 70/20/10 --seed 7`
+impl Display for Workload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "--dist {} --mix {} --seed {}", self.distribution, self.mix, self.seed)?;
+        if let Some(keys) = self.key_space {
+            write!(f, " --keys {}", keys)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_ops() {
+        let workload = Workload::new(Distribution::Uniform).seed(42).mix(Mix::new(50, 30, 20));
+        assert_eq!(workload.ops(500), workload.ops(500));
+        assert_ne!(workload.ops(500), workload.seed(43).ops(500));
+    }
+
+    #[test]
+    fn test_distributions() {
+        assert_eq!(Workload::new(Distribution::Sorted).keys(4), vec![0, 1, 2, 3]);
+        assert_eq!(Workload::new(Distribution::Reverse).keys(4), vec![3, 2, 1, 0]);
+
+        let keys = Workload::new(Distribution::Uniform).key_space(100).keys(1000);
+        assert!(keys.iter().all(|&key| (0..100).contains(&key)));
+
+        // Most draws land on the smallest tenth of the key space
+        let keys = Workload::new(Distribution::Zipfian).key_space(1000).keys(1000);
+        assert!(keys.iter().filter(|&&key| key < 100).count() > 600);
+
+        let keys = Workload::new(Distribution::Clustered).keys(640);
+        let mut centers: Vec<i64> = keys.iter().map(|key| key / CLUSTER_WIDTH as i64).collect();
+        centers.sort();
+        centers.dedup();
+        assert!(centers.len() <= 20);
+    }
+
+    #[test]
+    fn test_mix_ratios() {
+        let ops = Workload::new(Distribution::Uniform).seed(1).mix(Mix::new(60, 30, 10)).ops(10_000);
+        let inserts = ops.iter().filter(|op| matches!(op, Op::Insert(_))).count();
+        let deletes = ops.iter().filter(|op| matches!(op, Op::Delete(_))).count();
+        assert!((5_700..6_300).contains(&inserts), "{} inserts", inserts);
+        assert!((2_700..3_300).contains(&deletes), "{} deletes", deletes);
+        // Deletes only name live keys
+        let mut live = HashSet::new();
+        for op in ops {
+            match op {
+                Op::Insert(key) => {
+                    live.insert(key);
+                }
+                Op::Delete(key) => assert!(live.remove(&key), "{} is not live", key),
+                Op::Search(_) => (),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("zipf".parse(), Ok(Distribution::Zipfian));
+        assert_eq!("70/20/10".parse(), Ok(Mix::new(70, 20, 10)));
+        assert!("4294967295/1/0".parse::<Mix>().is_err());
+        assert!("0/0/0".parse::<Mix>().is_err());
+        assert!("70/30".parse::<Mix>().is_err());
+    }
+}
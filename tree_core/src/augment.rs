@@ -0,0 +1,251 @@
+//! Subtree summaries kept in every node, so range aggregates take O(log n).
+//!
+//! A `Monoid` says how to summarize one key and how to combine two
+//! summaries. Each node stores the combination of its left subtree, its own
+//! key and its right subtree, in that order, and both the AVL and the
+//! red-black tree keep it current through every insert, delete and rotation.
+//! `aggregate` walks either tree's nodes through `Summarized`.
+//!
+//! An `EntryMonoid` summarizes a map's entries, values included. The
+//! built-in summaries below do both: over a set they read the keys and over
+//! a map the values.
+
+use std::cell::RefCell;
+use std::fmt::Debug;
+use std::ops::{Add, Bound};
+use std::rc::Rc;
+
+pub trait Monoid<T> {
+    type Value: Clone + Debug;
+
+    /// The summary of no keys; combining with it changes nothing
+    fn empty() -> Self::Value;
+    fn of(key: &T) -> Self::Value;
+    /// Must be associative: `combine(a, combine(b, c)) == combine(combine(a, b), c)`
+    fn combine(left: &Self::Value, right: &Self::Value) -> Self::Value;
+}
+
+/// Like `Monoid`, but over a map's keys and values together
+pub trait EntryMonoid<K, V> {
+    type Value: Clone + Debug;
+
+    fn empty() -> Self::Value;
+    fn of(key: &K, value: &V) -> Self::Value;
+    fn combine(left: &Self::Value, right: &Self::Value) -> Self::Value;
+}
+
+/// A node that keeps its subtree's summary, as `aggregate` sees it
+pub trait Summarized<T, A: Monoid<T>>: Sized {
+    fn key(&self) -> &T;
+    fn summary(&self) -> &A::Value;
+    fn left(&self) -> &Option<Rc<RefCell<Self>>>;
+    fn right(&self) -> &Option<Rc<RefCell<Self>>>;
+}
+
+/// Combine the summaries of every key under `node` between `low` and
+/// `high`, in ascending order, visiting O(log n) nodes
+pub fn aggregate<T: Ord, A: Monoid<T>, N: Summarized<T, A>>(
+    node: &Option<Rc<RefCell<N>>>,
+    low: Bound<&T>,
+    high: Bound<&T>,
+) -> A::Value {
+    let node = match node {
+        None => return A::empty(),
+        Some(node) => node.borrow(),
+    };
+    if let (Bound::Unbounded, Bound::Unbounded) = (low, high) {
+        return node.summary().clone();
+    }
+    if !after_start(node.key(), low) {
+        return aggregate(node.right(), low, high);
+    }
+    if !before_end(node.key(), high) {
+        return aggregate(node.left(), low, high);
+    }
+    // The range splits here: everything right of the left path and left
+    // of the right path is inside it, so only one bound applies to each side
+    let left = aggregate(node.left(), low, Bound::Unbounded);
+    let right = aggregate(node.right(), Bound::Unbounded, high);
+    A::combine(&A::combine(&left, &A::of(node.key())), &right)
+}
+
+/// Whether `key` is at or past the start bound `low`
+pub fn after_start<T: Ord + ?Sized>(key: &T, low: Bound<&T>) -> bool {
+    match low {
+        Bound::Included(low) => key >= low,
+        Bound::Excluded(low) => key > low,
+        Bound::Unbounded => true,
+    }
+}
+
+/// Whether `key` is at or before the end bound `high`
+pub fn before_end<T: Ord + ?Sized>(key: &T, high: Bound<&T>) -> bool {
+    match high {
+        Bound::Included(high) => key <= high,
+        Bound::Excluded(high) => key < high,
+        Bound::Unbounded => true,
+    }
+}
+
+/// No summary; the default for trees that do not need one
+impl<T> Monoid<T> for () {
+    type Value = ();
+
+    fn empty() {}
+
+    fn of(_: &T) {}
+
+    fn combine(_: &(), _: &()) {}
+}
+
+/// Number of keys
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Count;
+
+impl<T> Monoid<T> for Count {
+    type Value = usize;
+
+    fn empty() -> usize {
+        0
+    }
+
+    fn of(_: &T) -> usize {
+        1
+    }
+
+    fn combine(left: &usize, right: &usize) -> usize {
+        left + right
+    }
+}
+
+impl<K, V> EntryMonoid<K, V> for Count {
+    type Value = usize;
+
+    fn empty() -> usize {
+        0
+    }
+
+    fn of(_: &K, _: &V) -> usize {
+        1
+    }
+
+    fn combine(left: &usize, right: &usize) -> usize {
+        left + right
+    }
+}
+
+/// Sum of the keys, or of a map's values, starting from the default
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Sum;
+
+impl<T: Clone + Debug + Default + Add<Output = T>> Monoid<T> for Sum {
+    type Value = T;
+
+    fn empty() -> T {
+        T::default()
+    }
+
+    fn of(key: &T) -> T {
+        key.clone()
+    }
+
+    fn combine(left: &T, right: &T) -> T {
+        left.clone() + right.clone()
+    }
+}
+
+impl<K, V: Clone + Debug + Default + Add<Output = V>> EntryMonoid<K, V> for Sum {
+    type Value = V;
+
+    fn empty() -> V {
+        V::default()
+    }
+
+    fn of(_: &K, value: &V) -> V {
+        value.clone()
+    }
+
+    fn combine(left: &V, right: &V) -> V {
+        left.clone() + right.clone()
+    }
+}
+
+/// Smallest key, or a map's smallest value, or `None` for neither
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Min;
+
+impl<T: Clone + Debug + Ord> Monoid<T> for Min {
+    type Value = Option<T>;
+
+    fn empty() -> Option<T> {
+        None
+    }
+
+    fn of(key: &T) -> Option<T> {
+        Some(key.clone())
+    }
+
+    fn combine(left: &Option<T>, right: &Option<T>) -> Option<T> {
+        match (left, right) {
+            (Some(left), Some(right)) => Some(std::cmp::min(left, right).clone()),
+            (Some(only), None) | (None, Some(only)) => Some(only.clone()),
+            (None, None) => None,
+        }
+    }
+}
+
+impl<K, V: Clone + Debug + Ord> EntryMonoid<K, V> for Min {
+    type Value = Option<V>;
+
+    fn empty() -> Option<V> {
+        None
+    }
+
+    fn of(_: &K, value: &V) -> Option<V> {
+        Some(value.clone())
+    }
+
+    fn combine(left: &Option<V>, right: &Option<V>) -> Option<V> {
+        <Min as Monoid<V>>::combine(left, right)
+    }
+}
+
+/// Largest key, or a map's largest value, or `None` for neither
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Max;
+
+impl<T: Clone + Debug + Ord> Monoid<T> for Max {
+    type Value = Option<T>;
+
+    fn empty() -> Option<T> {
+        None
+    }
+
+    fn of(key: &T) -> Option<T> {
+        Some(key.clone())
+    }
+
+    fn combine(left: &Option<T>, right: &Option<T>) -> Option<T> {
+        match (left, right) {
+            (Some(left), Some(right)) => Some(std::cmp::max(left, right).clone()),
+            (Some(only), None) | (None, Some(only)) => Some(only.clone()),
+            (None, None) => None,
+        }
+    }
+}
+
+impl<K, V: Clone + Debug + Ord> EntryMonoid<K, V> for Max {
+    type Value = Option<V>;
+
+    fn empty() -> Option<V> {
+        None
+    }
+
+    fn of(_: &K, value: &V) -> Option<V> {
+        Some(value.clone())
+    }
+
+    fn combine(left: &Option<V>, right: &Option<V>) -> Option<V> {
+        <Max as Monoid<V>>::combine(left, right)
+    }
+}
@@ -0,0 +1,239 @@
+//! Top-down tree diagrams: each key is centered over its children and joined
+//! to them with `/` and `\` branches.
+//!
+//! ```text
+//!     _50_
+//!    /    \
+//!   40   70
+//!  /    /  \
+//! 30   60 80
+//! ```
+
+/// Settings for `diagram` / `write_diagram`
+#[derive(Debug, Clone, Default)]
+pub struct DiagramOptions {
+    /// Widest diagram allowed, in columns. Deeper levels are replaced by
+    /// `...` until the diagram fits (the root is always drawn).
+    pub max_width: Option<usize>,
+}
+
+impl DiagramOptions {
+    pub fn new() -> Self {
+        DiagramOptions::default()
+    }
+
+    pub fn max_width(mut self, columns: usize) -> Self {
+        self.max_width = Some(columns);
+        self
+    }
+}
+
+/// Text drawn for one node. `width` is the number of columns it occupies,
+/// which differs from `text.len()` when the text holds escape codes or
+/// multi-byte characters.
+#[derive(Debug, Clone)]
+pub struct Label {
+    text: String,
+    width: usize,
+}
+
+impl Label {
+    pub fn plain(text: String) -> Self {
+        let width = text.chars().count();
+        Label { text, width }
+    }
+
+    pub fn styled(text: String, width: usize) -> Self {
+        Label { text, width }
+    }
+}
+
+/// Owned copy of the shape of a tree, built by each tree before layout
+pub struct Sketch {
+    pub label: Label,
+    pub left: Option<Box<Sketch>>,
+    pub right: Option<Box<Sketch>>,
+}
+
+impl Sketch {
+    fn height(&self) -> usize {
+        let left = self.left.as_ref().map_or(0, |n| n.height());
+        let right = self.right.as_ref().map_or(0, |n| n.height());
+        1 + std::cmp::max(left, right)
+    }
+}
+
+/// A rendered subtree. Every line is exactly `width` columns wide and
+/// `center` is the column the subtree's root label is centered on.
+struct Block {
+    lines: Vec<String>,
+    width: usize,
+    center: usize,
+}
+
+const ELIDED: &str = "...";
+
+/// Lay out `sketch`, eliding deeper levels until it fits `options.max_width`
+pub fn render(sketch: &Sketch, options: &DiagramOptions) -> String {
+    let mut block = layout(sketch, 0, None);
+    if let Some(max_width) = options.max_width {
+        let mut limit = sketch.height();
+        while block.width > max_width && limit > 0 {
+            limit -= 1;
+            block = layout(sketch, 0, Some(limit));
+        }
+    }
+
+    let mut out = String::new();
+    for line in block.lines {
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+fn layout(sketch: &Sketch, depth: usize, limit: Option<usize>) -> Block {
+    let label = &sketch.label;
+    let child = |node: &Option<Box<Sketch>>| {
+        node.as_ref().map(|node| {
+            if Some(depth) == limit {
+                leaf(&Label::plain(ELIDED.to_string()))
+            } else {
+                layout(node, depth + 1, limit)
+            }
+        })
+    };
+
+    match (child(&sketch.left), child(&sketch.right)) {
+        (None, None) => leaf(label),
+        (Some(left), None) => {
+            let start = left.center + 1;
+            let width = std::cmp::max(left.width, start + label.width);
+            let mut lines = vec![
+                pad(format!("{}{}", spaces(start), label.text), start + label.width, width),
+                pad(format!("{}/", spaces(left.center)), left.center + 1, width),
+            ];
+            lines.extend(left.lines.into_iter().map(|line| pad(line, left.width, width)));
+            Block { lines, width, center: start + label.width / 2 }
+        }
+        (None, Some(right)) => {
+            let offset = label.width.saturating_sub(right.center);
+            let branch = offset + right.center;
+            let start = branch - label.width;
+            let width = offset + right.width;
+            let mut lines = vec![
+                pad(format!("{}{}", spaces(start), label.text), branch, width),
+                pad(format!("{}\\", spaces(branch)), branch + 1, width),
+            ];
+            lines.extend(right.lines.into_iter().map(|line| format!("{}{}", spaces(offset), line)));
+            Block { lines, width, center: start + label.width / 2 }
+        }
+        (Some(left), Some(right)) => {
+            // Leave room for the label strictly between the two branches
+            let needed = (label.width + 1 + left.center).saturating_sub(left.width + right.center);
+            let gap = std::cmp::max(1, needed);
+            let right_branch = left.width + gap + right.center;
+            let start = left.center + 1 + (right_branch - left.center - 1 - label.width) / 2;
+            let width = left.width + gap + right.width;
+
+            let first = format!(
+                "{}{}{}{}",
+                spaces(left.center + 1),
+                "_".repeat(start - left.center - 1),
+                label.text,
+                "_".repeat(right_branch - start - label.width)
+            );
+            let second = format!(
+                "{}/{}\\",
+                spaces(left.center),
+                spaces(right_branch - left.center - 1)
+            );
+            let mut lines = vec![pad(first, right_branch, width), pad(second, right_branch + 1, width)];
+
+            let rows = std::cmp::max(left.lines.len(), right.lines.len());
+            let mut left_lines = left.lines.into_iter();
+            let mut right_lines = right.lines.into_iter();
+            for _ in 0..rows {
+                let l = left_lines.next().unwrap_or_else(|| spaces(left.width));
+                let r = right_lines.next().unwrap_or_else(|| spaces(right.width));
+                lines.push(format!("{}{}{}", l, spaces(gap), r));
+            }
+            Block { lines, width, center: start + label.width / 2 }
+        }
+    }
+}
+
+fn leaf(label: &Label) -> Block {
+    Block {
+        lines: vec![label.text.clone()],
+        width: label.width,
+        center: label.width / 2,
+    }
+}
+
+fn spaces(count: usize) -> String {
+    " ".repeat(count)
+}
+
+/// Pad `line`, currently `used` columns wide, out to `width` columns
+fn pad(mut line: String, used: usize, width: usize) -> String {
+    line.push_str(&spaces(width.saturating_sub(used)));
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(key: &str, left: Option<Sketch>, right: Option<Sketch>) -> Sketch {
+        Sketch {
+            label: Label::plain(key.to_string()),
+            left: left.map(Box::new),
+            right: right.map(Box::new),
+        }
+    }
+
+    fn leaf_node(key: &str) -> Option<Sketch> {
+        Some(node(key, None, None))
+    }
+
+    #[test]
+    fn test_single_node() {
+        assert_eq!(render(&node("7", None, None), &DiagramOptions::new()), "7\n");
+    }
+
+    #[test]
+    fn test_one_sided_children() {
+        let left = node("40", leaf_node("30"), None);
+        assert_eq!(render(&left, &DiagramOptions::new()), "  40\n /\n30\n");
+
+        let right = node("10", None, leaf_node("20"));
+        assert_eq!(render(&right, &DiagramOptions::new()), "10\n  \\\n 20\n");
+    }
+
+    #[test]
+    fn test_wide_keys_are_centered_between_branches() {
+        let sketch = node("parent", leaf_node("a"), leaf_node("b"));
+        assert_eq!(
+            render(&sketch, &DiagramOptions::new()),
+            " parent\n/      \\\na      b\n"
+        );
+    }
+
+    #[test]
+    fn test_max_width_elides_deep_levels() {
+        let sketch = node(
+            "50",
+            Some(node("40", leaf_node("30"), leaf_node("45"))),
+            Some(node("70", leaf_node("60"), leaf_node("80"))),
+        );
+        let full = "    _50__\n   /     \\\n  40    70\n /  \\  /  \\\n30 45 60 80\n";
+        assert_eq!(render(&sketch, &DiagramOptions::new()), full);
+        assert_eq!(render(&sketch, &DiagramOptions::new().max_width(11)), full);
+
+        let elided = "  50_\n /   \\\n... ...\n";
+        assert_eq!(render(&sketch, &DiagramOptions::new().max_width(10)), elided);
+        // The root is always drawn, even when nothing else fits
+        assert_eq!(render(&sketch, &DiagramOptions::new().max_width(1)), elided);
+    }
+}
@@ -0,0 +1,8 @@
+//! Pieces shared by the AVL and red-black tree crates.
+
+pub mod augment;
+pub mod diagram;
+pub mod map;
+pub mod stats;
+pub mod testing;
+pub mod trace;
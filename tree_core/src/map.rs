@@ -0,0 +1,477 @@
+//! A sorted map over either tree, which each tree crate names for itself.
+//!
+//! The tree orders `Slot`s, each pairing a key with the index of its value in
+//! a slab the map owns. Values therefore live outside the tree's `RefCell`s,
+//! so `get`, `get_mut` and the entry API can hand out plain references.
+//! Removing a key frees its slot for the next insert to reuse.
+//!
+//! `entry` walks down the tree once and keeps the `Place` where it stopped.
+//! A vacant entry links its new leaf there and an occupied one unlinks its
+//! node from there, so an upsert costs one descent and one rebalance.
+
+use std::cmp::Ordering;
+use std::fmt::{self, Debug, Display};
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::ops::{Bound, Index, RangeBounds};
+
+use crate::augment::{after_start, before_end, EntryMonoid};
+use crate::stats::TreeStats;
+
+/// A key and where its value is stored, ordered by the key alone
+#[derive(Clone)]
+pub struct Slot<K> {
+    key: K,
+    index: usize,
+}
+
+impl<K> Slot<K> {
+    /// Where the key's value sits in the map's slab
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
+impl<K: Ord> PartialEq for Slot<K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<K: Ord> Eq for Slot<K> {}
+
+impl<K: Ord> PartialOrd for Slot<K> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Ord> Ord for Slot<K> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+/// Only the key, so traces and diagrams read like the set's
+impl<K: Debug> Debug for Slot<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.key.fmt(f)
+    }
+}
+
+impl<K: Display> Display for Slot<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.key.fmt(f)
+    }
+}
+
+/// What a tree of `Slot`s offers a `Map`. `probe` compares the sought key
+/// with a node's, as the trees' own searches do.
+pub trait MapTree<K>: Default {
+    /// Where a descent stopped, enough to link or unlink there without
+    /// searching again
+    type Place;
+    type Slots<'a>: Iterator<Item = Slot<K>>
+    where
+        Self: 'a;
+
+    fn is_empty(&self) -> bool;
+    fn stats(&self) -> TreeStats;
+    fn reset_stats(&mut self);
+    fn validate(&self) -> Result<(), String>;
+    /// The slots in key order, cloned out of the tree
+    fn slots(&self) -> Self::Slots<'_>;
+    /// The slots from `start`, or the first one above it, onwards
+    fn slots_from(&self, start: &Slot<K>) -> Self::Slots<'_>;
+
+    /// The value index of the slot `probe` calls `Equal`, counted as a search
+    fn lookup(&self, probe: impl Fn(&Slot<K>) -> Ordering) -> Option<usize>;
+    /// Walk down to the slot `probe` calls `Equal`, or to the node a new slot
+    /// would hang from, returning that place and what `probe` said about its
+    /// last node. The ordering is `None` only when the tree is empty.
+    fn descend(&mut self, probe: impl Fn(&Slot<K>) -> Ordering) -> (Self::Place, Option<Ordering>);
+    /// The value index at a place `descend` found occupied
+    fn index_at(place: &Self::Place) -> usize;
+    /// Hang `slot` off a place `descend` found vacant, on the left if
+    /// `as_left`, counted as an insert
+    fn link(&mut self, place: Self::Place, slot: Slot<K>, as_left: bool);
+    /// Remove the node at a place `descend` found occupied, counted as a
+    /// delete
+    fn unlink(&mut self, place: Self::Place) -> Slot<K>;
+}
+
+pub struct Map<K, V, T> {
+    tree: T,
+    values: Vec<Option<V>>,
+    /// Indices of `values` left empty by removals
+    free: Vec<usize>,
+    /// The keys live in `tree`'s slots
+    keys: PhantomData<K>,
+}
+
+impl<K: Ord + Debug, V, T: MapTree<K>> Map<K, V, T> {
+    pub fn new() -> Self {
+        Map { tree: T::default(), values: Vec::new(), free: Vec::new(), keys: PhantomData }
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// How many values the slab holds room for, empty slots included
+    pub fn capacity(&self) -> usize {
+        self.values.len()
+    }
+
+    /// The tree's counters; a lookup counts as a search, and `entry` counts
+    /// its comparisons plus the insert or delete it ends in
+    pub fn stats(&self) -> TreeStats {
+        self.tree.stats()
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.tree.reset_stats();
+    }
+
+    pub fn get<Q: ?Sized + Ord>(&self, key: &Q) -> Option<&V>
+    where
+        K: std::borrow::Borrow<Q>,
+    {
+        let index = self.tree.lookup(|slot| key.cmp(slot.key.borrow()))?;
+        self.values[index].as_ref()
+    }
+
+    pub fn get_mut<Q: ?Sized + Ord>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: std::borrow::Borrow<Q>,
+    {
+        let index = self.tree.lookup(|slot| key.cmp(slot.key.borrow()))?;
+        self.values[index].as_mut()
+    }
+
+    pub fn contains_key<Q: ?Sized + Ord>(&self, key: &Q) -> bool
+    where
+        K: std::borrow::Borrow<Q>,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Map `key` to `value`, returning the value it replaced, if any
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.entry(key) {
+            Entry::Occupied(mut entry) => Some(entry.insert(value)),
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+                None
+            }
+        }
+    }
+
+    pub fn remove<Q: ?Sized + Ord>(&mut self, key: &Q) -> Option<V>
+    where
+        K: std::borrow::Borrow<Q>,
+    {
+        self.remove_entry(key).map(|(_, value)| value)
+    }
+
+    /// Remove `key`, returning the stored key and its value
+    pub fn remove_entry<Q: ?Sized + Ord>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: std::borrow::Borrow<Q>,
+    {
+        let (place, ordering) = self.tree.descend(|slot| key.cmp(slot.key.borrow()));
+        if ordering != Some(Ordering::Equal) {
+            return None;
+        }
+        let slot = self.tree.unlink(place);
+        Some((slot.key, self.release(slot.index)))
+    }
+
+    /// The place for `key`, found with a single descent, to read, change,
+    /// fill or remove
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, T> {
+        let (place, ordering) = self.tree.descend(|slot| key.cmp(&slot.key));
+        match ordering {
+            Some(Ordering::Equal) => {
+                let index = T::index_at(&place);
+                Entry::Occupied(OccupiedEntry { map: self, place, key, index })
+            }
+            _ => Entry::Vacant(VacantEntry { map: self, place, as_left: ordering == Some(Ordering::Less), key }),
+        }
+    }
+
+    /// The keys and values in key order, with the keys cloned out of the tree
+    pub fn iter(&self) -> Iter<'_, K, V, T> {
+        Iter { slots: self.tree.slots(), values: &self.values }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = K> + '_ {
+        self.iter().map(|(key, _)| key)
+    }
+
+    /// Combine `M`'s summaries of the entries with keys in `range`, in key
+    /// order, e.g. `map.aggregate::<Sum, _>(a..=b)` for the total of the
+    /// values. Values can change through `get_mut` without the tree seeing
+    /// it, so the nodes keep no summaries of them and this visits every
+    /// entry in the range after one seek.
+    pub fn aggregate<M: EntryMonoid<K, V>, R: RangeBounds<K>>(&self, range: R) -> M::Value
+    where
+        K: Clone,
+    {
+        let slots = match range.start_bound() {
+            Bound::Included(start) | Bound::Excluded(start) => {
+                self.tree.slots_from(&Slot { key: start.clone(), index: 0 })
+            }
+            Bound::Unbounded => self.tree.slots(),
+        };
+        slots
+            .skip_while(|slot| !after_start(&slot.key, range.start_bound()))
+            .take_while(|slot| before_end(&slot.key, range.end_bound()))
+            .fold(M::empty(), |total, slot| {
+                M::combine(&total, &M::of(&slot.key, self.values[slot.index].as_ref().unwrap()))
+            })
+    }
+
+    /// Check the tree's invariants and that every key has a value
+    pub fn validate(&self) -> Result<(), String> {
+        self.tree.validate()?;
+        let mut keys = 0;
+        for slot in self.tree.slots() {
+            if self.values.get(slot.index).is_none_or(Option::is_none) {
+                return Err(format!("{:?} points at empty slot {}", slot.key, slot.index));
+            }
+            keys += 1;
+        }
+        if keys != self.len() {
+            return Err(format!("{} keys but {} values", keys, self.len()));
+        }
+        Ok(())
+    }
+
+    fn store(&mut self, value: V) -> usize {
+        match self.free.pop() {
+            Some(index) => {
+                self.values[index] = Some(value);
+                index
+            }
+            None => {
+                self.values.push(Some(value));
+                self.values.len() - 1
+            }
+        }
+    }
+
+    fn release(&mut self, index: usize) -> V {
+        self.free.push(index);
+        self.values[index].take().unwrap()
+    }
+}
+
+pub enum Entry<'a, K, V, T: MapTree<K>> {
+    Occupied(OccupiedEntry<'a, K, V, T>),
+    Vacant(VacantEntry<'a, K, V, T>),
+}
+
+impl<'a, K: Ord + Debug, V, T: MapTree<K>> Entry<'a, K, V, T> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// Run `f` on the value if there is one, leaving a vacant entry as it is
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+pub struct OccupiedEntry<'a, K, V, T: MapTree<K>> {
+    map: &'a mut Map<K, V, T>,
+    /// Where the key's node was found
+    place: T::Place,
+    key: K,
+    index: usize,
+}
+
+impl<'a, K: Ord + Debug, V, T: MapTree<K>> OccupiedEntry<'a, K, V, T> {
+    /// The key passed to `entry`
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn get(&self) -> &V {
+        self.map.values[self.index].as_ref().unwrap()
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        self.map.values[self.index].as_mut().unwrap()
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        self.map.values[self.index].as_mut().unwrap()
+    }
+
+    /// Replace the value, returning the old one
+    pub fn insert(&mut self, value: V) -> V {
+        std::mem::replace(self.get_mut(), value)
+    }
+
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+
+    /// Unlink the key's node where it was found, returning the stored key
+    /// and its value
+    pub fn remove_entry(self) -> (K, V) {
+        let slot = self.map.tree.unlink(self.place);
+        (slot.key, self.map.release(slot.index))
+    }
+}
+
+pub struct VacantEntry<'a, K, V, T: MapTree<K>> {
+    map: &'a mut Map<K, V, T>,
+    /// Where the new key hangs from
+    place: T::Place,
+    as_left: bool,
+    key: K,
+}
+
+impl<'a, K: Ord + Debug, V, T: MapTree<K>> VacantEntry<'a, K, V, T> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Link the key in where the search for it ended, rebalance once, and
+    /// return its new value
+    pub fn insert(self, value: V) -> &'a mut V {
+        let index = self.map.store(value);
+        self.map.tree.link(self.place, Slot { key: self.key, index }, self.as_left);
+        self.map.values[index].as_mut().unwrap()
+    }
+}
+
+/// The keys and values in key order
+pub struct Iter<'a, K, V, T: MapTree<K> + 'a> {
+    slots: T::Slots<'a>,
+    values: &'a [Option<V>],
+}
+
+impl<'a, K, V, T: MapTree<K> + 'a> Iterator for Iter<'a, K, V, T> {
+    type Item = (K, &'a V);
+
+    fn next(&mut self) -> Option<(K, &'a V)> {
+        let slot = self.slots.next()?;
+        Some((slot.key, self.values[slot.index].as_ref().unwrap()))
+    }
+}
+
+impl<K: Ord + Debug, V, T: MapTree<K>> Default for Map<K, V, T> {
+    fn default() -> Self {
+        Map::new()
+    }
+}
+
+/// A copy that shares no nodes with the original
+impl<K, V: Clone, T: Clone> Clone for Map<K, V, T> {
+    fn clone(&self) -> Self {
+        Map { tree: self.tree.clone(), values: self.values.clone(), free: self.free.clone(), keys: PhantomData }
+    }
+}
+
+/// Maps are equal when they hold the same keys and values, whatever their
+/// shape
+impl<K: Ord + Debug, V: PartialEq, T: MapTree<K>> PartialEq for Map<K, V, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<K: Ord + Debug, V: Eq, T: MapTree<K>> Eq for Map<K, V, T> {}
+
+impl<K: Ord + Debug, V: PartialOrd, T: MapTree<K>> PartialOrd for Map<K, V, T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other.iter())
+    }
+}
+
+/// Compares the entries in key order, like `BTreeMap`
+impl<K: Ord + Debug, V: Ord, T: MapTree<K>> Ord for Map<K, V, T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+impl<K: Ord + Debug + Hash, V: Hash, T: MapTree<K>> Hash for Map<K, V, T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len().hash(state);
+        for entry in self.iter() {
+            entry.hash(state);
+        }
+    }
+}
+
+impl<K: Ord + Debug, V, T: MapTree<K>> Extend<(K, V)> for Map<K, V, T> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<K: Ord + Debug, V, T: MapTree<K>> FromIterator<(K, V)> for Map<K, V, T> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = Map::new();
+        map.extend(iter);
+        map
+    }
+}
+
+/// Panics if `key` is missing
+impl<K: Ord + Debug, V, T: MapTree<K>, Q: ?Sized + Ord> Index<&Q> for Map<K, V, T>
+where
+    K: std::borrow::Borrow<Q>,
+{
+    type Output = V;
+
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("no entry found for key")
+    }
+}
+
+impl<'a, K: Ord + Debug, V, T: MapTree<K>> IntoIterator for &'a Map<K, V, T> {
+    type Item = (K, &'a V);
+    type IntoIter = Iter<'a, K, V, T>;
+
+    fn into_iter(self) -> Iter<'a, K, V, T> {
+        self.iter()
+    }
+}
+
+impl<K: Ord + Debug, V: Debug, T: MapTree<K>> Debug for Map<K, V, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
@@ -0,0 +1,50 @@
+//! Running operation counters kept by both trees, read with their `stats`
+//! method.
+//!
+//! Every counter except `searches` and `comparisons` counts only operations
+//! that changed the tree: inserting a key already present, or deleting one
+//! that is absent, is not an insert or a delete.
+
+/// Snapshot of the work a tree has done since it was created or since its
+/// `reset_stats`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TreeStats {
+    /// Keys added
+    pub inserts: u64,
+    /// Keys removed
+    pub deletes: u64,
+    pub searches: u64,
+    /// Key comparisons made while descending the tree
+    pub comparisons: u64,
+    /// Rebalancing steps that rotated once: AVL's LL and RR cases, and the
+    /// red-black insert line case and delete red-sibling and far-child cases
+    pub single_rotations: u64,
+    /// Rebalancing steps that rotated twice: AVL's LR and RL cases, and the
+    /// red-black insert triangle case and delete near-child case
+    pub double_rotations: u64,
+    /// Nodes whose color actually changed; only red-black trees count these
+    pub recolors: u64,
+    /// Nodes allocated
+    pub allocations: u64,
+    /// Deepest level any operation reached, counting the root as depth 1
+    pub max_depth: usize,
+}
+
+impl TreeStats {
+    /// Total calls to `rotate_left` and `rotate_right`
+    pub fn rotations(&self) -> u64 {
+        self.single_rotations + 2 * self.double_rotations
+    }
+
+    pub fn rotations_per_insert(&self) -> f64 {
+        if self.inserts == 0 {
+            0.0
+        } else {
+            self.rotations() as f64 / self.inserts as f64
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = TreeStats::default();
+    }
+}
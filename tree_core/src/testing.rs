@@ -0,0 +1,208 @@
+//! Test suites both tree crates run against their own types, written once
+//! here. Not part of the public API.
+
+/// Cursor tests for a tree type with `new`, `augmented`, `insert`, `cursor`,
+/// `cursor_mut`, `validate` and `aggregate`. Its cursors must offer `key`,
+/// `seek`, `move_next`, `move_prev`, `peek_next` and `peek_prev`, and the
+/// mutable one `remove_current`, `insert_before` and `insert_after` too. The
+/// calling crate needs `rand` as a dev-dependency.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! cursor_tests {
+    ($tree:ident) => {
+        #[test]
+        fn test_walk_both_ways_through_the_ghost() {
+            let mut tree = $tree::new();
+            for key in [40, 20, 60, 10, 30, 50, 70] {
+                tree.insert(key);
+            }
+            let mut cursor = tree.cursor();
+            let mut forward = Vec::new();
+            while let Some(key) = cursor.key() {
+                forward.push(key);
+                cursor.move_next();
+            }
+            assert_eq!(forward, vec![10, 20, 30, 40, 50, 60, 70]);
+            assert_eq!(cursor.peek_next(), Some(10));
+            cursor.move_prev();
+            assert_eq!(cursor.key(), Some(70));
+
+            assert!(!cursor.seek(&35));
+            assert_eq!((cursor.peek_prev(), cursor.key(), cursor.peek_next()), (Some(30), Some(40), Some(50)));
+            assert!(cursor.seek(&10));
+            assert_eq!(cursor.peek_prev(), None);
+            assert!(!cursor.seek(&71));
+            assert_eq!(cursor.key(), None);
+            assert!($tree::<i32>::new().cursor().key().is_none());
+        }
+
+        #[test]
+        fn test_edits_at_the_cursor_match_btreeset() {
+            use rand::{Rng, SeedableRng};
+
+            let mut tree: $tree<u32, $crate::augment::Count> = $tree::augmented();
+            let mut expected = std::collections::BTreeSet::new();
+            let mut rng = rand::rngs::StdRng::seed_from_u64(421);
+            for step in 0..2000 {
+                let key: u32 = rng.gen_range(0..300);
+                let mut cursor = tree.cursor_mut();
+                cursor.seek(&key);
+                match step % 4 {
+                    0 | 1 => {
+                        let removed = cursor.remove_current();
+                        let lower_bound = expected.range(key..).next().copied();
+                        assert_eq!(removed, lower_bound);
+                        if let Some(removed) = removed {
+                            expected.remove(&removed);
+                        }
+                        assert_eq!(cursor.key(), lower_bound.and_then(|removed| expected.range(removed..).next().copied()));
+                    }
+                    2 => {
+                        let at = cursor.key();
+                        assert_eq!(cursor.insert_before(key), expected.insert(key));
+                        assert_eq!(cursor.key(), at);
+                    }
+                    _ => {
+                        let at = cursor.key();
+                        let candidate = at.map_or(key, |at| at + 1);
+                        // From the ghost position, "after" is before the first key
+                        let next = match at {
+                            Some(at) => expected.range(at + 1..).next().copied(),
+                            None => expected.first().copied(),
+                        };
+                        let fits = at.is_none_or(|at| at < candidate) && next.is_none_or(|next| candidate < next);
+                        assert_eq!(cursor.insert_after(candidate), fits);
+                        if fits {
+                            expected.insert(candidate);
+                        }
+                        assert_eq!(cursor.key(), at);
+                    }
+                }
+                // The cursor can still step both ways from wherever the edit left it
+                let (prev, next) = match cursor.key() {
+                    Some(at) => (expected.range(..at).next_back().copied(), expected.range(at + 1..).next().copied()),
+                    None => (expected.last().copied(), expected.first().copied()),
+                };
+                assert_eq!((cursor.peek_prev(), cursor.peek_next()), (prev, next));
+                tree.validate().unwrap();
+                assert_eq!(tree.aggregate(..), expected.len());
+            }
+        }
+    };
+}
+
+/// Map tests for a `map::Map` alias, such as `AvlMap`. The calling crate
+/// needs `rand` as a dev-dependency.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! map_tests {
+    ($map:ident) => {
+        #[test]
+        fn test_entries_match_btreemap() {
+            use rand::{Rng, SeedableRng};
+            use $crate::map::Entry;
+
+            let mut map = $map::new();
+            let mut expected = std::collections::BTreeMap::new();
+            let mut rng = rand::rngs::StdRng::seed_from_u64(421);
+            for step in 0..3000 {
+                let key: u64 = rng.gen_range(0..200);
+                match step % 5 {
+                    0 | 1 => *map.entry(key).or_insert(0) += 1,
+                    2 => {
+                        map.entry(key).and_modify(|count| *count *= 2).or_insert_with(|| 7);
+                    }
+                    3 => assert_eq!(map.insert(key, step), expected.insert(key, step)),
+                    _ => {
+                        let removed = match map.entry(key) {
+                            Entry::Occupied(entry) => Some(entry.remove_entry()),
+                            Entry::Vacant(_) => None,
+                        };
+                        assert_eq!(removed, expected.remove_entry(&key));
+                    }
+                }
+                match step % 5 {
+                    0 | 1 => *expected.entry(key).or_insert(0) += 1,
+                    2 => {
+                        expected.entry(key).and_modify(|count| *count *= 2).or_insert_with(|| 7);
+                    }
+                    _ => {}
+                }
+                map.validate().unwrap();
+                assert_eq!(map.len(), expected.len());
+                assert_eq!(map.get(&key), expected.get(&key));
+            }
+            assert_eq!(map.iter().map(|(key, value)| (key, *value)).collect::<Vec<_>>(), expected.into_iter().collect::<Vec<_>>());
+        }
+
+        #[test]
+        fn test_vacant_insert_descends_once() {
+            let mut map = $map::new();
+            for key in 0..100 {
+                map.insert(key, ());
+            }
+            map.reset_stats();
+            map.entry(1000).or_insert(());
+            let stats = map.stats();
+            assert_eq!((stats.searches, stats.inserts, stats.allocations), (0, 1, 1));
+            assert_eq!(stats.comparisons as usize, stats.max_depth);
+            map.validate().unwrap();
+        }
+
+        #[test]
+        fn test_borrowed_lookups_and_removal() {
+            let mut map = $map::new();
+            for (index, word) in ["pear", "apple", "fig", "kiwi"].into_iter().enumerate() {
+                map.insert(word.to_string(), index);
+            }
+            assert_eq!(map.get("fig"), Some(&2));
+            *map.get_mut("kiwi").unwrap() += 10;
+            assert_eq!(map.remove("kiwi"), Some(13));
+            assert!(!map.contains_key("kiwi"));
+            assert_eq!(map.remove("kiwi"), None);
+            // The freed slot is reused
+            map.insert("plum".to_string(), 4);
+            assert_eq!(map.capacity(), 4);
+            assert_eq!(format!("{:?}", map), r#"{"apple": 1, "fig": 2, "pear": 0, "plum": 4}"#);
+        }
+
+        #[test]
+        fn test_clone_compare_and_index() {
+            let map: $map<&str, i32> = [("b", 2), ("a", 1), ("c", 3)].into_iter().collect();
+            let mut copy = map.clone();
+            assert_eq!(copy, map);
+            assert_eq!(map["b"], 2);
+            copy.insert("b", 20);
+            copy.validate().unwrap();
+            assert_eq!((map["b"], copy["b"]), (2, 20));
+            assert!(map < copy);
+
+            let mut reordered = $map::new();
+            reordered.extend([("c", 3), ("a", 1), ("b", 2)]);
+            assert_eq!(reordered, map);
+            reordered.remove("c");
+            assert_ne!(reordered, map);
+        }
+
+        #[test]
+        fn test_aggregate_values_over_a_key_range() {
+            use $crate::augment::{Count, Max, Sum};
+
+            // Trade volume by day
+            let map: $map<u32, u64> = (1..=30).map(|day| (day, day as u64 * 10)).collect();
+            assert_eq!(map.aggregate::<Sum, _>(10..=12), 330);
+            assert_eq!(map.aggregate::<Sum, _>((std::ops::Bound::Excluded(28), std::ops::Bound::Unbounded)), 590);
+            assert_eq!(map.aggregate::<Sum, _>(..), (1..=30).sum::<u64>() * 10);
+            assert_eq!(map.aggregate::<Count, _>(5..5), 0);
+            assert_eq!(map.aggregate::<Max, _>(..=7), Some(70));
+            assert_eq!(map.aggregate::<Max, _>(31..), None);
+        }
+
+        #[test]
+        #[should_panic(expected = "no entry found for key")]
+        fn test_index_missing_key_panics() {
+            let map: $map<i32, i32> = $map::new();
+            let _ = map[&1];
+        }
+    };
+}
@@ -0,0 +1,52 @@
+//! The parts of a trace both trees share: which way a rotation turned, and
+//! the recorder each tree collects its own `TraceEvent`s with.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    Left,
+    Right,
+}
+
+/// Collects events while an operation runs. A disabled recorder drops them
+/// without building them.
+#[derive(Debug)]
+pub struct Recorder<E> {
+    events: Option<Vec<E>>,
+    snapshots: bool,
+}
+
+impl<E> Recorder<E> {
+    pub fn disabled() -> Self {
+        Recorder { events: None, snapshots: false }
+    }
+
+    pub fn enabled() -> Self {
+        Recorder { events: Some(Vec::new()), snapshots: false }
+    }
+
+    /// Like `enabled`, but also keeps snapshots
+    pub fn with_snapshots() -> Self {
+        Recorder { events: Some(Vec::new()), snapshots: true }
+    }
+
+    pub fn takes_snapshots(&self) -> bool {
+        self.snapshots
+    }
+
+    pub fn record(&mut self, event: impl FnOnce() -> E) {
+        if let Some(events) = &mut self.events {
+            events.push(event());
+        }
+    }
+
+    /// Record `event`, a snapshot of the tree, only if snapshots are kept
+    pub fn snapshot(&mut self, event: impl FnOnce() -> E) {
+        if self.snapshots {
+            self.record(event);
+        }
+    }
+
+    pub fn into_events(self) -> Vec<E> {
+        self.events.unwrap_or_default()
+    }
+}